@@ -0,0 +1,169 @@
+//! Synthesized audio passed to plugins
+
+use crate::PluginStr;
+
+/// Synthesized audio handed to `on_audio`
+///
+/// Everything is borrowed from the host and valid only during the call:
+/// a plugin that needs the data later (e.g. to upload it in the background)
+/// must copy it before returning.
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct PluginAudio {
+    /// Encoded audio bytes exactly as returned by the TTS provider
+    pub data: *const u8,
+    pub len: usize,
+    /// Format name: "mp3", "opus", "aac", "flac", "wav", "ogg" or "pcm" (signed 16-bit LE)
+    pub format: PluginStr,
+    /// Sample rate for "pcm" (0 for formats that carry it in their header)
+    pub sample_rate: u32,
+    /// Channel count for "pcm" (0 for formats that carry it in their header)
+    pub channels: u16,
+    /// Duration in milliseconds (0 if unknown)
+    pub duration_ms: u64,
+    /// Id of the history message being spoken (empty if spoken outside the queue)
+    pub message_id: PluginStr,
+}
+
+impl PluginAudio {
+    /// View the audio bytes
+    ///
+    /// # Safety
+    ///
+    /// Must only be called during the `on_audio` call that received this value.
+    pub unsafe fn bytes<'a>(&self) -> &'a [u8] {
+        if self.data.is_null() || self.len == 0 {
+            return &[];
+        }
+        std::slice::from_raw_parts(self.data, self.len)
+    }
+}
+
+/// Format codes of [`PluginAudioBuffer::format`]
+pub mod audio_format {
+    pub const MP3: u32 = 1;
+    /// Opus in an Ogg container
+    pub const OPUS: u32 = 2;
+    /// AAC in an ADTS stream
+    pub const AAC: u32 = 3;
+    pub const FLAC: u32 = 4;
+    pub const WAV: u32 = 5;
+    /// Ogg Vorbis or Ogg Opus
+    pub const OGG: u32 = 6;
+    /// Raw signed 16-bit little-endian PCM (`sample_rate` and `channels` required)
+    pub const PCM: u32 = 7;
+}
+
+/// Audio synthesized by a plugin, owned by the plugin
+///
+/// The host copies the bytes and hands the buffer back through
+/// `PluginVTable::free_audio`, like [`crate::PluginString`].
+#[repr(C)]
+#[derive(Debug)]
+pub struct PluginAudioBuffer {
+    pub ptr: *mut u8,
+    pub len: usize,
+    pub capacity: usize,
+    /// One of the [`audio_format`] codes
+    pub format: u32,
+    /// Sample rate for PCM (0 otherwise)
+    pub sample_rate: u32,
+    /// Channel count for PCM (0 otherwise)
+    pub channels: u16,
+}
+
+impl PluginAudioBuffer {
+    /// Empty value (no allocation, nothing to free)
+    pub const fn empty() -> Self {
+        Self {
+            ptr: std::ptr::null_mut(),
+            len: 0,
+            capacity: 0,
+            format: 0,
+            sample_rate: 0,
+            channels: 0,
+        }
+    }
+
+    /// Hand encoded audio over to the host (plugin side)
+    pub fn from_vec(data: Vec<u8>, format: u32) -> Self {
+        let mut data = std::mem::ManuallyDrop::new(data);
+        Self {
+            ptr: data.as_mut_ptr(),
+            len: data.len(),
+            capacity: data.capacity(),
+            format,
+            sample_rate: 0,
+            channels: 0,
+        }
+    }
+
+    /// Hand raw 16-bit PCM over to the host (plugin side)
+    pub fn from_pcm(data: Vec<u8>, sample_rate: u32, channels: u16) -> Self {
+        Self {
+            sample_rate,
+            channels,
+            ..Self::from_vec(data, audio_format::PCM)
+        }
+    }
+
+    /// Copy the bytes out without taking ownership
+    ///
+    /// # Safety
+    ///
+    /// The buffer must still be alive (not yet passed to `free_audio`).
+    pub unsafe fn to_vec(&self) -> Vec<u8> {
+        if self.ptr.is_null() || self.len == 0 {
+            return Vec::new();
+        }
+        std::slice::from_raw_parts(self.ptr, self.len).to_vec()
+    }
+
+    /// Reclaim a buffer created by [`PluginAudioBuffer::from_vec`] in this same module
+    ///
+    /// # Safety
+    ///
+    /// Must only be called by the side that allocated the buffer, exactly once.
+    pub unsafe fn into_vec(self) -> Vec<u8> {
+        if self.ptr.is_null() {
+            return Vec::new();
+        }
+        Vec::from_raw_parts(self.ptr, self.len, self.capacity)
+    }
+}
+
+/// `free_audio` implementation for plugins written in Rust
+pub extern "C" fn free_plugin_audio(audio: PluginAudioBuffer) {
+    // SAFETY: the buffer was produced by `PluginAudioBuffer::from_vec` inside this plugin
+    drop(unsafe { audio.into_vec() });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_plugin_audio_bytes() {
+        let data = [1u8, 2, 3];
+        let audio = PluginAudio {
+            data: data.as_ptr(),
+            len: data.len(),
+            format: PluginStr::new("mp3"),
+            sample_rate: 0,
+            channels: 0,
+            duration_ms: 0,
+            message_id: PluginStr::new(""),
+        };
+        assert_eq!(unsafe { audio.bytes() }, &[1, 2, 3]);
+        assert_eq!(unsafe { audio.format.as_str() }, "mp3");
+    }
+
+    #[test]
+    fn test_audio_buffer_roundtrip() {
+        let buffer = PluginAudioBuffer::from_pcm(vec![0, 1, 2, 3], 24000, 1);
+        assert_eq!(buffer.format, audio_format::PCM);
+        assert_eq!(unsafe { buffer.to_vec() }, vec![0, 1, 2, 3]);
+        free_plugin_audio(buffer);
+        free_plugin_audio(PluginAudioBuffer::empty());
+    }
+}
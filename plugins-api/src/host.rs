@@ -0,0 +1,124 @@
+//! Services the host offers to plugins
+//!
+//! A plugin that implements `init_with_host` receives a pointer to [`HostServices`]
+//! and may keep it (and copies of it) until its `destroy` returns. All functions
+//! are thread-safe, so a plugin can call them from its own threads (e.g. a chat
+//! reader pushing incoming messages with `enqueue_text`).
+//!
+//! Strings returned by the host are owned by the host and must be handed back
+//! through [`HostServices::free_string`] (the safe wrappers below do this).
+
+use crate::{PluginStr, PluginString};
+use std::ffi::c_void;
+
+/// Log levels for [`HostServices::log`]
+pub mod log_level {
+    pub const ERROR: u32 = 0;
+    pub const WARN: u32 = 1;
+    pub const INFO: u32 = 2;
+    pub const DEBUG: u32 = 3;
+}
+
+/// `kv_get` result: the key exists and its value was written to `out`
+pub const KV_FOUND: i32 = 0;
+
+/// `kv_get` result: the key does not exist
+pub const KV_NOT_FOUND: i32 = 1;
+
+/// Function table passed by the host to `init_with_host`
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct HostServices {
+    /// Size of this struct as compiled into the host
+    pub struct_size: usize,
+
+    /// Opaque host context, passed back as the first argument of every function
+    pub context: *mut c_void,
+
+    /// Write a message to the host log
+    pub log: extern "C" fn(context: *mut c_void, level: u32, message: PluginStr),
+
+    /// Add text to the TTS queue as if the user typed it
+    /// Returns 0 if the text was accepted
+    pub enqueue_text: extern "C" fn(context: *mut c_void, text: PluginStr) -> i32,
+
+    /// Directory reserved for this plugin's files (created on first call)
+    pub get_data_dir: extern "C" fn(context: *mut c_void) -> PluginString,
+
+    /// Show a notification in the app
+    pub emit_notification: extern "C" fn(context: *mut c_void, title: PluginStr, body: PluginStr),
+
+    /// Read a value from the plugin's persistent key-value store
+    /// Returns [`KV_FOUND`] with the value written to `out`, or [`KV_NOT_FOUND`]
+    pub kv_get: extern "C" fn(context: *mut c_void, key: PluginStr, out: *mut PluginString) -> i32,
+
+    /// Store a value (persisted across restarts)
+    /// Returns 0 on success
+    pub kv_set: extern "C" fn(context: *mut c_void, key: PluginStr, value: PluginStr) -> i32,
+
+    /// Remove a value
+    /// Returns 0 on success (also when the key did not exist)
+    pub kv_delete: extern "C" fn(context: *mut c_void, key: PluginStr) -> i32,
+
+    /// Release a string returned by the host
+    pub free_string: extern "C" fn(context: *mut c_void, s: PluginString),
+}
+
+// SAFETY: the host guarantees that all functions may be called from any thread
+// and that `context` stays valid until the plugin's `destroy` returns
+unsafe impl Send for HostServices {}
+unsafe impl Sync for HostServices {}
+
+impl HostServices {
+    /// Copy a host string and give the buffer back to the host
+    fn take_string(&self, s: PluginString) -> String {
+        // SAFETY: `s` was just returned by the host and is freed exactly once
+        let text = unsafe { s.to_string_lossy() };
+        if !s.ptr.is_null() {
+            (self.free_string)(self.context, s);
+        }
+        text
+    }
+
+    /// Write a message to the host log
+    pub fn log(&self, level: u32, message: &str) {
+        (self.log)(self.context, level, PluginStr::new(message));
+    }
+
+    /// Add text to the TTS queue
+    pub fn enqueue_text(&self, text: &str) -> bool {
+        (self.enqueue_text)(self.context, PluginStr::new(text)) == 0
+    }
+
+    /// Directory reserved for this plugin's files
+    pub fn data_dir(&self) -> String {
+        self.take_string((self.get_data_dir)(self.context))
+    }
+
+    /// Show a notification in the app
+    pub fn notify(&self, title: &str, body: &str) {
+        (self.emit_notification)(self.context, PluginStr::new(title), PluginStr::new(body));
+    }
+
+    /// Read a value from the key-value store
+    pub fn kv_get(&self, key: &str) -> Option<String> {
+        let mut out = PluginString::empty();
+        let result = (self.kv_get)(self.context, PluginStr::new(key), &mut out);
+        let value = self.take_string(out);
+        if result == KV_FOUND {
+            Some(value)
+        } else {
+            None
+        }
+    }
+
+    /// Store a value in the key-value store
+    pub fn kv_set(&self, key: &str, value: &str) -> bool {
+        (self.kv_set)(self.context, PluginStr::new(key), PluginStr::new(value)) == 0
+    }
+
+    /// Remove a value from the key-value store
+    pub fn kv_delete(&self, key: &str) -> bool {
+        (self.kv_delete)(self.context, PluginStr::new(key)) == 0
+    }
+}
@@ -1,8 +1,18 @@
 //! Plugin API for app-tts dynamic plugins
 //!
-//! This crate defines the C ABI interface for loading plugins as dynamic libraries (.dll)
+//! This crate defines the C ABI interface for loading plugins as dynamic libraries (.dll).
+//! Plugins written in Rust can use the safe [`sdk`] instead of implementing it by hand.
 
-use std::ffi::{c_char, c_void};
+use std::ffi::c_void;
+
+mod audio;
+mod host;
+pub mod sdk;
+mod strings;
+
+pub use audio::{audio_format, free_plugin_audio, PluginAudio, PluginAudioBuffer};
+pub use host::{log_level, HostServices, KV_FOUND, KV_NOT_FOUND};
+pub use strings::{free_plugin_string, PluginStr, PluginString};
 
 /// Status of plugin connection/operation
 #[repr(C)]
@@ -32,76 +42,292 @@ impl PluginStatus {
     }
 }
 
+/// Major version of the plugin ABI. Plugins built for another major version are rejected.
+pub const PLUGIN_API_VERSION_MAJOR: u16 = 3;
+
+/// Minor version of the plugin ABI. Minor bumps only append optional vtable entries,
+/// so the host accepts plugins with the same major and an equal or lower minor.
+pub const PLUGIN_API_VERSION_MINOR: u16 = 6;
+
+/// Packed ABI version of this crate (see [`pack_api_version`])
+pub const PLUGIN_API_VERSION: u32 = pack_api_version(PLUGIN_API_VERSION_MAJOR, PLUGIN_API_VERSION_MINOR);
+
+/// Name of the exported version function (`extern "C" fn() -> u32`)
+pub const PLUGIN_API_VERSION_SYMBOL: &[u8] = b"plugin_api_version\0";
+
+/// Name of the exported vtable function (`extern "C" fn() -> *const PluginVTable`)
+pub const PLUGIN_VTABLE_SYMBOL: &[u8] = b"get_plugin_vtable\0";
+
+/// Signature of the exported `plugin_api_version` function
+pub type PluginApiVersionFn = extern "C" fn() -> u32;
+
+/// Signature of the exported `get_plugin_vtable` function
+pub type GetPluginVTableFn = extern "C" fn() -> *const PluginVTable;
+
+/// Pack major/minor into a single `u32` (`major << 16 | minor`)
+pub const fn pack_api_version(major: u16, minor: u16) -> u32 {
+    ((major as u32) << 16) | minor as u32
+}
+
+/// Split a packed version into `(major, minor)`
+pub const fn unpack_api_version(version: u32) -> (u16, u16) {
+    ((version >> 16) as u16, (version & 0xFFFF) as u16)
+}
+
+/// Check whether a plugin built for `plugin_version` can be loaded by this host
+pub fn check_api_version(plugin_version: u32) -> Result<(), String> {
+    let (major, minor) = unpack_api_version(plugin_version);
+    if major != PLUGIN_API_VERSION_MAJOR {
+        return Err(format!(
+            "Incompatible plugin API version {}.{} (host supports {}.x)",
+            major, minor, PLUGIN_API_VERSION_MAJOR
+        ));
+    }
+    if minor > PLUGIN_API_VERSION_MINOR {
+        return Err(format!(
+            "Plugin requires API {}.{}, host supports up to {}.{}",
+            major, minor, PLUGIN_API_VERSION_MAJOR, PLUGIN_API_VERSION_MINOR
+        ));
+    }
+    Ok(())
+}
+
+/// Capability flags a plugin declares in [`PluginVTable::capabilities`]
+pub mod capabilities {
+    /// Plugin has configuration (`get_config_schema`, `set_config`)
+    pub const CONFIG: u64 = 1 << 0;
+    /// Plugin reports its status (`check_status`)
+    pub const STATUS: u64 = 1 << 1;
+    /// Plugin receives spoken text (`on_text`)
+    pub const TEXT: u64 = 1 << 2;
+    /// Plugin rewrites text before it is spoken (`transform_text`)
+    pub const TRANSFORM: u64 = 1 << 3;
+    /// Plugin follows playback and app state (`on_tts_*`, `on_provider_changed`, `on_blocking_changed`)
+    pub const EVENTS: u64 = 1 << 4;
+    /// Plugin receives synthesized audio (`on_audio`)
+    pub const AUDIO: u64 = 1 << 5;
+    /// Plugin is a TTS provider (`get_voices`, `synthesize`, `free_audio`)
+    pub const SYNTH: u64 = 1 << 6;
+
+    /// All capability flags with their names
+    pub const ALL: &[(u64, &str)] = &[
+        (CONFIG, "config"),
+        (STATUS, "status"),
+        (TEXT, "text"),
+        (TRANSFORM, "transform"),
+        (EVENTS, "events"),
+        (AUDIO, "audio"),
+        (SYNTH, "synth"),
+    ];
+
+    /// Names of the flags set in `capabilities`
+    pub fn names(capabilities: u64) -> Vec<&'static str> {
+        ALL.iter()
+            .filter(|(flag, _)| capabilities & flag != 0)
+            .map(|(_, name)| *name)
+            .collect()
+    }
+}
+
 /// Function table exported by plugin DLL
 ///
-/// All functions use C ABI (extern "C") and C-compatible types
+/// All functions use C ABI (extern "C") and C-compatible types.
+/// The table starts with its own size so that the host can read tables
+/// from plugins built against an older minor version: entries past
+/// `struct_size` are treated as absent. Optional entries may be `None`,
+/// in which case the host uses a default behaviour.
+///
+/// Strings follow the rules in [`PluginStr`] / [`PluginString`]: arguments are
+/// borrowed for the duration of the call, returned strings are owned by the
+/// plugin and given back to it through `free_string`.
 #[repr(C)]
 #[derive(Clone, Copy)]
 pub struct PluginVTable {
+    /// Size of this struct as compiled into the plugin (`size_of::<PluginVTable>()`)
+    pub struct_size: usize,
+
+    /// Bitmask of [`capabilities`] flags
+    pub capabilities: u64,
+
     /// Plugin display name
-    /// Returns null-terminated C string
-    pub name: extern "C" fn() -> *const c_char,
+    pub name: extern "C" fn() -> PluginString,
 
-    /// Plugin version string
-    /// Returns null-terminated C string (e.g. "1.0.0")
-    pub version: extern "C" fn() -> *const c_char,
+    /// Plugin version string (e.g. "1.0.0")
+    pub version: extern "C" fn() -> PluginString,
 
-    /// Get JSON schema of configuration
-    /// Returns null-terminated C string with JSON Schema
-    pub get_config_schema: extern "C" fn() -> *const c_char,
+    /// Initialize plugin
+    /// Returns opaque pointer to plugin data
+    pub init: extern "C" fn() -> *mut c_void,
 
-    /// Set plugin configuration
-    /// Returns 0 on success, non-zero on error
-    pub set_config: extern "C" fn(
-        plugin_data: *mut c_void,
-        config: *const c_char,
-        len: usize,
-    ) -> i32,
+    /// Cleanup and free plugin data
+    pub destroy: extern "C" fn(*mut c_void),
 
-    /// Check current plugin status
-    pub check_status: extern "C" fn(plugin_data: *mut c_void) -> PluginStatus,
+    /// Release a string returned by any function of this table
+    /// (plugins written in Rust can use [`free_plugin_string`])
+    pub free_string: extern "C" fn(PluginString),
+
+    /// Get JSON schema of configuration (default: empty schema)
+    pub get_config_schema: Option<extern "C" fn() -> PluginString>,
+
+    /// Set plugin configuration (JSON)
+    /// Returns 0 on success, non-zero on error (default: accepted)
+    pub set_config: Option<extern "C" fn(plugin_data: *mut c_void, config: PluginStr) -> i32>,
+
+    /// Check current plugin status (default: `Ok`)
+    pub check_status: Option<extern "C" fn(plugin_data: *mut c_void) -> PluginStatus>,
 
     /// Handle text (e.g., send to chat)
+    /// Returns 0 on success, non-zero on error (default: ignored)
+    pub on_text: Option<extern "C" fn(plugin_data: *mut c_void, text: PluginStr) -> i32>,
+
+    /// Human-readable description of the last error (e.g. "Permission denied: C:\logs\x.txt")
+    /// Empty if there is no error
+    pub get_last_error: Option<extern "C" fn(plugin_data: *mut c_void) -> PluginString>,
+
+    /// Free-form status detail shown next to the status (e.g. "Logging to C:\logs\x.txt")
+    pub get_status_detail: Option<extern "C" fn(plugin_data: *mut c_void) -> PluginString>,
+
+    // === API 3.1 ===
+
+    /// Rewrite text before it is queued and spoken (translation, masking, normalization...)
+    /// Returns [`TRANSFORM_REPLACED`] with the new text written to `out`,
+    /// [`TRANSFORM_UNCHANGED`] to keep the text as is, or a negative code on error
+    pub transform_text: Option<extern "C" fn(
+        plugin_data: *mut c_void,
+        text: PluginStr,
+        out: *mut PluginString,
+    ) -> i32>,
+
+    // === API 3.2 ===
+
+    /// Message started playing
+    pub on_tts_started: Option<extern "C" fn(plugin_data: *mut c_void, id: PluginStr, text: PluginStr)>,
+
+    /// Message finished playing
+    pub on_tts_completed: Option<extern "C" fn(plugin_data: *mut c_void, id: PluginStr, text: PluginStr)>,
+
+    /// Message could not be synthesized or played
+    pub on_tts_failed: Option<extern "C" fn(
+        plugin_data: *mut c_void,
+        id: PluginStr,
+        text: PluginStr,
+        error: PluginStr,
+    )>,
+
+    /// Playback was cancelled by the user
+    pub on_tts_cancelled: Option<extern "C" fn(plugin_data: *mut c_void, id: PluginStr, text: PluginStr)>,
+
+    /// TTS provider was switched (e.g. "openai")
+    pub on_provider_changed: Option<extern "C" fn(plugin_data: *mut c_void, provider: PluginStr)>,
+
+    /// Keyboard interception was turned on or off
+    pub on_blocking_changed: Option<extern "C" fn(plugin_data: *mut c_void, blocking: bool)>,
+
+    // === API 3.3 ===
+
+    /// Receive synthesized audio before it is played (see [`PluginAudio`] for lifetime rules)
     /// Returns 0 on success, non-zero on error
-    pub on_text: extern "C" fn(
+    pub on_audio: Option<extern "C" fn(plugin_data: *mut c_void, audio: *const PluginAudio) -> i32>,
+
+    // === API 3.4 ===
+
+    /// Voices of a TTS provider plugin as a JSON array: `[{"id": "anna", "name": "Anna"}]`
+    pub get_voices: Option<extern "C" fn(plugin_data: *mut c_void) -> PluginString>,
+
+    /// Synthesize `text` with `voice` (empty for the default voice)
+    /// Returns 0 with the audio written to `out`, non-zero on error
+    pub synthesize: Option<extern "C" fn(
         plugin_data: *mut c_void,
-        text: *const c_char,
-        len: usize,
-    ) -> i32,
+        text: PluginStr,
+        voice: PluginStr,
+        out: *mut PluginAudioBuffer,
+    ) -> i32>,
 
-    /// Initialize plugin
-    /// Returns opaque pointer to plugin data
-    pub init: extern "C" fn() -> *mut c_void,
+    /// Release audio returned by `synthesize`
+    /// (plugins written in Rust can use [`free_plugin_audio`])
+    pub free_audio: Option<extern "C" fn(PluginAudioBuffer)>,
 
-    /// Cleanup and free plugin data
-    pub destroy: extern "C" fn(*mut c_void),
+    // === API 3.5 ===
+
+    /// Initialize plugin with access to [`HostServices`]; used instead of `init` when present
+    /// The pointer stays valid until `destroy` returns
+    pub init_with_host: Option<extern "C" fn(host: *const HostServices) -> *mut c_void>,
+
+    // === API 3.6 ===
+
+    /// Handle text with details of the message as JSON (see [`MESSAGE_DETAILS_EXAMPLE`]);
+    /// called instead of `on_text` when present
+    /// Returns 0 on success, non-zero on error
+    pub on_message: Option<extern "C" fn(plugin_data: *mut c_void, text: PluginStr, details: PluginStr) -> i32>,
 }
 
-/// Helper to convert C string to Rust String
-pub unsafe fn c_str_to_string(ptr: *const c_char) -> String {
-    if ptr.is_null() {
-        return String::new();
+/// Details passed to `on_message`; fields that are unknown are `null`
+///
+/// `source` is "typed", "repeated" or "api", `provider` is the TTS provider id
+/// ("system", "openai", "plugin:<id>", ...).
+pub const MESSAGE_DETAILS_EXAMPLE: &str =
+    r#"{"id": "5f0c...", "source": "typed", "provider": "openai", "voice": "alloy"}"#;
+
+/// `transform_text` result: `out` holds the new text
+pub const TRANSFORM_REPLACED: i32 = 0;
+
+/// `transform_text` result: text is left unchanged, `out` is ignored
+pub const TRANSFORM_UNCHANGED: i32 = 1;
+
+impl PluginVTable {
+    /// Copy a string returned by the plugin and give the buffer back to it (host side)
+    ///
+    /// # Safety
+    ///
+    /// `s` must have been returned by a function of this vtable and not freed yet.
+    pub unsafe fn take_string(&self, s: PluginString) -> String {
+        if s.ptr.is_null() {
+            return String::new();
+        }
+        let text = s.to_string_lossy();
+        (self.free_string)(s);
+        text
     }
-    let len = std::ffi::CStr::from_ptr(ptr)
-        .to_bytes()
-        .len();
-    let slice = std::slice::from_raw_parts(ptr as *const u8, len);
-    String::from_utf8_lossy(slice).to_string()
 }
 
-/// Helper to convert Rust String to C string (leaked)
-pub fn string_to_c_str(s: &str) -> *const c_char {
-    std::ffi::CString::new(s)
-        .expect("Invalid string (contains null byte)")
-        .into_raw()
-}
+/// Size of the required part of the vtable; every plugin must provide at least this much
+pub const PLUGIN_VTABLE_CORE_SIZE: usize = std::mem::offset_of!(PluginVTable, get_config_schema);
+
+/// Read a vtable exported by a plugin built against any compatible minor version
+///
+/// Entries beyond the plugin's `struct_size` are filled with `None`. A size that
+/// doesn't end on an entry boundary is rejected rather than copying half a pointer.
+///
+/// # Safety
+///
+/// `ptr` must point to a valid vtable whose first `struct_size` bytes are readable
+/// and laid out as in this crate's `PluginVTable`.
+pub unsafe fn read_vtable(ptr: *const PluginVTable) -> Result<PluginVTable, String> {
+    if ptr.is_null() {
+        return Err("get_plugin_vtable returned null".to_string());
+    }
 
-/// Macro to create null-terminated static string for C
-#[macro_export]
-macro_rules! c_str {
-    ($s:expr) => {
-        concat!($s, "\0") as *const str as *const ::std::ffi::c_char
-    };
+    let struct_size = std::ptr::read(ptr as *const usize);
+    if struct_size < PLUGIN_VTABLE_CORE_SIZE {
+        return Err(format!(
+            "Plugin vtable is too small ({} bytes, at least {} required)",
+            struct_size, PLUGIN_VTABLE_CORE_SIZE
+        ));
+    }
+    if !(struct_size - PLUGIN_VTABLE_CORE_SIZE).is_multiple_of(std::mem::size_of::<usize>()) {
+        return Err(format!(
+            "Plugin vtable size {} does not end on an entry boundary (plugin built against a different layout)",
+            struct_size
+        ));
+    }
+
+    // Zeroed optional entries are `None`; required ones are always within `struct_size`
+    let mut vtable = std::mem::MaybeUninit::<PluginVTable>::zeroed();
+    let len = struct_size.min(std::mem::size_of::<PluginVTable>());
+    std::ptr::copy_nonoverlapping(ptr as *const u8, vtable.as_mut_ptr() as *mut u8, len);
+    let mut vtable = vtable.assume_init();
+    vtable.struct_size = len;
+    Ok(vtable)
 }
 
 #[cfg(test)]
@@ -119,8 +345,103 @@ mod tests {
 
     #[test]
     fn test_vtable_size() {
-        // Ensure VTable has expected size for C compatibility
-        // Changed from u32 to *const c_char, so size increased by 4 bytes (64 -> 68 on 64-bit)
-        assert_eq!(std::mem::size_of::<PluginVTable>(), 68);
+        // struct_size + capabilities + 5 required and 19 optional function pointers
+        let ptr = std::mem::size_of::<usize>();
+        assert_eq!(std::mem::size_of::<PluginVTable>(), ptr + 8 + 24 * ptr);
+        assert_eq!(PLUGIN_VTABLE_CORE_SIZE, ptr + 8 + 5 * ptr);
+    }
+
+    #[test]
+    fn test_api_version_compat() {
+        assert_eq!(unpack_api_version(PLUGIN_API_VERSION), (PLUGIN_API_VERSION_MAJOR, PLUGIN_API_VERSION_MINOR));
+        assert!(check_api_version(PLUGIN_API_VERSION).is_ok());
+        assert!(check_api_version(pack_api_version(PLUGIN_API_VERSION_MAJOR, PLUGIN_API_VERSION_MINOR + 1)).is_err());
+        assert!(check_api_version(pack_api_version(PLUGIN_API_VERSION_MAJOR + 1, 0)).is_err());
+        assert!(check_api_version(pack_api_version(1, 0)).is_err());
+    }
+
+    #[test]
+    fn test_capability_names() {
+        assert_eq!(capabilities::names(capabilities::CONFIG | capabilities::TEXT), vec!["config", "text"]);
+        assert!(capabilities::names(0).is_empty());
+    }
+
+    extern "C" fn test_name() -> PluginString {
+        PluginString::from("Test")
+    }
+
+    extern "C" fn test_init() -> *mut c_void {
+        std::ptr::null_mut()
+    }
+
+    extern "C" fn test_destroy(_: *mut c_void) {}
+
+    extern "C" fn test_detail(_: *mut c_void) -> PluginString {
+        PluginString::from("Detail")
+    }
+
+    #[test]
+    fn test_read_vtable_from_older_minor() {
+        let full = PluginVTable {
+            struct_size: std::mem::size_of::<PluginVTable>(),
+            capabilities: 0,
+            name: test_name,
+            version: test_name,
+            init: test_init,
+            destroy: test_destroy,
+            free_string: free_plugin_string,
+            get_config_schema: Some(test_name),
+            set_config: None,
+            check_status: None,
+            on_text: None,
+            get_last_error: None,
+            get_status_detail: Some(test_detail),
+            transform_text: None,
+            on_tts_started: None,
+            on_tts_completed: None,
+            on_tts_failed: None,
+            on_tts_cancelled: None,
+            on_provider_changed: None,
+            on_blocking_changed: None,
+            on_audio: None,
+            get_voices: None,
+            synthesize: None,
+            free_audio: None,
+            init_with_host: None,
+            on_message: None,
+        };
+
+        // Pretend the plugin only knows the core entries
+        let old = PluginVTable { struct_size: PLUGIN_VTABLE_CORE_SIZE, ..full };
+        let vtable = unsafe { read_vtable(&old) }.unwrap();
+        assert_eq!(vtable.struct_size, PLUGIN_VTABLE_CORE_SIZE);
+        assert!(vtable.get_config_schema.is_none());
+
+        // A table that ends right before `get_last_error`
+        let partial = PluginVTable {
+            struct_size: std::mem::offset_of!(PluginVTable, get_last_error),
+            ..full
+        };
+        let vtable = unsafe { read_vtable(&partial) }.unwrap();
+        assert!(vtable.get_config_schema.is_some());
+        assert!(vtable.get_status_detail.is_none());
+
+        let vtable = unsafe { read_vtable(&full) }.unwrap();
+        assert!(vtable.get_config_schema.is_some());
+        let detail = (vtable.get_status_detail.unwrap())(std::ptr::null_mut());
+        assert_eq!(unsafe { vtable.take_string(detail) }, "Detail");
+
+        let broken = PluginVTable { struct_size: 8, ..full };
+        assert!(unsafe { read_vtable(&broken) }.is_err());
+
+        // A size in the middle of an entry would copy half a function pointer
+        let misaligned = PluginVTable {
+            struct_size: std::mem::offset_of!(PluginVTable, get_last_error) + 3,
+            ..full
+        };
+        match unsafe { read_vtable(&misaligned) } {
+            Ok(_) => panic!("misaligned vtable size accepted"),
+            Err(e) => assert!(e.contains("entry boundary")),
+        }
     }
 }
@@ -0,0 +1,615 @@
+//! Safe way to write plugins in Rust
+//!
+//! Implement [`Plugin`] and export it with [`export_plugin!`](crate::export_plugin).
+//! The macro builds the [`PluginVTable`], keeps one plugin value per `init` (no
+//! globals), turns returned errors into `get_last_error` and catches panics so
+//! they never cross the FFI boundary:
+//!
+//! ```ignore
+//! use plugins_api::{capabilities, export_plugin, sdk::Plugin, HostServices};
+//!
+//! #[derive(serde::Deserialize)]
+//! struct Config {
+//!     prefix: String,
+//! }
+//!
+//! struct Echo {
+//!     prefix: String,
+//! }
+//!
+//! impl Plugin for Echo {
+//!     type Config = Config;
+//!     const NAME: &'static str = "Echo";
+//!     const VERSION: &'static str = "1.0.0";
+//!     const CAPABILITIES: u64 = capabilities::CONFIG | capabilities::TEXT;
+//!
+//!     fn new(_host: Option<HostServices>) -> Self {
+//!         Echo { prefix: String::new() }
+//!     }
+//!
+//!     fn configure(&mut self, config: Config) -> Result<(), String> {
+//!         self.prefix = config.prefix;
+//!         Ok(())
+//!     }
+//!
+//!     fn on_text(&mut self, text: &str) -> Result<(), String> {
+//!         println!("{}{}", self.prefix, text);
+//!         Ok(())
+//!     }
+//! }
+//!
+//! export_plugin!(Echo);
+//! ```
+
+use crate::{
+    free_plugin_audio, free_plugin_string, HostServices, PluginAudio, PluginAudioBuffer, PluginStatus, PluginStr,
+    PluginString, PluginVTable, TRANSFORM_REPLACED, TRANSFORM_UNCHANGED,
+};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::any::Any;
+use std::ffi::c_void;
+use std::panic::{self, AssertUnwindSafe};
+
+/// Failed status check: the status to report and why
+#[derive(Debug, Clone)]
+pub struct StatusError {
+    pub status: PluginStatus,
+    pub message: String,
+}
+
+impl StatusError {
+    pub fn new(status: PluginStatus, message: impl Into<String>) -> Self {
+        Self {
+            status,
+            message: message.into(),
+        }
+    }
+}
+
+/// Playback and app events (capability `EVENTS`)
+#[derive(Debug, Clone, Copy)]
+pub enum Event<'a> {
+    TtsStarted { id: &'a str, text: &'a str },
+    TtsCompleted { id: &'a str, text: &'a str },
+    TtsFailed { id: &'a str, text: &'a str, error: &'a str },
+    TtsCancelled { id: &'a str, text: &'a str },
+    ProviderChanged { provider: &'a str },
+    BlockingChanged { blocking: bool },
+}
+
+/// Synthesized audio passed to [`Plugin::on_audio`], valid only during the call
+#[derive(Debug, Clone, Copy)]
+pub struct Audio<'a> {
+    /// Encoded audio bytes exactly as returned by the TTS provider
+    pub data: &'a [u8],
+    /// "mp3", "opus", "aac", "flac", "wav", "ogg" or "pcm"
+    pub format: &'a str,
+    pub sample_rate: u32,
+    pub channels: u16,
+    /// Duration in milliseconds (0 if unknown)
+    pub duration_ms: u64,
+    /// Id of the history message being spoken (empty outside the queue)
+    pub message_id: &'a str,
+}
+
+/// Details of a spoken message passed to [`Plugin::on_message`] (API 3.6)
+///
+/// Fields are None when the host doesn't know them (older hosts send none at all).
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct MessageDetails {
+    /// Id of the history message
+    pub id: Option<String>,
+    /// "typed", "repeated" or "api"
+    pub source: Option<String>,
+    /// TTS provider id ("system", "openai", "plugin:<id>", ...)
+    pub provider: Option<String>,
+    pub voice: Option<String>,
+}
+
+/// Voice offered by a TTS provider plugin
+#[derive(Debug, Clone, Serialize)]
+pub struct Voice {
+    pub id: String,
+    pub name: String,
+}
+
+/// A plugin written in safe Rust
+///
+/// Only the methods of the declared [`Plugin::CAPABILITIES`] are called by the
+/// host; the others can keep their defaults. An `Err` makes the call fail and
+/// its text is shown to the user as the plugin's last error.
+///
+/// The plugin is dropped by `destroy` and the library may be unloaded right
+/// after it, so threads the plugin started must be stopped and joined in `Drop`.
+pub trait Plugin: Sized + 'static {
+    /// Config as sent by the host, parsed with serde
+    type Config: DeserializeOwned;
+
+    /// Display name
+    const NAME: &'static str;
+    /// Version string (e.g. "1.0.0")
+    const VERSION: &'static str;
+    /// Bitmask of [`crate::capabilities`] flags
+    const CAPABILITIES: u64;
+
+    /// Create the plugin; `host` is None for hosts older than API 3.5
+    fn new(host: Option<HostServices>) -> Self;
+
+    /// JSON schema of the config (shown as the settings form)
+    fn config_schema() -> serde_json::Value {
+        serde_json::json!({})
+    }
+
+    fn configure(&mut self, config: Self::Config) -> Result<(), String> {
+        let _ = config;
+        Ok(())
+    }
+
+    fn status(&mut self) -> Result<PluginStatus, StatusError> {
+        Ok(PluginStatus::Ok)
+    }
+
+    /// Shown next to the status (e.g. "Logging to C:\logs\x.txt")
+    fn status_detail(&self) -> Option<String> {
+        None
+    }
+
+    fn on_text(&mut self, text: &str) -> Result<(), String> {
+        let _ = text;
+        Ok(())
+    }
+
+    /// Spoken text with its details; by default the details are dropped and
+    /// `on_text` is called
+    fn on_message(&mut self, text: &str, details: &MessageDetails) -> Result<(), String> {
+        let _ = details;
+        self.on_text(text)
+    }
+
+    /// New text, or None to keep it unchanged
+    fn transform_text(&mut self, text: &str) -> Result<Option<String>, String> {
+        let _ = text;
+        Ok(None)
+    }
+
+    fn on_event(&mut self, event: Event<'_>) {
+        let _ = event;
+    }
+
+    fn on_audio(&mut self, audio: Audio<'_>) -> Result<(), String> {
+        let _ = audio;
+        Ok(())
+    }
+
+    fn voices(&mut self) -> Vec<Voice> {
+        Vec::new()
+    }
+
+    /// Build the result with [`PluginAudioBuffer::from_vec`] or [`PluginAudioBuffer::from_pcm`]
+    fn synthesize(&mut self, text: &str, voice: &str) -> Result<PluginAudioBuffer, String> {
+        let _ = (text, voice);
+        Err(format!("{} is not a TTS provider", Self::NAME))
+    }
+}
+
+/// Plugin value behind the `plugin_data` pointer
+struct Instance<P> {
+    plugin: P,
+    last_error: String,
+}
+
+fn panic_message(payload: &(dyn Any + Send)) -> String {
+    match payload.downcast_ref::<&str>() {
+        Some(message) => message.to_string(),
+        None => match payload.downcast_ref::<String>() {
+            Some(message) => message.clone(),
+            None => "unknown panic".to_string(),
+        },
+    }
+}
+
+/// Run `f` on the instance; a panic is recorded as the last error and `fallback` returned
+fn with_instance<P: Plugin, T>(data: *mut c_void, fallback: T, f: impl FnOnce(&mut Instance<P>) -> T) -> T {
+    if data.is_null() {
+        return fallback;
+    }
+    // SAFETY: `data` was created by `create` for this plugin type and the host
+    // makes one call at a time until `destroy`
+    let instance = unsafe { &mut *(data as *mut Instance<P>) };
+    match panic::catch_unwind(AssertUnwindSafe(|| f(instance))) {
+        Ok(value) => value,
+        Err(payload) => {
+            instance.last_error = format!("{} panicked: {}", P::NAME, panic_message(payload.as_ref()));
+            fallback
+        }
+    }
+}
+
+/// 0 on success, -1 with the error remembered otherwise
+fn result_code<P>(instance: &mut Instance<P>, result: Result<(), String>) -> i32 {
+    match result {
+        Ok(()) => {
+            instance.last_error.clear();
+            0
+        }
+        Err(e) => {
+            instance.last_error = e;
+            -1
+        }
+    }
+}
+
+fn create<P: Plugin>(host: Option<HostServices>) -> *mut c_void {
+    match panic::catch_unwind(|| P::new(host)) {
+        Ok(plugin) => Box::into_raw(Box::new(Instance {
+            plugin,
+            last_error: String::new(),
+        })) as *mut c_void,
+        Err(payload) => {
+            eprintln!("[{}] init panicked: {}", P::NAME, panic_message(payload.as_ref()));
+            std::ptr::null_mut()
+        }
+    }
+}
+
+extern "C" fn ffi_name<P: Plugin>() -> PluginString {
+    PluginString::from(P::NAME)
+}
+
+extern "C" fn ffi_version<P: Plugin>() -> PluginString {
+    PluginString::from(P::VERSION)
+}
+
+extern "C" fn ffi_init<P: Plugin>() -> *mut c_void {
+    create::<P>(None)
+}
+
+extern "C" fn ffi_init_with_host<P: Plugin>(host: *const HostServices) -> *mut c_void {
+    // SAFETY: the host passes a valid table that outlives the plugin
+    let host = if host.is_null() { None } else { Some(unsafe { *host }) };
+    create::<P>(host)
+}
+
+extern "C" fn ffi_destroy<P: Plugin>(data: *mut c_void) {
+    if data.is_null() {
+        return;
+    }
+    // SAFETY: `data` came from `create` and the host destroys it exactly once
+    let instance = unsafe { Box::from_raw(data as *mut Instance<P>) };
+    if panic::catch_unwind(AssertUnwindSafe(|| drop(instance))).is_err() {
+        eprintln!("[{}] destroy panicked", P::NAME);
+    }
+}
+
+extern "C" fn ffi_get_config_schema<P: Plugin>() -> PluginString {
+    match panic::catch_unwind(P::config_schema) {
+        Ok(schema) => PluginString::from(schema.to_string()),
+        Err(_) => PluginString::empty(),
+    }
+}
+
+extern "C" fn ffi_set_config<P: Plugin>(data: *mut c_void, config: PluginStr) -> i32 {
+    with_instance::<P, _>(data, -1, |instance| {
+        // SAFETY: the string is borrowed from the host for this call
+        let config = unsafe { config.as_str() };
+        let result = serde_json::from_str::<P::Config>(&config)
+            .map_err(|e| format!("Invalid config: {}", e))
+            .and_then(|config| instance.plugin.configure(config));
+        result_code(instance, result)
+    })
+}
+
+extern "C" fn ffi_check_status<P: Plugin>(data: *mut c_void) -> PluginStatus {
+    with_instance::<P, _>(data, PluginStatus::UnknownError, |instance| match instance.plugin.status() {
+        Ok(status) => {
+            instance.last_error.clear();
+            status
+        }
+        Err(e) => {
+            instance.last_error = e.message;
+            e.status
+        }
+    })
+}
+
+extern "C" fn ffi_on_text<P: Plugin>(data: *mut c_void, text: PluginStr) -> i32 {
+    with_instance::<P, _>(data, -1, |instance| {
+        // SAFETY: the string is borrowed from the host for this call
+        let text = unsafe { text.as_str() };
+        let result = instance.plugin.on_text(&text);
+        result_code(instance, result)
+    })
+}
+
+extern "C" fn ffi_on_message<P: Plugin>(data: *mut c_void, text: PluginStr, details: PluginStr) -> i32 {
+    with_instance::<P, _>(data, -1, |instance| {
+        // SAFETY: both strings are borrowed from the host for this call
+        let (text, details) = unsafe { (text.as_str(), details.as_str()) };
+        let details: MessageDetails = serde_json::from_str(&details).unwrap_or_default();
+        let result = instance.plugin.on_message(&text, &details);
+        result_code(instance, result)
+    })
+}
+
+extern "C" fn ffi_get_last_error<P: Plugin>(data: *mut c_void) -> PluginString {
+    with_instance::<P, _>(data, PluginString::empty(), |instance| {
+        PluginString::from(instance.last_error.as_str())
+    })
+}
+
+extern "C" fn ffi_get_status_detail<P: Plugin>(data: *mut c_void) -> PluginString {
+    with_instance::<P, _>(data, PluginString::empty(), |instance| {
+        PluginString::from(instance.plugin.status_detail().unwrap_or_default())
+    })
+}
+
+extern "C" fn ffi_transform_text<P: Plugin>(data: *mut c_void, text: PluginStr, out: *mut PluginString) -> i32 {
+    with_instance::<P, _>(data, -1, |instance| {
+        // SAFETY: the string is borrowed from the host for this call
+        let text = unsafe { text.as_str() };
+        match instance.plugin.transform_text(&text) {
+            Ok(Some(_)) if out.is_null() => -1,
+            Ok(Some(new_text)) => {
+                // SAFETY: `out` is a valid, non-null pointer provided by the host
+                unsafe { *out = PluginString::from(new_text) };
+                instance.last_error.clear();
+                TRANSFORM_REPLACED
+            }
+            Ok(None) => {
+                instance.last_error.clear();
+                TRANSFORM_UNCHANGED
+            }
+            Err(e) => {
+                instance.last_error = e;
+                -1
+            }
+        }
+    })
+}
+
+fn dispatch_event<P: Plugin>(data: *mut c_void, call: impl FnOnce(&mut P)) {
+    with_instance::<P, _>(data, (), |instance| call(&mut instance.plugin))
+}
+
+extern "C" fn ffi_on_tts_started<P: Plugin>(data: *mut c_void, id: PluginStr, text: PluginStr) {
+    // SAFETY (all events): the strings are borrowed from the host for this call
+    let (id, text) = unsafe { (id.as_str(), text.as_str()) };
+    dispatch_event::<P>(data, |p| p.on_event(Event::TtsStarted { id: &id, text: &text }));
+}
+
+extern "C" fn ffi_on_tts_completed<P: Plugin>(data: *mut c_void, id: PluginStr, text: PluginStr) {
+    let (id, text) = unsafe { (id.as_str(), text.as_str()) };
+    dispatch_event::<P>(data, |p| p.on_event(Event::TtsCompleted { id: &id, text: &text }));
+}
+
+extern "C" fn ffi_on_tts_failed<P: Plugin>(data: *mut c_void, id: PluginStr, text: PluginStr, error: PluginStr) {
+    let (id, text, error) = unsafe { (id.as_str(), text.as_str(), error.as_str()) };
+    dispatch_event::<P>(data, |p| {
+        p.on_event(Event::TtsFailed {
+            id: &id,
+            text: &text,
+            error: &error,
+        })
+    });
+}
+
+extern "C" fn ffi_on_tts_cancelled<P: Plugin>(data: *mut c_void, id: PluginStr, text: PluginStr) {
+    let (id, text) = unsafe { (id.as_str(), text.as_str()) };
+    dispatch_event::<P>(data, |p| p.on_event(Event::TtsCancelled { id: &id, text: &text }));
+}
+
+extern "C" fn ffi_on_provider_changed<P: Plugin>(data: *mut c_void, provider: PluginStr) {
+    let provider = unsafe { provider.as_str() };
+    dispatch_event::<P>(data, |p| p.on_event(Event::ProviderChanged { provider: &provider }));
+}
+
+extern "C" fn ffi_on_blocking_changed<P: Plugin>(data: *mut c_void, blocking: bool) {
+    dispatch_event::<P>(data, |p| p.on_event(Event::BlockingChanged { blocking }));
+}
+
+extern "C" fn ffi_on_audio<P: Plugin>(data: *mut c_void, audio: *const PluginAudio) -> i32 {
+    if audio.is_null() {
+        return -1;
+    }
+    with_instance::<P, _>(data, -1, |instance| {
+        // SAFETY: the audio and everything it points to is borrowed for this call
+        let (audio, format, message_id) = unsafe { (&*audio, (*audio).format.as_str(), (*audio).message_id.as_str()) };
+        let result = instance.plugin.on_audio(Audio {
+            data: unsafe { audio.bytes() },
+            format: &format,
+            sample_rate: audio.sample_rate,
+            channels: audio.channels,
+            duration_ms: audio.duration_ms,
+            message_id: &message_id,
+        });
+        result_code(instance, result)
+    })
+}
+
+extern "C" fn ffi_get_voices<P: Plugin>(data: *mut c_void) -> PluginString {
+    with_instance::<P, _>(data, PluginString::empty(), |instance| {
+        let voices = instance.plugin.voices();
+        PluginString::from(serde_json::to_string(&voices).unwrap_or_default())
+    })
+}
+
+extern "C" fn ffi_synthesize<P: Plugin>(
+    data: *mut c_void,
+    text: PluginStr,
+    voice: PluginStr,
+    out: *mut PluginAudioBuffer,
+) -> i32 {
+    if out.is_null() {
+        return -1;
+    }
+    with_instance::<P, _>(data, -1, |instance| {
+        // SAFETY: the strings are borrowed from the host for this call
+        let (text, voice) = unsafe { (text.as_str(), voice.as_str()) };
+        match instance.plugin.synthesize(&text, &voice) {
+            Ok(audio) => {
+                // SAFETY: `out` is a valid, non-null pointer provided by the host
+                unsafe { *out = audio };
+                instance.last_error.clear();
+                0
+            }
+            Err(e) => {
+                instance.last_error = e;
+                -1
+            }
+        }
+    })
+}
+
+/// Vtable for a [`Plugin`] (used by [`export_plugin!`](crate::export_plugin))
+pub const fn vtable<P: Plugin>() -> PluginVTable {
+    PluginVTable {
+        struct_size: std::mem::size_of::<PluginVTable>(),
+        capabilities: P::CAPABILITIES,
+        name: ffi_name::<P>,
+        version: ffi_version::<P>,
+        init: ffi_init::<P>,
+        destroy: ffi_destroy::<P>,
+        free_string: free_plugin_string,
+        get_config_schema: Some(ffi_get_config_schema::<P>),
+        set_config: Some(ffi_set_config::<P>),
+        check_status: Some(ffi_check_status::<P>),
+        on_text: Some(ffi_on_text::<P>),
+        get_last_error: Some(ffi_get_last_error::<P>),
+        get_status_detail: Some(ffi_get_status_detail::<P>),
+        transform_text: Some(ffi_transform_text::<P>),
+        on_tts_started: Some(ffi_on_tts_started::<P>),
+        on_tts_completed: Some(ffi_on_tts_completed::<P>),
+        on_tts_failed: Some(ffi_on_tts_failed::<P>),
+        on_tts_cancelled: Some(ffi_on_tts_cancelled::<P>),
+        on_provider_changed: Some(ffi_on_provider_changed::<P>),
+        on_blocking_changed: Some(ffi_on_blocking_changed::<P>),
+        on_audio: Some(ffi_on_audio::<P>),
+        get_voices: Some(ffi_get_voices::<P>),
+        synthesize: Some(ffi_synthesize::<P>),
+        free_audio: Some(free_plugin_audio),
+        init_with_host: Some(ffi_init_with_host::<P>),
+        on_message: Some(ffi_on_message::<P>),
+    }
+}
+
+/// Export a [`sdk::Plugin`](crate::sdk::Plugin) implementation from a `cdylib`
+///
+/// Generates the `plugin_api_version` and `get_plugin_vtable` functions the host
+/// looks for. Use it once per library.
+#[macro_export]
+macro_rules! export_plugin {
+    ($plugin:ty) => {
+        static __PLUGIN_VTABLE: $crate::PluginVTable = $crate::sdk::vtable::<$plugin>();
+
+        /// Plugin API version this library was built for
+        #[no_mangle]
+        pub extern "C" fn plugin_api_version() -> u32 {
+            $crate::PLUGIN_API_VERSION
+        }
+
+        /// Plugin function table
+        #[no_mangle]
+        pub extern "C" fn get_plugin_vtable() -> *const $crate::PluginVTable {
+            &__PLUGIN_VTABLE
+        }
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::capabilities;
+    use serde::Deserialize;
+
+    #[derive(Deserialize)]
+    struct Config {
+        suffix: String,
+    }
+
+    struct Suffix {
+        suffix: String,
+        events: Vec<String>,
+    }
+
+    impl Plugin for Suffix {
+        type Config = Config;
+        const NAME: &'static str = "Suffix";
+        const VERSION: &'static str = "0.1.0";
+        const CAPABILITIES: u64 = capabilities::CONFIG | capabilities::TRANSFORM | capabilities::EVENTS;
+
+        fn new(_host: Option<HostServices>) -> Self {
+            Suffix {
+                suffix: String::new(),
+                events: Vec::new(),
+            }
+        }
+
+        fn configure(&mut self, config: Config) -> Result<(), String> {
+            if config.suffix == "!" {
+                panic!("no shouting");
+            }
+            self.suffix = config.suffix;
+            Ok(())
+        }
+
+        fn transform_text(&mut self, text: &str) -> Result<Option<String>, String> {
+            if self.suffix.is_empty() {
+                return Err("Suffix is not set".to_string());
+            }
+            Ok(Some(format!("{}{}", text, self.suffix)))
+        }
+
+        fn on_event(&mut self, event: Event<'_>) {
+            if let Event::ProviderChanged { provider } = event {
+                self.events.push(provider.to_string());
+            }
+        }
+    }
+
+    fn last_error(vtable: &PluginVTable, data: *mut c_void) -> String {
+        unsafe { vtable.take_string((vtable.get_last_error.unwrap())(data)) }
+    }
+
+    fn transform(vtable: &PluginVTable, data: *mut c_void, text: &str) -> Result<String, String> {
+        let mut out = PluginString::empty();
+        match (vtable.transform_text.unwrap())(data, PluginStr::new(text), &mut out) {
+            TRANSFORM_REPLACED => Ok(unsafe { vtable.take_string(out) }),
+            _ => Err(last_error(vtable, data)),
+        }
+    }
+
+    #[test]
+    fn test_plugin_through_vtable() {
+        let vtable = vtable::<Suffix>();
+        assert_eq!(unsafe { vtable.take_string((vtable.name)()) }, "Suffix");
+        let data = (vtable.init)();
+        let set_config = vtable.set_config.unwrap();
+
+        // Errors are reported through get_last_error
+        assert_eq!(transform(&vtable, data, "hi").unwrap_err(), "Suffix is not set");
+        assert_eq!(set_config(data, PluginStr::new(r#"{"other": 1}"#)), -1);
+        assert!(last_error(&vtable, data).starts_with("Invalid config"));
+
+        assert_eq!(set_config(data, PluginStr::new(r#"{"suffix": "?"}"#)), 0);
+        assert_eq!(last_error(&vtable, data), "");
+        assert_eq!(transform(&vtable, data, "hi").unwrap(), "hi?");
+
+        (vtable.on_provider_changed.unwrap())(data, PluginStr::new("openai"));
+        let plugin = unsafe { &(*(data as *mut Instance<Suffix>)).plugin };
+        assert_eq!(plugin.events, vec!["openai"]);
+
+        // A panic doesn't cross the boundary and leaves the plugin usable
+        assert_eq!(set_config(data, PluginStr::new(r#"{"suffix": "!"}"#)), -1);
+        assert_eq!(last_error(&vtable, data), "Suffix panicked: no shouting");
+        assert_eq!(transform(&vtable, data, "hi").unwrap(), "hi?");
+
+        // A good status check clears an old error
+        assert_eq!(set_config(data, PluginStr::new(r#"{"suffix": "!"}"#)), -1);
+        assert_eq!((vtable.check_status.unwrap())(data), PluginStatus::Ok);
+        assert_eq!(last_error(&vtable, data), "");
+
+        (vtable.destroy)(data);
+    }
+}
@@ -0,0 +1,161 @@
+//! String buffers passed across the plugin boundary
+//!
+//! Ownership rules:
+//! - [`PluginStr`] is borrowed: the caller keeps ownership and the callee may only
+//!   read it for the duration of the call (host → plugin arguments).
+//! - [`PluginString`] is owned by the side that allocated it (the plugin). The receiver
+//!   copies the contents and hands the buffer back through `PluginVTable::free_string`,
+//!   so memory is always released by the allocator that created it.
+
+use std::borrow::Cow;
+
+/// Borrowed UTF-8 text, valid only during the call it is passed to
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct PluginStr {
+    pub ptr: *const u8,
+    pub len: usize,
+}
+
+impl PluginStr {
+    /// Borrow a Rust string (the string must outlive the call)
+    pub fn new(s: &str) -> Self {
+        Self {
+            ptr: s.as_ptr(),
+            len: s.len(),
+        }
+    }
+
+    /// View the text (invalid UTF-8 is replaced)
+    ///
+    /// # Safety
+    ///
+    /// `ptr` must be null or point to `len` readable bytes for the lifetime `'a`.
+    pub unsafe fn as_str<'a>(&self) -> Cow<'a, str> {
+        if self.ptr.is_null() || self.len == 0 {
+            return Cow::Borrowed("");
+        }
+        let bytes = std::slice::from_raw_parts(self.ptr, self.len);
+        String::from_utf8_lossy(bytes)
+    }
+}
+
+impl<'a> From<&'a str> for PluginStr {
+    fn from(s: &'a str) -> Self {
+        Self::new(s)
+    }
+}
+
+/// UTF-8 text allocated by the plugin and released by the plugin's `free_string`
+#[repr(C)]
+#[derive(Debug)]
+pub struct PluginString {
+    pub ptr: *mut u8,
+    pub len: usize,
+    pub capacity: usize,
+}
+
+impl PluginString {
+    /// Empty value (no allocation, nothing to free)
+    pub const fn empty() -> Self {
+        Self {
+            ptr: std::ptr::null_mut(),
+            len: 0,
+            capacity: 0,
+        }
+    }
+
+    /// Hand a Rust string over to the other side (plugin side)
+    pub fn from_string(s: String) -> Self {
+        if s.is_empty() {
+            return Self::empty();
+        }
+        let mut s = std::mem::ManuallyDrop::new(s);
+        Self {
+            ptr: s.as_mut_ptr(),
+            len: s.len(),
+            capacity: s.capacity(),
+        }
+    }
+
+    /// True if there is no text
+    pub fn is_empty(&self) -> bool {
+        self.ptr.is_null() || self.len == 0
+    }
+
+    /// Copy the text out without taking ownership (invalid UTF-8 is replaced)
+    ///
+    /// # Safety
+    ///
+    /// The buffer must still be alive (not yet passed to `free_string`).
+    pub unsafe fn to_string_lossy(&self) -> String {
+        PluginStr {
+            ptr: self.ptr,
+            len: self.len,
+        }
+        .as_str()
+        .into_owned()
+    }
+
+    /// Reclaim a buffer created by [`PluginString::from_string`] in this same module
+    ///
+    /// # Safety
+    ///
+    /// Must only be called by the side that allocated the buffer, exactly once.
+    pub unsafe fn into_string(self) -> String {
+        if self.ptr.is_null() {
+            return String::new();
+        }
+        String::from_raw_parts(self.ptr, self.len, self.capacity)
+    }
+}
+
+impl From<String> for PluginString {
+    fn from(s: String) -> Self {
+        Self::from_string(s)
+    }
+}
+
+impl From<&str> for PluginString {
+    fn from(s: &str) -> Self {
+        Self::from_string(s.to_string())
+    }
+}
+
+/// `free_string` implementation for plugins written in Rust
+///
+/// Being compiled into the plugin, it frees with the plugin's own allocator.
+pub extern "C" fn free_plugin_string(s: PluginString) {
+    // SAFETY: the buffer was produced by `PluginString::from_string` inside this plugin
+    drop(unsafe { s.into_string() });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_plugin_str_roundtrip() {
+        let text = String::from("Привет");
+        let s = PluginStr::new(&text);
+        assert_eq!(unsafe { s.as_str() }, "Привет");
+
+        let null = PluginStr {
+            ptr: std::ptr::null(),
+            len: 5,
+        };
+        assert_eq!(unsafe { null.as_str() }, "");
+    }
+
+    #[test]
+    fn test_plugin_string_roundtrip() {
+        let s = PluginString::from("status: ok");
+        assert!(!s.is_empty());
+        assert_eq!(unsafe { s.to_string_lossy() }, "status: ok");
+        assert_eq!(unsafe { s.into_string() }, "status: ok");
+
+        let empty = PluginString::from_string(String::new());
+        assert!(empty.is_empty());
+        free_plugin_string(empty);
+    }
+}
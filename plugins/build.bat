@@ -2,4 +2,6 @@
 cargo build --release
 if %ERRORLEVEL% NEQ 0 exit /b %ERRORLEVEL%
 copy target\release\file_logger_plugin.dll ..\src-tauri\plugins\ /Y
-echo Plugin built and copied to src-tauri/plugins/
+copy target\release\webhook_plugin.dll ..\src-tauri\plugins\ /Y
+copy target\release\obs_overlay_plugin.dll ..\src-tauri\plugins\ /Y
+echo Plugins built and copied to src-tauri/plugins/
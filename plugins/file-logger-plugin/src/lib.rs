@@ -1,259 +1,456 @@
 //! File Logger Plugin - логирует полученные тексты в файл
 //!
 //! Пример плагина для app-tts, который записывает все полученные тексты
-//! в указанный файл с временными метками.
-
-use plugins_api::{PluginStatus, PluginVTable};
-use std::ffi::{c_char, c_void};
+//! в указанный файл с временными метками. Написан на безопасном SDK из
+//! `plugins_api::sdk`: vtable, разбор конфигурации и перехват паник
+//! генерирует макрос `export_plugin!`.
+//!
+//! Форматы записи: `plain` (как раньше, блоки с разделителем), `jsonl`
+//! (JSON-объект на строку) и `csv`. Вместе с текстом пишутся id сообщения,
+//! источник, провайдер и голос, если хост их передал (API 3.6).
+//!
+//! Ротация: `size` переименовывает `log.txt` в `log.1.txt` (старые сдвигаются
+//! до `log.<keep_files>.txt`), `daily` - в `log.YYYY-MM-DD.txt` по дате
+//! последней записи. `{date}` в имени файла сразу даёт файл на каждый день.
+//! Лишние старые файлы удаляются, остаётся `keep_files`.
+
+use plugins_api::sdk::{MessageDetails, Plugin, StatusError};
+use plugins_api::{capabilities, export_plugin, HostServices, PluginStatus};
+use serde::Deserialize;
 use std::fs::OpenOptions;
 use std::io::Write;
 use std::path::{Path, PathBuf};
-use std::slice;
+
+/// Разделитель между записями
+const SEPARATOR: &str = "\n------\n";
+
+/// Заголовок CSV (пишется в начало нового файла)
+const CSV_HEADER: &str = "timestamp,id,source,provider,voice,text\n";
+
+/// Подстановка даты в имени файла
+const DATE_PLACEHOLDER: &str = "{date}";
+
+/// Формат даты в именах файлов
+const DATE_FORMAT: &str = "%Y-%m-%d";
+
+/// Формат записей
+#[derive(Debug, Clone, Copy, PartialEq, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum LogFormat {
+    #[default]
+    Plain,
+    Jsonl,
+    Csv,
+}
+
+impl LogFormat {
+    fn name(self) -> &'static str {
+        match self {
+            LogFormat::Plain => "plain",
+            LogFormat::Jsonl => "jsonl",
+            LogFormat::Csv => "csv",
+        }
+    }
+}
+
+/// Когда начинать новый файл
+#[derive(Debug, Clone, Copy, PartialEq, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum Rotation {
+    #[default]
+    None,
+    Size,
+    Daily,
+}
+
+fn default_max_size_kb() -> u64 {
+    1024
+}
+
+fn default_keep_files() -> usize {
+    7
+}
+
+/// Конфигурация плагина
+#[derive(Deserialize)]
+struct FileLoggerConfig {
+    /// Путь к файлу лога (относительно exe или абсолютный), может содержать `{date}`
+    #[serde(default)]
+    file_path: Option<String>,
+    #[serde(default)]
+    format: LogFormat,
+    #[serde(default)]
+    rotation: Rotation,
+    /// Размер файла для ротации `size`, КБ
+    #[serde(default = "default_max_size_kb")]
+    max_size_kb: u64,
+    /// Сколько старых файлов хранить
+    #[serde(default = "default_keep_files")]
+    keep_files: usize,
+}
 
 /// Состояние плагина
-struct FileLoggerState {
-    /// Путь к файлу лога
-    file_path: PathBuf,
+struct FileLogger {
+    /// Путь к файлу лога (None, пока плагин не настроен); `{date}` ещё не подставлен
+    file_path: Option<PathBuf>,
     /// Базовая директория (для относительных путей)
     base_dir: PathBuf,
-    /// Последняя ошибка
-    last_error: String,
-    /// Настроен ли плагин
-    configured: bool,
+    format: LogFormat,
+    rotation: Rotation,
+    max_size: u64,
+    keep_files: usize,
 }
 
-/// Глобальное состояние плагина
-static mut STATE: Option<FileLoggerState> = None;
+impl FileLogger {
+    /// Путь к настроенному файлу лога
+    fn file_path(&self) -> Result<&Path, String> {
+        match self.file_path {
+            Some(ref path) => Ok(path),
+            None => Err("Plugin not configured".to_string()),
+        }
+    }
 
-/// Разделитель между записями
-const SEPARATOR: &str = "\n------\n";
+    /// Файл, в который пишется сегодня (с подставленной датой)
+    fn current_path(&self) -> Result<PathBuf, String> {
+        let path = self.file_path()?;
+        let path_str = path.to_string_lossy();
+        if !path_str.contains(DATE_PLACEHOLDER) {
+            return Ok(path.to_path_buf());
+        }
+        let today = chrono::Local::now().format(DATE_FORMAT).to_string();
+        Ok(PathBuf::from(path_str.replace(DATE_PLACEHOLDER, &today)))
+    }
 
-/// JSON схема конфигурации
-const CONFIG_SCHEMA: &str = r#"{
-  "type": "object",
-  "properties": {
-    "file_path": {
-      "type": "string",
-      "title": "File Path",
-      "description": "Path to log file (relative to exe or absolute)"
+    /// Открыть файл лога на дозапись
+    fn open(path: &Path) -> Result<std::fs::File, String> {
+        OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .map_err(|e| format!("{}: {}", e, path.display()))
+    }
+
+    /// Запись в выбранном формате
+    fn format_entry(&self, text: &str, details: &MessageDetails) -> String {
+        let now = chrono::Local::now();
+        match self.format {
+            LogFormat::Plain => {
+                // Без подробностей запись такая же, как до API 3.6
+                let mut header = format!("[{}]", now.format("%Y-%m-%d %H:%M:%S"));
+                let fields = [
+                    ("id", &details.id),
+                    ("source", &details.source),
+                    ("provider", &details.provider),
+                    ("voice", &details.voice),
+                ];
+                for (name, value) in fields {
+                    if let Some(value) = value {
+                        header.push_str(&format!(" {}={}", name, value));
+                    }
+                }
+                format!("{}\n{}\n", header, text)
+            }
+            LogFormat::Jsonl => {
+                let entry = serde_json::json!({
+                    "timestamp": now.to_rfc3339(),
+                    "id": details.id,
+                    "source": details.source,
+                    "provider": details.provider,
+                    "voice": details.voice,
+                    "text": text,
+                });
+                format!("{}\n", entry)
+            }
+            LogFormat::Csv => {
+                let fields = [
+                    now.to_rfc3339(),
+                    details.id.clone().unwrap_or_default(),
+                    details.source.clone().unwrap_or_default(),
+                    details.provider.clone().unwrap_or_default(),
+                    details.voice.clone().unwrap_or_default(),
+                    text.to_string(),
+                ];
+                let fields: Vec<String> = fields.iter().map(|f| csv_field(f)).collect();
+                format!("{}\n", fields.join(","))
+            }
+        }
     }
-  },
-  "required": ["file_path"]
-}"#;
 
-/// Имя плагина
-extern "C" fn plugin_name() -> *const c_char {
-    b"File Logger\0".as_ptr() as *const c_char
+    /// Убрать текущий файл в архив, если пора
+    fn rotate(&self, path: &Path, entry_len: u64) -> Result<(), String> {
+        let metadata = match std::fs::metadata(path) {
+            Ok(metadata) if metadata.len() > 0 => metadata,
+            // Файла ещё нет или он пуст
+            _ => return Ok(()),
+        };
+
+        match self.rotation {
+            Rotation::None => Ok(()),
+            Rotation::Size => {
+                if metadata.len() + entry_len <= self.max_size {
+                    return Ok(());
+                }
+                if self.keep_files == 0 {
+                    return std::fs::remove_file(path)
+                        .map_err(|e| format!("Failed to remove {}: {}", path.display(), e));
+                }
+                // log.<keep>.txt удаляется, остальные сдвигаются на один номер
+                let _ = std::fs::remove_file(sibling(path, &self.keep_files.to_string()));
+                for n in (1..self.keep_files).rev() {
+                    let from = sibling(path, &n.to_string());
+                    if from.exists() {
+                        rename(&from, &sibling(path, &(n + 1).to_string()))?;
+                    }
+                }
+                rename(path, &sibling(path, "1"))
+            }
+            // С {date} в имени файл и так свой на каждый день
+            Rotation::Daily if self.file_path()? != path => Ok(()),
+            Rotation::Daily => {
+                let modified = match metadata.modified() {
+                    Ok(modified) => chrono::DateTime::<chrono::Local>::from(modified).date_naive(),
+                    Err(_) => return Ok(()),
+                };
+                if modified == chrono::Local::now().date_naive() {
+                    return Ok(());
+                }
+                let date = modified.format(DATE_FORMAT).to_string();
+                rename(path, &sibling(path, &date))?;
+                let (prefix, suffix) = split_name(&sibling(path, DATE_PLACEHOLDER));
+                prune_dated(path, &prefix, &suffix, self.keep_files);
+                Ok(())
+            }
+        }
+    }
+
+    fn write_entry(&self, text: &str, details: &MessageDetails) -> Result<(), String> {
+        let template = self.file_path()?;
+        let path = self.current_path()?;
+        let entry = self.format_entry(text, details);
+        self.rotate(&path, entry.len() as u64)?;
+
+        let mut file = Self::open(&path)?;
+        let is_empty = file.metadata().map(|m| m.len() == 0).unwrap_or(true);
+
+        // Новый файл по шаблону с {date} (его мог создать и `status`):
+        // старые дни сверх keep_files удаляются
+        if is_empty && template != path.as_path() {
+            let (prefix, suffix) = split_name(template);
+            prune_dated(&path, &prefix, &suffix, self.keep_files + 1);
+        }
+
+        // Если файл не пуст, добавляем разделитель
+        if is_empty && self.format == LogFormat::Csv {
+            file.write_all(CSV_HEADER.as_bytes())
+                .map_err(|e| format!("Failed to write {}: {}", path.display(), e))?;
+        }
+
+        file.write_all(entry.as_bytes())
+            .map_err(|e| format!("Failed to write {}: {}", path.display(), e))?;
+
+        if !is_empty && self.format == LogFormat::Plain {
+            file.write_all(SEPARATOR.as_bytes())
+                .map_err(|e| format!("Failed to write separator: {}", e))?;
+        }
+
+        // Сбрасываем буфер
+        file.flush().map_err(|e| format!("Failed to flush: {}", e))
+    }
+}
+
+/// Поле CSV: в кавычках, если нужно (RFC 4180)
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
 }
 
-/// Версия плагина
-extern "C" fn plugin_version() -> *const c_char {
-    b"1.0.0\0".as_ptr() as *const c_char
+/// `log.txt` -> `log.<tag>.txt` в той же директории
+fn sibling(path: &Path, tag: &str) -> PathBuf {
+    let stem = path.file_stem().map(|s| s.to_string_lossy().into_owned()).unwrap_or_default();
+    let name = match path.extension() {
+        Some(ext) => format!("{}.{}.{}", stem, tag, ext.to_string_lossy()),
+        None => format!("{}.{}", stem, tag),
+    };
+    path.with_file_name(name)
 }
 
-/// Получить схему конфигурации
-extern "C" fn plugin_get_config_schema() -> *const c_char {
-    CONFIG_SCHEMA.as_ptr() as *const c_char
+/// Части имени файла до и после `{date}`
+fn split_name(path: &Path) -> (String, String) {
+    let name = path.file_name().map(|n| n.to_string_lossy().into_owned()).unwrap_or_default();
+    match name.split_once(DATE_PLACEHOLDER) {
+        Some((prefix, suffix)) => (prefix.to_string(), suffix.to_string()),
+        None => (name, String::new()),
+    }
+}
+
+/// Оставить `keep` самых новых файлов `<prefix>YYYY-MM-DD<suffix>` рядом с `path`
+fn prune_dated(path: &Path, prefix: &str, suffix: &str, keep: usize) {
+    let dir = match path.parent() {
+        Some(dir) => dir,
+        None => return,
+    };
+    let entries = match std::fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(_) => return,
+    };
+
+    let mut dated: Vec<(chrono::NaiveDate, PathBuf)> = entries
+        .filter_map(|entry| entry.ok())
+        .filter_map(|entry| {
+            let name = entry.file_name().to_string_lossy().into_owned();
+            let date = name.strip_prefix(prefix)?.strip_suffix(suffix)?;
+            let date = chrono::NaiveDate::parse_from_str(date, DATE_FORMAT).ok()?;
+            Some((date, entry.path()))
+        })
+        .collect();
+    dated.sort();
+
+    let excess = dated.len().saturating_sub(keep);
+    for (_, old) in dated.into_iter().take(excess) {
+        eprintln!("[FileLogger] removing old log {:?}", old);
+        if let Err(e) = std::fs::remove_file(&old) {
+            eprintln!("[FileLogger] remove failed: {}", e);
+        }
+    }
 }
 
-/// Инициализация плагина
-extern "C" fn plugin_init() -> *mut c_void {
-    unsafe {
+fn rename(from: &Path, to: &Path) -> Result<(), String> {
+    std::fs::rename(from, to).map_err(|e| format!("Failed to rotate {}: {}", from.display(), e))
+}
+
+impl Plugin for FileLogger {
+    type Config = FileLoggerConfig;
+
+    const NAME: &'static str = "File Logger";
+    const VERSION: &'static str = "1.1.0";
+    const CAPABILITIES: u64 = capabilities::CONFIG | capabilities::STATUS | capabilities::TEXT;
+
+    fn new(_host: Option<HostServices>) -> Self {
         // Получаем директорию exe
-        let exe_dir = std::env::current_exe()
+        let base_dir = std::env::current_exe()
             .ok()
             .and_then(|p| p.parent().map(|p| p.to_path_buf()))
             .unwrap_or_else(|| PathBuf::from("."));
 
-        STATE = Some(FileLoggerState {
-            file_path: PathBuf::new(),
-            base_dir: exe_dir,
-            last_error: String::new(),
-            configured: false,
-        });
-
-        STATE.as_mut().unwrap() as *mut _ as *mut c_void
+        FileLogger {
+            file_path: None,
+            base_dir,
+            format: LogFormat::default(),
+            rotation: Rotation::default(),
+            max_size: default_max_size_kb() * 1024,
+            keep_files: default_keep_files(),
+        }
     }
-}
 
-/// Установить конфигурацию
-extern "C" fn plugin_set_config(
-    plugin_data: *mut c_void,
-    config: *const c_char,
-    len: usize,
-) -> i32 {
-    unsafe {
-        let state = &mut *(plugin_data as *mut FileLoggerState);
-
-        // Конвертируем C строку в Rust
-        let slice = slice::from_raw_parts(config as *const u8, len);
-        let config_str = String::from_utf8_lossy(slice);
-
-        eprintln!("[FileLogger] set_config called: {}", config_str);
-
-        // Парсим JSON
-        let config_value: serde_json::Value = match serde_json::from_str(&config_str) {
-            Ok(v) => v,
-            Err(e) => {
-                state.last_error = format!("Invalid JSON: {}", e);
-                eprintln!("[FileLogger] JSON parse error: {}", e);
-                return -1;
-            }
-        };
-
-        // Получаем file_path
-        let file_path = match config_value.get("file_path") {
-            Some(v) if v.is_string() => {
-                let s = v.as_str().unwrap();
-                eprintln!("[FileLogger] file_path: {}", s);
-                s
+    /// JSON схема конфигурации
+    fn config_schema() -> serde_json::Value {
+        serde_json::json!({
+            "type": "object",
+            "properties": {
+                "file_path": {
+                    "type": "string",
+                    "title": "File Path",
+                    "description": "Path to log file (relative to exe or absolute); {date} in the name gives a file per day"
+                },
+                "format": {
+                    "type": "string",
+                    "title": "Format",
+                    "enum": ["plain", "jsonl", "csv"],
+                    "default": "plain"
+                },
+                "rotation": {
+                    "type": "string",
+                    "title": "Rotation",
+                    "description": "size: by max size, daily: by date of the last entry",
+                    "enum": ["none", "size", "daily"],
+                    "default": "none"
+                },
+                "max_size_kb": {
+                    "type": "integer",
+                    "title": "Max size, KB",
+                    "default": default_max_size_kb(),
+                    "minimum": 1
+                },
+                "keep_files": {
+                    "type": "integer",
+                    "title": "Old files to keep",
+                    "default": default_keep_files(),
+                    "minimum": 0,
+                    "maximum": 365
+                }
             },
-            other => {
-                state.last_error = format!("file_path is required, got: {:?}", other);
-                eprintln!("[FileLogger] file_path missing or invalid");
-                return -1;
+            "required": ["file_path"]
+        })
+    }
+
+    fn configure(&mut self, config: FileLoggerConfig) -> Result<(), String> {
+        let file_path = match config.file_path {
+            Some(file_path) if !file_path.is_empty() => file_path,
+            _ => {
+                eprintln!("[FileLogger] file_path missing");
+                return Err("file_path is required".to_string());
             }
         };
+        eprintln!("[FileLogger] file_path: {}", file_path);
 
         // Формируем полный путь
-        let full_path = if Path::new(file_path).is_absolute() {
+        let full_path = if Path::new(&file_path).is_absolute() {
             PathBuf::from(file_path)
         } else {
-            state.base_dir.join(file_path)
+            self.base_dir.join(file_path)
         };
 
         eprintln!("[FileLogger] full path: {:?}", full_path);
 
-        // Проверяем что можем создать/открыть файл
+        // Проверяем что можем создать директорию для файла
         if let Some(parent) = full_path.parent() {
             eprintln!("[FileLogger] creating dir: {:?}", parent);
             if let Err(e) = std::fs::create_dir_all(parent) {
-                state.last_error = format!("Failed to create directory: {}", e);
                 eprintln!("[FileLogger] dir creation failed: {}", e);
-                return -1;
+                return Err(format!("Failed to create directory {}: {}", parent.display(), e));
             }
         }
 
-        state.file_path = full_path;
-        state.configured = true;
-        state.last_error.clear();
-
-        eprintln!("[FileLogger] config OK");
-        0 // OK
+        self.file_path = Some(full_path);
+        self.format = config.format;
+        self.rotation = config.rotation;
+        self.max_size = config.max_size_kb.max(1) * 1024;
+        self.keep_files = config.keep_files;
+        eprintln!("[FileLogger] config OK ({:?}, rotation {:?})", self.format, self.rotation);
+        Ok(())
     }
-}
 
-/// Проверить статус плагина
-extern "C" fn plugin_check_status(plugin_data: *mut c_void) -> PluginStatus {
-    unsafe {
-        let state = &*(plugin_data as *mut FileLoggerState);
-
-        if !state.configured {
-            return PluginStatus::NotConfigured;
-        }
+    fn status(&mut self) -> Result<PluginStatus, StatusError> {
+        let path = match self.current_path() {
+            Ok(path) => path,
+            Err(_) => return Ok(PluginStatus::NotConfigured),
+        };
 
         // Проверяем что можем писать в файл
-        match OpenOptions::new()
-            .create(true)
-            .append(true)
-            .open(&state.file_path)
-        {
-            Ok(_) => PluginStatus::Ok,
-            Err(e) => {
-                // Записываем ошибку в состояние (нужен mutable access)
-                let state = &mut *(plugin_data as *mut FileLoggerState);
-                state.last_error = format!("Cannot open file: {}", e);
-                PluginStatus::ConnectionFailed
-            }
+        match Self::open(&path) {
+            Ok(_) => Ok(PluginStatus::Ok),
+            Err(e) => Err(StatusError::new(PluginStatus::ConnectionFailed, e)),
         }
     }
-}
-
-/// Обработать текст
-extern "C" fn plugin_on_text(
-    plugin_data: *mut c_void,
-    text: *const c_char,
-    len: usize,
-) -> i32 {
-    unsafe {
-        let state = &mut *(plugin_data as *mut FileLoggerState);
-
-        if !state.configured {
-            state.last_error = "Plugin not configured".to_string();
-            return -1;
-        }
 
-        // Конвертируем C строку в Rust
-        let slice = slice::from_raw_parts(text as *const u8, len);
-        let text_str = String::from_utf8_lossy(slice);
-
-        // Формируем запись с timestamp
-        let timestamp = chrono::Local::now().format("%Y-%m-%d %H:%M:%S");
-        let log_entry = format!("[{}]\n{}\n", timestamp, text_str);
-
-        // Открываем файл и дописываем
-        match OpenOptions::new()
-            .create(true)
-            .append(true)
-            .open(&state.file_path)
-        {
-            Ok(mut file) => {
-                // Если файл не пуст, добавляем разделитель
-                let metadata = file.metadata();
-                let need_separator = metadata
-                    .map(|m| m.len() > 0)
-                    .unwrap_or(false);
-
-                if let Err(e) = file.write_all(log_entry.as_bytes()) {
-                    state.last_error = format!("Failed to write: {}", e);
-                    return -1;
-                }
-
-                if need_separator {
-                    if let Err(e) = file.write_all(SEPARATOR.as_bytes()) {
-                        state.last_error = format!("Failed to write separator: {}", e);
-                        return -1;
-                    }
-                }
-
-                // Сбрасываем буфер
-                if let Err(e) = file.flush() {
-                    state.last_error = format!("Failed to flush: {}", e);
-                    return -1;
-                }
+    /// Подробности статуса: куда пишется лог
+    fn status_detail(&self) -> Option<String> {
+        Some(match self.current_path() {
+            Ok(path) => format!("Logging to {} ({})", path.display(), self.format.name()),
+            Err(_) => "File path is not set".to_string(),
+        })
+    }
 
-                state.last_error.clear();
-                0 // OK
-            }
-            Err(e) => {
-                state.last_error = format!("Failed to open file: {}", e);
-                -1
-            }
-        }
+    fn on_text(&mut self, text: &str) -> Result<(), String> {
+        self.write_entry(text, &MessageDetails::default())
     }
-}
 
-/// Освободить ресурсы
-extern "C" fn plugin_destroy(plugin_data: *mut c_void) {
-    unsafe {
-        let _state = Box::from_raw(plugin_data as *mut FileLoggerState);
+    fn on_message(&mut self, text: &str, details: &MessageDetails) -> Result<(), String> {
+        self.write_entry(text, details)
     }
 }
 
-/// Vtable плагина
-static VTABLE: PluginVTable = PluginVTable {
-    name: plugin_name,
-    version: plugin_version,
-    get_config_schema: plugin_get_config_schema,
-    set_config: plugin_set_config,
-    check_status: plugin_check_status,
-    on_text: plugin_on_text,
-    init: plugin_init,
-    destroy: plugin_destroy,
-};
-
-/// Экспортируемая функция для получения vtable
-#[no_mangle]
-pub extern "C" fn get_plugin_vtable() -> *const PluginVTable {
-    &VTABLE
-}
+export_plugin!(FileLogger);
@@ -0,0 +1,503 @@
+//! Host simulator for app-tts plugins
+//!
+//! Loads a built plugin library with the app's own loader (the plugin modules of
+//! `src-tauri` are compiled into this crate), so a plugin can be driven from
+//! `cargo test` on any OS without running the app:
+//!
+//! ```ignore
+//! use host_simulator::{build_plugin, PluginStatus, SimulatedPlugin};
+//!
+//! let path = build_plugin(Path::new(env!("CARGO_MANIFEST_DIR")).parent().unwrap(), "my-plugin").unwrap();
+//! let mut plugin = SimulatedPlugin::load(&path).unwrap();
+//! plugin.set_config(serde_json::json!({ "token": "..." })).unwrap();
+//! plugin.on_text("Hello").unwrap();
+//! assert_eq!(plugin.check_status(), PluginStatus::Ok);
+//! ```
+
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+
+/// The app's plugin loader (not everything of it is used here)
+#[allow(dead_code)]
+mod plugins;
+
+use plugins::dynamic::load_plugin;
+use plugins::host::PluginHost;
+use plugins::manifest::default_plugin_id;
+use plugins::schema;
+use plugins::types::LoadedPlugin;
+
+pub use plugins::host::HostRequest;
+pub use plugins::routing::{MessageSource, TextMessage};
+pub use plugins::types::{PluginEvent, PluginInfo};
+pub use plugins_api::PluginStatus;
+
+/// Simulators created by this process (each gets its own data directory)
+static NEXT_ID: AtomicUsize = AtomicUsize::new(0);
+
+/// A plugin library loaded the way the app loads it
+pub struct SimulatedPlugin {
+    plugin: LoadedPlugin,
+    /// Everything the plugin asked the host to do (enqueued text, notifications)
+    requests: Arc<Mutex<Vec<HostRequest>>>,
+    data_root: PathBuf,
+}
+
+impl SimulatedPlugin {
+    /// Load and enable a plugin library; its data lives in a fresh temp directory
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let data_root = std::env::temp_dir().join(format!(
+            "host-simulator-{}-{}",
+            std::process::id(),
+            NEXT_ID.fetch_add(1, Ordering::Relaxed)
+        ));
+        Self::load_with_data_root(path, data_root)
+    }
+
+    /// Like [`SimulatedPlugin::load`] with the plugin data kept under `data_root`
+    pub fn load_with_data_root(path: &Path, data_root: PathBuf) -> Result<Self, String> {
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let sink = Arc::clone(&requests);
+        let host = PluginHost::new(data_root.clone()).with_sender(Arc::new(move |request| {
+            if let Ok(mut requests) = sink.lock() {
+                requests.push(request);
+            }
+        }));
+
+        let mut plugin = load_plugin(path, &default_plugin_id(path), &host)?;
+        plugin.set_enabled(true);
+        Ok(Self {
+            plugin,
+            requests,
+            data_root,
+        })
+    }
+
+    /// Plugin info as shown in the app's plugin list
+    pub fn info(&self) -> PluginInfo {
+        self.plugin.info()
+    }
+
+    /// Where the plugin's data directory (`get_data_dir`) and key-value store live
+    pub fn data_root(&self) -> &Path {
+        &self.data_root
+    }
+
+    /// Apply a config like the settings form does
+    ///
+    /// The config is checked against the plugin's schema and completed with
+    /// defaults first; an invalid config never reaches the plugin.
+    pub fn set_config(&mut self, config: serde_json::Value) -> Result<(), String> {
+        let config = schema::validate_config(&self.plugin.config_schema, &config).map_err(|errors| {
+            let errors: Vec<String> = errors.iter().map(|e| format!("{}: {}", e.field, e.message)).collect();
+            errors.join(", ")
+        })?;
+        self.plugin.set_config(&config)
+    }
+
+    pub fn check_status(&mut self) -> PluginStatus {
+        self.plugin.check_status()
+    }
+
+    /// Send spoken text to the plugin
+    pub fn on_text(&mut self, text: &str) -> Result<(), String> {
+        self.plugin.on_text(text)
+    }
+
+    /// Send spoken text with its details (id, source, provider, voice) like the app does
+    pub fn on_message(&mut self, message: &TextMessage) -> Result<(), String> {
+        self.plugin.on_message(message)
+    }
+
+    /// Send a playback or app event (`tts:started`, `tts:completed`, ...)
+    pub fn on_event(&self, event: &PluginEvent) {
+        self.plugin.on_event(event)
+    }
+
+    /// Run text through the plugin's transform (None if it kept the text)
+    pub fn transform_text(&mut self, text: &str) -> Result<Option<String>, String> {
+        self.plugin.transform_text(text)
+    }
+
+    /// Requests the plugin made so far
+    pub fn requests(&self) -> Vec<HostRequest> {
+        self.requests.lock().map(|r| r.clone()).unwrap_or_default()
+    }
+}
+
+/// Build a plugin package of a cargo workspace and return the path of its library
+pub fn build_plugin(workspace: &Path, package: &str) -> Result<PathBuf, String> {
+    let cargo = std::env::var("CARGO").unwrap_or_else(|_| "cargo".to_string());
+    let status = Command::new(cargo)
+        .args(["build", "-p", package])
+        .current_dir(workspace)
+        .status()
+        .map_err(|e| format!("Failed to run cargo: {}", e))?;
+    if !status.success() {
+        return Err(format!("cargo build -p {} failed ({})", package, status));
+    }
+
+    let target = match std::env::var_os("CARGO_TARGET_DIR") {
+        Some(dir) => PathBuf::from(dir),
+        None => workspace.join("target"),
+    };
+    let library = target
+        .join("debug")
+        .join(libloading::library_filename(package.replace('-', "_")));
+    if library.exists() {
+        Ok(library)
+    } else {
+        Err(format!("{} was not built as a cdylib ({} not found)", package, library.display()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use std::io::{BufRead, BufReader, Read, Write};
+    use std::net::{TcpListener, TcpStream};
+    use std::sync::mpsc::{self, Receiver};
+    use std::time::{Duration, Instant};
+
+    fn load(package: &str) -> SimulatedPlugin {
+        let workspace = Path::new(env!("CARGO_MANIFEST_DIR")).parent().unwrap();
+        let path = build_plugin(workspace, package).unwrap();
+        SimulatedPlugin::load(&path).unwrap()
+    }
+
+    fn file_logger() -> SimulatedPlugin {
+        load("file-logger-plugin")
+    }
+
+    /// Request received by [`serve`]: method, headers (lowercase names) and body
+    struct Received {
+        method: String,
+        headers: Vec<(String, String)>,
+        body: String,
+    }
+
+    impl Received {
+        fn header(&self, name: &str) -> Option<&str> {
+            self.headers.iter().find(|(n, _)| n == name).map(|(_, v)| v.as_str())
+        }
+    }
+
+    /// Local HTTP stand-in: answers requests with `statuses` in turn (the last one repeats)
+    fn serve(statuses: &[u16]) -> (String, Receiver<Received>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/hook", listener.local_addr().unwrap());
+        let statuses = statuses.to_vec();
+        let (sender, received) = mpsc::channel();
+
+        std::thread::spawn(move || {
+            for (index, stream) in listener.incoming().enumerate() {
+                let mut stream = match stream {
+                    Ok(stream) => stream,
+                    Err(_) => return,
+                };
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+                let mut line = String::new();
+                reader.read_line(&mut line).unwrap();
+                let method = line.split(' ').next().unwrap_or_default().to_string();
+
+                let mut headers = Vec::new();
+                loop {
+                    line.clear();
+                    reader.read_line(&mut line).unwrap();
+                    match line.trim_end().split_once(':') {
+                        Some((name, value)) => headers.push((name.to_lowercase(), value.trim().to_string())),
+                        None => break,
+                    }
+                }
+                let length = headers
+                    .iter()
+                    .find(|(name, _)| name == "content-length")
+                    .and_then(|(_, value)| value.parse().ok())
+                    .unwrap_or(0);
+                let mut body = vec![0; length];
+                reader.read_exact(&mut body).unwrap();
+
+                let status = statuses[index.min(statuses.len() - 1)];
+                let _ = write!(stream, "HTTP/1.1 {} Test\r\nContent-Length: 0\r\nConnection: close\r\n\r\n", status);
+                let received = Received {
+                    method,
+                    headers,
+                    body: String::from_utf8(body).unwrap(),
+                };
+                if sender.send(received).is_err() {
+                    return;
+                }
+            }
+        });
+        (url, received)
+    }
+
+    #[test]
+    fn test_file_logger_writes_text() {
+        let temp = tempfile::tempdir().unwrap();
+        let log_path = temp.path().join("logs").join("tts.txt");
+        let mut plugin = file_logger();
+        assert_eq!(plugin.info().name, "File Logger");
+        assert_eq!(plugin.check_status(), PluginStatus::NotConfigured);
+
+        plugin.set_config(json!({ "file_path": log_path })).unwrap();
+        assert_eq!(plugin.check_status(), PluginStatus::Ok);
+        assert!(plugin.info().status_detail.unwrap().contains("tts.txt"));
+
+        plugin.on_text("Первое сообщение").unwrap();
+        plugin.on_text("Second").unwrap();
+        let log = std::fs::read_to_string(&log_path).unwrap();
+        assert!(log.contains("Первое сообщение\n"));
+        assert!(log.contains("Second\n"));
+        assert!(log.contains("------"));
+
+        // The logger doesn't ask the host for anything
+        assert!(plugin.requests().is_empty());
+    }
+
+    #[test]
+    fn test_file_logger_errors() {
+        let temp = tempfile::tempdir().unwrap();
+        let mut plugin = file_logger();
+
+        let err = plugin.on_text("too early").unwrap_err();
+        assert_eq!(err, "Plugin not configured");
+
+        // Checked against the schema before the plugin sees it
+        let err = plugin.set_config(json!({ "file_path": "" })).unwrap_err();
+        assert_eq!(err, "file_path: Required");
+
+        // A directory can't be opened as the log file
+        plugin.set_config(json!({ "file_path": temp.path() })).unwrap();
+        assert_eq!(plugin.check_status(), PluginStatus::ConnectionFailed);
+        assert!(plugin.on_text("lost").is_err());
+    }
+
+    fn message(text: &str, id: &str) -> TextMessage {
+        TextMessage {
+            text: text.to_string(),
+            source: MessageSource::Typed,
+            provider: "openai".to_string(),
+            id: Some(id.to_string()),
+            voice: Some("alloy".to_string()),
+        }
+    }
+
+    #[test]
+    fn test_file_logger_formats() {
+        let temp = tempfile::tempdir().unwrap();
+        let mut plugin = file_logger();
+
+        let jsonl = temp.path().join("tts.jsonl");
+        plugin.set_config(json!({ "file_path": jsonl, "format": "jsonl" })).unwrap();
+        plugin.on_message(&message("Привет", "7")).unwrap();
+        plugin.on_text("no details").unwrap();
+        let log = std::fs::read_to_string(&jsonl).unwrap();
+        let lines: Vec<serde_json::Value> = log.lines().map(|l| serde_json::from_str(l).unwrap()).collect();
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0]["id"], "7");
+        assert_eq!(lines[0]["source"], "typed");
+        assert_eq!(lines[0]["provider"], "openai");
+        assert_eq!(lines[0]["voice"], "alloy");
+        assert_eq!(lines[0]["text"], "Привет");
+        assert!(lines[0]["timestamp"].is_string());
+        assert!(lines[1]["id"].is_null());
+
+        let csv = temp.path().join("tts.csv");
+        plugin.set_config(json!({ "file_path": csv, "format": "csv" })).unwrap();
+        plugin.on_message(&message("Hello, \"world\"\nbye", "8")).unwrap();
+        let log = std::fs::read_to_string(&csv).unwrap();
+        let mut lines = log.splitn(2, '\n');
+        assert_eq!(lines.next().unwrap(), "timestamp,id,source,provider,voice,text");
+        assert!(lines.next().unwrap().ends_with(",8,typed,openai,alloy,\"Hello, \"\"world\"\"\nbye\"\n"));
+
+        let plain = temp.path().join("tts.txt");
+        plugin.set_config(json!({ "file_path": plain })).unwrap();
+        plugin.on_message(&message("text", "9")).unwrap();
+        let log = std::fs::read_to_string(&plain).unwrap();
+        assert!(log.contains("] id=9 source=typed provider=openai voice=alloy\ntext\n"));
+    }
+
+    #[test]
+    fn test_file_logger_rotation() {
+        let temp = tempfile::tempdir().unwrap();
+        let mut plugin = file_logger();
+
+        // Each entry is over 1 KB, so every write starts a new file; two old ones are kept
+        let log_path = temp.path().join("tts.log");
+        plugin
+            .set_config(json!({ "file_path": log_path, "rotation": "size", "max_size_kb": 1, "keep_files": 2 }))
+            .unwrap();
+        for i in 0..4 {
+            plugin.on_text(&format!("{}{}", i, "x".repeat(1100))).unwrap();
+        }
+        let read = |name: &str| std::fs::read_to_string(temp.path().join(name)).unwrap();
+        assert!(read("tts.log").contains("3xxx"));
+        assert!(read("tts.1.log").contains("2xxx"));
+        assert!(read("tts.2.log").contains("1xxx"));
+        assert!(!temp.path().join("tts.3.log").exists());
+
+        // Yesterday's file is renamed by its date
+        let daily = temp.path().join("daily.log");
+        std::fs::write(&daily, "old\n").unwrap();
+        let yesterday = std::time::SystemTime::now() - Duration::from_secs(24 * 60 * 60);
+        std::fs::File::options().write(true).open(&daily).unwrap().set_modified(yesterday).unwrap();
+        plugin.set_config(json!({ "file_path": daily, "rotation": "daily" })).unwrap();
+        plugin.on_text("today").unwrap();
+        let date = chrono::DateTime::<chrono::Local>::from(yesterday).format("%Y-%m-%d");
+        assert_eq!(read(&format!("daily.{}.log", date)), "old\n");
+        assert!(read("daily.log").contains("today"));
+
+        // {date} in the name: a file per day, old days beyond keep_files are removed
+        let dir = temp.path().join("days");
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("tts-2020-01-01.txt"), "").unwrap();
+        std::fs::write(dir.join("tts-2020-01-02.txt"), "").unwrap();
+        plugin
+            .set_config(json!({ "file_path": dir.join("tts-{date}.txt"), "keep_files": 1 }))
+            .unwrap();
+        plugin.on_text("dated").unwrap();
+        let today = chrono::Local::now().format("%Y-%m-%d");
+        assert!(std::fs::read_to_string(dir.join(format!("tts-{}.txt", today))).unwrap().contains("dated"));
+        assert!(!dir.join("tts-2020-01-01.txt").exists());
+        assert!(dir.join("tts-2020-01-02.txt").exists());
+        assert!(plugin.info().status_detail.unwrap().contains(&format!("tts-{}.txt (plain)", today)));
+    }
+
+    /// GET a path from a local server and return the response body
+    fn http_get(port: u16, path: &str) -> String {
+        let mut stream = std::net::TcpStream::connect(("127.0.0.1", port)).unwrap();
+        write!(stream, "GET {} HTTP/1.1\r\nHost: localhost\r\n\r\n", path).unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        assert!(response.starts_with("HTTP/1.1 200"), "{}", response);
+        response.split_once("\r\n\r\n").unwrap().1.to_string()
+    }
+
+    #[test]
+    fn test_obs_overlay_shows_spoken_text() {
+        let temp = tempfile::tempdir().unwrap();
+        let text_path = temp.path().join("overlay.txt");
+        let port = TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port();
+        let mut plugin = load("obs-overlay-plugin");
+        assert_eq!(plugin.check_status(), PluginStatus::NotConfigured);
+
+        plugin
+            .set_config(json!({ "file_path": text_path, "port": port, "clear_after_secs": 1, "history_lines": 1 }))
+            .unwrap();
+        assert_eq!(plugin.check_status(), PluginStatus::Ok);
+        assert!(plugin.info().status_detail.unwrap().contains(&format!("page http://127.0.0.1:{}/", port)));
+        assert!(http_get(port, "/").contains("<!DOCTYPE html>"));
+
+        let started = |id: &str, text: &str| PluginEvent::TtsStarted { id: id.to_string(), text: text.to_string() };
+        plugin.on_event(&started("1", "Первое"));
+        plugin.on_event(&started("2", "Второе"));
+        assert_eq!(std::fs::read_to_string(&text_path).unwrap(), "Первое\nВторое");
+        assert_eq!(http_get(port, "/text"), "Первое\nВторое");
+        let state: serde_json::Value = serde_json::from_str(&http_get(port, "/json")).unwrap();
+        assert_eq!(state, json!({ "current": "Второе", "history": ["Первое"] }));
+
+        // A client that never sends its request doesn't hold up the others
+        let _silent = TcpStream::connect(("127.0.0.1", port)).unwrap();
+        let asked = Instant::now();
+        assert_eq!(http_get(port, "/text"), "Первое\nВторое");
+        assert!(asked.elapsed() < Duration::from_secs(1));
+
+        // Cleared a second after the message is spoken
+        let completed = Instant::now();
+        plugin.on_event(&PluginEvent::TtsCompleted { id: "2".to_string(), text: "Второе".to_string() });
+        assert_eq!(http_get(port, "/text"), "Первое\nВторое");
+        let deadline = completed + Duration::from_secs(5);
+        while !http_get(port, "/text").is_empty() {
+            assert!(Instant::now() < deadline, "overlay text was not cleared");
+            std::thread::sleep(Duration::from_millis(50));
+        }
+        assert!(completed.elapsed() >= Duration::from_secs(1));
+        assert_eq!(std::fs::read_to_string(&text_path).unwrap(), "");
+
+        // Neither output is an invalid config
+        let err = plugin.set_config(json!({ "serve_http": false })).unwrap_err();
+        assert_eq!(err, "Set a text file or enable the page");
+    }
+
+    #[test]
+    fn test_webhook_posts_text() {
+        let (url, received) = serve(&[200]);
+        let mut plugin = load("webhook-plugin");
+        assert_eq!(plugin.check_status(), PluginStatus::NotConfigured);
+
+        plugin
+            .set_config(json!({
+                "url": url,
+                "body_template": "{\"content\": \"{{text}}\", \"source\": \"tts\"}",
+                "headers": "X-Source: app-tts",
+                "bearer_token": "secret"
+            }))
+            .unwrap();
+
+        // The status check after set_config is a real request, the next one uses its result
+        assert_eq!(plugin.check_status(), PluginStatus::Ok);
+        let probe = received.recv_timeout(Duration::from_secs(5)).unwrap();
+        assert_eq!(probe.method, "HEAD");
+
+        plugin.on_text("Привет, \"чат\"").unwrap();
+        let post = received.recv_timeout(Duration::from_secs(5)).unwrap();
+        assert_eq!(post.method, "POST");
+        assert_eq!(post.header("authorization"), Some("Bearer secret"));
+        assert_eq!(post.header("x-source"), Some("app-tts"));
+        assert_eq!(post.header("content-type"), Some("application/json"));
+        let body: serde_json::Value = serde_json::from_str(&post.body).unwrap();
+        assert_eq!(body, json!({ "content": "Привет, \"чат\"", "source": "tts" }));
+    }
+
+    #[test]
+    fn test_webhook_failure_reaches_host() {
+        let (url, received) = serve(&[200, 500]);
+        let mut plugin = load("webhook-plugin");
+        plugin.set_config(json!({ "url": url, "retries": 0 })).unwrap();
+        assert_eq!(received.recv_timeout(Duration::from_secs(5)).unwrap().method, "HEAD");
+
+        plugin.on_text("lost").unwrap();
+        assert_eq!(received.recv_timeout(Duration::from_secs(5)).unwrap().method, "POST");
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while !plugin.info().status_detail.unwrap().contains("failed 1") {
+            assert!(Instant::now() < deadline, "delivery failure was not recorded");
+            std::thread::sleep(Duration::from_millis(20));
+        }
+
+        // The next message fails, so the app's failure policy counts it
+        let err = plugin.on_text("next").unwrap_err();
+        assert!(err.contains("HTTP 500"));
+        assert_eq!(plugin.check_status(), PluginStatus::ConnectionFailed);
+        assert!(received.try_recv().is_err());
+    }
+
+    #[test]
+    fn test_webhook_retries_and_status() {
+        // A server error is retried, the second attempt succeeds
+        let (url, received) = serve(&[200, 503, 200]);
+        let mut plugin = load("webhook-plugin");
+        plugin.set_config(json!({ "url": url, "retries": 1 })).unwrap();
+        assert_eq!(received.recv_timeout(Duration::from_secs(5)).unwrap().method, "HEAD");
+        plugin.on_text("again").unwrap();
+        for _ in 0..2 {
+            let post = received.recv_timeout(Duration::from_secs(5)).unwrap();
+            assert_eq!(post.body, "{\"text\": \"again\"}");
+        }
+        std::thread::sleep(Duration::from_millis(100));
+        assert!(plugin.info().status_detail.unwrap().contains("sent 1, failed 0"));
+
+        // Rejected credentials and an unreachable server
+        let (url, _received) = serve(&[401]);
+        plugin.set_config(json!({ "url": url })).unwrap();
+        assert_eq!(plugin.check_status(), PluginStatus::AuthFailed);
+
+        let closed = TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap();
+        plugin.set_config(json!({ "url": format!("http://{}/hook", closed) })).unwrap();
+        assert_eq!(plugin.check_status(), PluginStatus::ConnectionFailed);
+        assert!(plugin.info().last_error.unwrap().contains("unreachable"));
+    }
+}
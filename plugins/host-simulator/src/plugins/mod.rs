@@ -0,0 +1,21 @@
+//! The app's plugin loader, built from the same sources as `src-tauri/src/plugins`
+//!
+//! Only the modules needed to load and call one plugin are included; the manager,
+//! workers and watcher depend on the app and are left out.
+
+#[path = "../../../../src-tauri/src/plugins/backend.rs"]
+pub mod backend;
+#[path = "../../../../src-tauri/src/plugins/dynamic.rs"]
+pub mod dynamic;
+#[path = "../../../../src-tauri/src/plugins/host.rs"]
+pub mod host;
+#[path = "../../../../src-tauri/src/plugins/manifest.rs"]
+pub mod manifest;
+#[path = "../../../../src-tauri/src/plugins/policy.rs"]
+pub mod policy;
+#[path = "../../../../src-tauri/src/plugins/routing.rs"]
+pub mod routing;
+#[path = "../../../../src-tauri/src/plugins/schema.rs"]
+pub mod schema;
+#[path = "../../../../src-tauri/src/plugins/types.rs"]
+pub mod types;
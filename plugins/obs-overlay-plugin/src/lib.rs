@@ -0,0 +1,511 @@
+//! OBS Overlay Plugin - показывает озвучиваемый текст на стриме
+//!
+//! Текущее сообщение (и, если включено, несколько предыдущих строк) пишется
+//! в файл для текстового источника OBS ("Читать из файла") и/или отдаётся
+//! локальной страницей для источника "Браузер": `http://127.0.0.1:<порт>/`.
+//! Текст появляется по событию `tts:started` и стирается через `clear_after_secs`
+//! после `tts:completed`, если за это время не началось новое сообщение.
+//!
+//! HTTP-сервер и таймер очистки работают в фоновом потоке, каждый запрос
+//! обслуживается в своём коротком потоке, чтобы медленный клиент не задерживал очистку.
+
+use plugins_api::sdk::{Event, Plugin, StatusError};
+use plugins_api::{capabilities, export_plugin, HostServices, PluginStatus};
+use serde::Deserialize;
+use std::collections::VecDeque;
+use std::io::{BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+/// Как часто поток проверяет подключения и таймер очистки
+const TICK: Duration = Duration::from_millis(50);
+
+/// Сколько ждать запрос от подключившегося клиента
+const READ_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Страница для источника "Браузер": опрашивает `/json` и рисует строки
+const PAGE: &str = r#"<!DOCTYPE html>
+<html>
+<head>
+<meta charset="utf-8">
+<title>app-tts overlay</title>
+<style>
+  body { margin: 0; background: transparent; color: #fff; font: 48px sans-serif;
+         text-shadow: 0 0 6px #000, 0 0 2px #000; white-space: pre-wrap; }
+  .history { opacity: 0.6; font-size: 0.7em; }
+</style>
+</head>
+<body>
+<div id="lines"></div>
+<script>
+const lines = document.getElementById("lines");
+async function update() {
+  try {
+    const state = await (await fetch("/json", { cache: "no-store" })).json();
+    lines.replaceChildren(
+      ...state.history.map(text => line(text, "history")),
+      ...(state.current === null ? [] : [line(state.current, "current")])
+    );
+  } catch (e) {}
+  setTimeout(update, 300);
+}
+function line(text, kind) {
+  const div = document.createElement("div");
+  div.className = kind;
+  div.textContent = text;
+  return div;
+}
+update();
+</script>
+</body>
+</html>
+"#;
+
+fn default_port() -> u16 {
+    8765
+}
+
+fn default_serve_http() -> bool {
+    true
+}
+
+fn default_clear_after() -> u64 {
+    3
+}
+
+/// Конфигурация плагина
+#[derive(Deserialize)]
+struct OverlayConfig {
+    /// Файл для текстового источника OBS (пусто - не писать)
+    #[serde(default)]
+    file_path: String,
+    #[serde(default = "default_serve_http")]
+    serve_http: bool,
+    #[serde(default = "default_port")]
+    port: u16,
+    /// Через сколько секунд после `tts:completed` стирать текст (0 - не стирать)
+    #[serde(default = "default_clear_after")]
+    clear_after_secs: u64,
+    /// Сколько предыдущих строк показывать над текущей
+    #[serde(default)]
+    history_lines: usize,
+}
+
+/// Что сейчас показано
+#[derive(Default)]
+struct Overlay {
+    /// Сообщение, которое озвучивается или только что озвучено
+    current: Option<(String, String)>,
+    /// Предыдущие строки, старые первыми
+    history: VecDeque<String>,
+    history_lines: usize,
+    /// None - текст не стирается
+    clear_after: Option<Duration>,
+    clear_at: Option<Instant>,
+    file_path: Option<PathBuf>,
+    /// Последняя ошибка записи файла
+    file_error: Option<String>,
+}
+
+impl Overlay {
+    fn started(&mut self, id: &str, text: &str) {
+        // Предыдущее сообщение уходит в историю
+        if let Some((_, previous)) = self.current.take() {
+            self.push_history(previous);
+        }
+        self.current = Some((id.to_string(), text.to_string()));
+        self.clear_at = None;
+        self.write_file();
+    }
+
+    fn completed(&mut self, id: &str) {
+        let is_current = matches!(self.current, Some((ref current, _)) if current == id);
+        if is_current {
+            self.clear_at = self.clear_after.map(|delay| Instant::now() + delay);
+        }
+    }
+
+    /// Сообщение не было озвучено до конца - убираем его сразу
+    fn dropped(&mut self, id: &str) {
+        let is_current = matches!(self.current, Some((ref current, _)) if current == id);
+        if is_current {
+            self.current = None;
+            self.clear_at = None;
+            self.write_file();
+        }
+    }
+
+    /// Стереть текст, если пора (вместе с историей: экран пустой, пока все молчат)
+    fn tick(&mut self, now: Instant) {
+        match self.clear_at {
+            Some(clear_at) if now >= clear_at => {}
+            _ => return,
+        }
+        self.current = None;
+        self.history.clear();
+        self.clear_at = None;
+        self.write_file();
+    }
+
+    fn push_history(&mut self, text: String) {
+        if self.history_lines == 0 {
+            return;
+        }
+        self.history.push_back(text);
+        while self.history.len() > self.history_lines {
+            self.history.pop_front();
+        }
+    }
+
+    /// Все строки для вывода, текущая последней
+    fn text(&self) -> String {
+        let mut lines: Vec<&str> = self.history.iter().map(String::as_str).collect();
+        if let Some((_, ref current)) = self.current {
+            lines.push(current);
+        }
+        lines.join("\n")
+    }
+
+    fn to_json(&self) -> serde_json::Value {
+        serde_json::json!({
+            "current": self.current.as_ref().map(|(_, text)| text),
+            "history": self.history,
+        })
+    }
+
+    fn write_file(&mut self) {
+        let path = match self.file_path {
+            Some(ref path) => path.clone(),
+            None => return,
+        };
+        self.file_error = match write_atomic(&path, &self.text()) {
+            Ok(()) => None,
+            Err(e) => {
+                eprintln!("[ObsOverlay] {}", e);
+                Some(e)
+            }
+        };
+    }
+}
+
+/// Записать файл целиком через временный, чтобы OBS не прочитал его наполовину
+fn write_atomic(path: &Path, text: &str) -> Result<(), String> {
+    let temp = path.with_extension("tmp");
+    std::fs::write(&temp, text).map_err(|e| format!("Failed to write {}: {}", temp.display(), e))?;
+    std::fs::rename(&temp, path).map_err(|e| format!("Failed to replace {}: {}", path.display(), e))
+}
+
+fn lock(overlay: &Mutex<Overlay>) -> MutexGuard<'_, Overlay> {
+    overlay.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+/// Фоновый поток: принимает подключения и ведёт таймер очистки
+struct Worker {
+    stopping: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl Worker {
+    fn start(listener: Option<TcpListener>, overlay: Arc<Mutex<Overlay>>) -> Result<Self, String> {
+        let stopping = Arc::new(AtomicBool::new(false));
+        let flag = Arc::clone(&stopping);
+        let thread = std::thread::Builder::new()
+            .name("obs-overlay".to_string())
+            .spawn(move || run(listener, overlay, flag))
+            .map_err(|e| format!("Failed to start overlay thread: {}", e))?;
+        Ok(Worker {
+            stopping,
+            thread: Some(thread),
+        })
+    }
+}
+
+impl Drop for Worker {
+    fn drop(&mut self) {
+        self.stopping.store(true, Ordering::Release);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+fn run(listener: Option<TcpListener>, overlay: Arc<Mutex<Overlay>>, stopping: Arc<AtomicBool>) {
+    // Потоки запросов, которые ещё могут работать (их дожидаются перед выходом)
+    let mut requests: Vec<JoinHandle<()>> = Vec::new();
+    while !stopping.load(Ordering::Acquire) {
+        if let Some(ref listener) = listener {
+            // Неблокирующий accept: забираем всех, кто успел подключиться
+            while let Ok((stream, _)) = listener.accept() {
+                let overlay = Arc::clone(&overlay);
+                let spawned = std::thread::Builder::new()
+                    .name("obs-overlay-request".to_string())
+                    .spawn(move || {
+                        if let Err(e) = respond(stream, &overlay) {
+                            eprintln!("[ObsOverlay] request failed: {}", e);
+                        }
+                    });
+                match spawned {
+                    Ok(thread) => requests.push(thread),
+                    Err(e) => eprintln!("[ObsOverlay] Failed to start request thread: {}", e),
+                }
+            }
+        }
+        requests.retain(|thread| !thread.is_finished());
+        lock(&overlay).tick(Instant::now());
+        std::thread::sleep(TICK);
+    }
+    for thread in requests {
+        let _ = thread.join();
+    }
+}
+
+/// Ответить на один HTTP-запрос и закрыть соединение
+fn respond(stream: TcpStream, overlay: &Mutex<Overlay>) -> std::io::Result<()> {
+    stream.set_nonblocking(false)?;
+    stream.set_read_timeout(Some(READ_TIMEOUT))?;
+    let mut reader = BufReader::new(stream);
+
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line)?;
+    // Заголовки не нужны, но их надо дочитать до пустой строки
+    loop {
+        let mut header = String::new();
+        if reader.read_line(&mut header)? == 0 || header.trim().is_empty() {
+            break;
+        }
+    }
+
+    let mut parts = request_line.split_whitespace();
+    let method = parts.next().unwrap_or("");
+    let path = parts.next().unwrap_or("");
+    let path = path.split('?').next().unwrap_or("");
+
+    let (status, content_type, body) = match (method, path) {
+        ("GET", "/") => ("200 OK", "text/html; charset=utf-8", PAGE.to_string()),
+        ("GET", "/text") => ("200 OK", "text/plain; charset=utf-8", lock(overlay).text()),
+        ("GET", "/json") => ("200 OK", "application/json", lock(overlay).to_json().to_string()),
+        ("GET", _) => ("404 Not Found", "text/plain; charset=utf-8", "Not found".to_string()),
+        _ => ("405 Method Not Allowed", "text/plain; charset=utf-8", "Method not allowed".to_string()),
+    };
+
+    let mut stream = reader.into_inner();
+    write!(
+        stream,
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nCache-Control: no-store\r\nConnection: close\r\n\r\n{}",
+        status,
+        content_type,
+        body.len(),
+        body
+    )?;
+    stream.flush()
+}
+
+/// Состояние плагина
+struct ObsOverlay {
+    overlay: Arc<Mutex<Overlay>>,
+    worker: Option<Worker>,
+    /// Адрес страницы, если сервер запущен
+    url: Option<String>,
+    /// Базовая директория (для относительных путей)
+    base_dir: PathBuf,
+    configured: bool,
+}
+
+impl Plugin for ObsOverlay {
+    type Config = OverlayConfig;
+
+    const NAME: &'static str = "OBS Overlay";
+    const VERSION: &'static str = "1.0.0";
+    const CAPABILITIES: u64 = capabilities::CONFIG | capabilities::STATUS | capabilities::EVENTS;
+
+    fn new(_host: Option<HostServices>) -> Self {
+        // Относительные пути - от директории exe, как у File Logger
+        let base_dir = std::env::current_exe()
+            .ok()
+            .and_then(|p| p.parent().map(|p| p.to_path_buf()))
+            .unwrap_or_else(|| PathBuf::from("."));
+
+        ObsOverlay {
+            overlay: Arc::new(Mutex::new(Overlay::default())),
+            worker: None,
+            url: None,
+            base_dir,
+            configured: false,
+        }
+    }
+
+    /// JSON схема конфигурации
+    fn config_schema() -> serde_json::Value {
+        serde_json::json!({
+            "type": "object",
+            "properties": {
+                "file_path": {
+                    "type": "string",
+                    "title": "Text file",
+                    "description": "File for an OBS text source (relative to exe or absolute, empty: don't write)",
+                    "default": ""
+                },
+                "serve_http": {
+                    "type": "boolean",
+                    "title": "Serve page for a browser source",
+                    "default": default_serve_http()
+                },
+                "port": {
+                    "type": "integer",
+                    "title": "Port",
+                    "description": "The page is at http://127.0.0.1:<port>/",
+                    "default": default_port(),
+                    "minimum": 1,
+                    "maximum": 65535
+                },
+                "clear_after_secs": {
+                    "type": "integer",
+                    "title": "Clear after, s",
+                    "description": "Seconds after the message is spoken (0: keep the text)",
+                    "default": default_clear_after(),
+                    "minimum": 0,
+                    "maximum": 3600
+                },
+                "history_lines": {
+                    "type": "integer",
+                    "title": "History lines",
+                    "description": "Previous messages shown above the current one",
+                    "default": 0,
+                    "minimum": 0,
+                    "maximum": 20
+                }
+            }
+        })
+    }
+
+    fn configure(&mut self, config: OverlayConfig) -> Result<(), String> {
+        if config.file_path.trim().is_empty() && !config.serve_http {
+            return Err("Set a text file or enable the page".to_string());
+        }
+
+        let file_path = match config.file_path.trim() {
+            "" => None,
+            path if Path::new(path).is_absolute() => Some(PathBuf::from(path)),
+            path => Some(self.base_dir.join(path)),
+        };
+        if let Some(parent) = file_path.as_ref().and_then(|p| p.parent()) {
+            std::fs::create_dir_all(parent)
+                .map_err(|e| format!("Failed to create directory {}: {}", parent.display(), e))?;
+        }
+
+        // Старый сервер освобождает порт до того, как занять его снова
+        self.worker = None;
+        self.url = None;
+        let listener = if config.serve_http {
+            let address = format!("127.0.0.1:{}", config.port);
+            let listener = TcpListener::bind(&address).map_err(|e| format!("Failed to listen on {}: {}", address, e))?;
+            listener
+                .set_nonblocking(true)
+                .map_err(|e| format!("Failed to listen on {}: {}", address, e))?;
+            self.url = Some(format!("http://{}/", address));
+            Some(listener)
+        } else {
+            None
+        };
+
+        {
+            let mut overlay = lock(&self.overlay);
+            overlay.history_lines = config.history_lines;
+            while overlay.history.len() > overlay.history_lines {
+                overlay.history.pop_front();
+            }
+            overlay.clear_after = match config.clear_after_secs {
+                0 => None,
+                secs => Some(Duration::from_secs(secs)),
+            };
+            overlay.file_path = file_path;
+            overlay.write_file();
+        }
+
+        self.worker = Some(Worker::start(listener, Arc::clone(&self.overlay))?);
+        self.configured = true;
+        eprintln!("[ObsOverlay] config OK (page: {:?})", self.url);
+        Ok(())
+    }
+
+    fn status(&mut self) -> Result<PluginStatus, StatusError> {
+        if !self.configured {
+            return Ok(PluginStatus::NotConfigured);
+        }
+        match lock(&self.overlay).file_error {
+            Some(ref error) => Err(StatusError::new(PluginStatus::ConnectionFailed, error.clone())),
+            None => Ok(PluginStatus::Ok),
+        }
+    }
+
+    /// Подробности статуса: где смотреть текст
+    fn status_detail(&self) -> Option<String> {
+        if !self.configured {
+            return Some("Output is not set".to_string());
+        }
+        let overlay = lock(&self.overlay);
+        let mut outputs = Vec::new();
+        if let Some(ref url) = self.url {
+            outputs.push(format!("page {}", url));
+        }
+        if let Some(ref path) = overlay.file_path {
+            outputs.push(format!("file {}", path.display()));
+        }
+        Some(outputs.join(", "))
+    }
+
+    fn on_event(&mut self, event: Event<'_>) {
+        let mut overlay = lock(&self.overlay);
+        match event {
+            Event::TtsStarted { id, text } => overlay.started(id, text),
+            Event::TtsCompleted { id, .. } => overlay.completed(id),
+            Event::TtsFailed { id, .. } | Event::TtsCancelled { id, .. } => overlay.dropped(id),
+            Event::ProviderChanged { .. } | Event::BlockingChanged { .. } => {}
+        }
+    }
+}
+
+export_plugin!(ObsOverlay);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_overlay_history_and_clear() {
+        let mut overlay = Overlay {
+            history_lines: 2,
+            clear_after: Some(Duration::from_secs(3)),
+            ..Default::default()
+        };
+
+        overlay.started("1", "one");
+        overlay.completed("1");
+        overlay.started("2", "two");
+        overlay.started("3", "three");
+        overlay.started("4", "four");
+        assert_eq!(overlay.text(), "two\nthree\nfour");
+        assert_eq!(overlay.to_json(), serde_json::json!({ "current": "four", "history": ["two", "three"] }));
+
+        // A late completion of an older message doesn't start the timer
+        overlay.completed("3");
+        assert!(overlay.clear_at.is_none());
+
+        overlay.completed("4");
+        let now = Instant::now();
+        overlay.tick(now);
+        assert_eq!(overlay.text(), "two\nthree\nfour");
+        overlay.tick(now + Duration::from_secs(4));
+        assert_eq!(overlay.text(), "");
+
+        // Cancelled messages disappear at once, the history stays
+        overlay.started("5", "five");
+        overlay.started("6", "six");
+        overlay.dropped("6");
+        assert_eq!(overlay.text(), "five");
+    }
+}
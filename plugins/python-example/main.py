@@ -0,0 +1,97 @@
+"""Пример плагина-процесса на Python.
+
+Печатает полученный текст в stderr — он попадает в лог приложения.
+Протокол: JSON-RPC 2.0, по одному сообщению на строку в stdin/stdout
+(подробности в plugins/README.md).
+"""
+
+import json
+import sys
+
+CONFIG_SCHEMA = {
+    "type": "object",
+    "properties": {
+        "prefix": {
+            "type": "string",
+            "title": "Префикс",
+            "default": ">",
+        },
+    },
+}
+
+config = {}
+received = 0
+
+
+def initialize(params):
+    return {
+        "name": "Python Example",
+        "version": "1.0.0",
+        "capabilities": ["config", "status", "text"],
+        "config_schema": CONFIG_SCHEMA,
+    }
+
+
+def set_config(params):
+    global config
+    config = params.get("config") or {}
+    return None
+
+
+def check_status(params):
+    prefix = config.get("prefix", ">")
+    return {
+        "status": "ok",
+        "detail": f"Получено сообщений: {received}, префикс «{prefix}»",
+    }
+
+
+def on_text(params):
+    global received
+    received += 1
+    print(f"{config.get('prefix', '>')} {params['text']}", file=sys.stderr, flush=True)
+    return None
+
+
+METHODS = {
+    "initialize": initialize,
+    "set_config": set_config,
+    "check_status": check_status,
+    "on_text": on_text,
+}
+
+
+def main():
+    # Приложение пишет UTF-8 независимо от кодовой страницы Windows
+    sys.stdin.reconfigure(encoding="utf-8")
+    sys.stderr.reconfigure(encoding="utf-8")
+
+    for line in sys.stdin:
+        if not line.strip():
+            continue
+        request = json.loads(line)
+        method = request.get("method")
+
+        if method == "shutdown":
+            break
+        # Уведомления (без id) не требуют ответа
+        if "id" not in request:
+            continue
+
+        handler = METHODS.get(method)
+        if handler is None:
+            reply = {"error": {"code": -32601, "message": f"Unknown method: {method}"}}
+        else:
+            try:
+                reply = {"result": handler(request.get("params") or {})}
+            except Exception as e:
+                reply = {"error": {"code": -32000, "message": str(e)}}
+
+        reply["jsonrpc"] = "2.0"
+        reply["id"] = request["id"]
+        sys.stdout.write(json.dumps(reply) + "\n")
+        sys.stdout.flush()
+
+
+if __name__ == "__main__":
+    main()
@@ -0,0 +1,477 @@
+//! Webhook Plugin - отправляет озвученный текст POST-запросом
+//!
+//! Каждое сообщение уходит JSON-телом на настроенный URL: тело собирается из
+//! шаблона, к запросу добавляются свои заголовки и (если задан) Bearer-токен.
+//! Отправка идёт в отдельном потоке с повторами, чтобы медленный сервер не
+//! задерживал очередь плагина.
+//!
+//! Статус берётся из последней отправки или проверки (HEAD-запрос к URL), пока
+//! он свежее `HEALTH_TTL`. Пока эндпоинт недоступен, `on_text` возвращает ошибку
+//! вместо постановки в очередь, и её учитывает политика сбоев приложения.
+
+use plugins_api::sdk::{Plugin, StatusError};
+use plugins_api::{capabilities, export_plugin, HostServices, PluginStatus};
+use serde::Deserialize;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, SyncSender, TrySendError};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+/// Шаблон тела по умолчанию
+const DEFAULT_TEMPLATE: &str = r#"{"text": "{{text}}"}"#;
+
+/// Сколько сообщений может ждать отправки
+const QUEUE_SIZE: usize = 64;
+
+/// Проверка статуса должна уложиться в таймаут вызова приложения (3 с)
+const MAX_PROBE_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Пауза перед первым повтором
+const RETRY_DELAY: Duration = Duration::from_millis(200);
+
+/// Сколько верить итогу отправки или проверки, прежде чем проверить снова
+const HEALTH_TTL: Duration = Duration::from_secs(10);
+
+/// Конфигурация плагина
+#[derive(Deserialize)]
+struct WebhookConfig {
+    #[serde(default)]
+    url: Option<String>,
+    #[serde(default = "default_template")]
+    body_template: String,
+    /// Доп. заголовки: `Имя: значение`, разделённые `;`
+    #[serde(default)]
+    headers: String,
+    #[serde(default)]
+    bearer_token: String,
+    #[serde(default = "default_timeout")]
+    timeout_secs: u64,
+    #[serde(default = "default_retries")]
+    retries: u32,
+}
+
+fn default_template() -> String {
+    DEFAULT_TEMPLATE.to_string()
+}
+
+fn default_timeout() -> u64 {
+    5
+}
+
+fn default_retries() -> u32 {
+    2
+}
+
+/// Всё, что нужно для одного запроса
+#[derive(Clone)]
+struct Endpoint {
+    url: String,
+    template: String,
+    headers: Vec<(String, String)>,
+    timeout: Duration,
+    retries: u32,
+}
+
+impl Endpoint {
+    fn from_config(config: WebhookConfig) -> Result<Self, String> {
+        let url = match config.url {
+            Some(url) if !url.trim().is_empty() => url.trim().to_string(),
+            _ => return Err("url is required".to_string()),
+        };
+        if !url.starts_with("http://") && !url.starts_with("https://") {
+            return Err(format!("url must start with http:// or https://: {}", url));
+        }
+
+        let mut headers = parse_headers(&config.headers)?;
+        if !config.bearer_token.is_empty() {
+            headers.push(("Authorization".to_string(), format!("Bearer {}", config.bearer_token)));
+        }
+
+        // Шаблон должен давать корректный JSON для любого текста
+        let sample = render_template(&config.body_template, "\"Пример\"\n\\");
+        if let Err(e) = serde_json::from_str::<serde_json::Value>(&sample) {
+            return Err(format!("body_template is not valid JSON: {}", e));
+        }
+
+        Ok(Endpoint {
+            url,
+            template: config.body_template,
+            headers,
+            timeout: Duration::from_secs(config.timeout_secs.max(1)),
+            retries: config.retries,
+        })
+    }
+
+    fn agent(&self, timeout: Duration) -> ureq::Agent {
+        ureq::Agent::config_builder()
+            .timeout_global(Some(timeout))
+            .http_status_as_error(false)
+            .build()
+            .into()
+    }
+
+    /// Одна попытка отправки: Err(ошибка, стоит ли повторять)
+    fn post(&self, agent: &ureq::Agent, text: &str) -> Result<(), (StatusError, bool)> {
+        let mut request = agent.post(&self.url).header("Content-Type", "application/json");
+        for (name, value) in &self.headers {
+            request = request.header(name.as_str(), value.as_str());
+        }
+
+        match request.send(render_template(&self.template, text)) {
+            Ok(response) => {
+                let status = response.status().as_u16();
+                let error = |status_kind| StatusError::new(status_kind, format!("HTTP {}", status));
+                match status {
+                    200..=299 => Ok(()),
+                    401 | 403 => Err((error(PluginStatus::AuthFailed), false)),
+                    // Сервер перегружен или временно недоступен — можно повторить
+                    429 | 500..=599 => Err((error(PluginStatus::ConnectionFailed), true)),
+                    _ => Err((error(PluginStatus::ConnectionFailed), false)),
+                }
+            }
+            Err(e) => Err((StatusError::new(PluginStatus::ConnectionFailed, e.to_string()), true)),
+        }
+    }
+
+    /// Отправить с повторами
+    fn deliver(&self, text: &str, stopping: &AtomicBool) -> Result<(), StatusError> {
+        let agent = self.agent(self.timeout);
+        let mut attempt = 0;
+        loop {
+            match self.post(&agent, text) {
+                Ok(()) => return Ok(()),
+                Err((error, retry)) => {
+                    if !retry || attempt >= self.retries || stopping.load(Ordering::Acquire) {
+                        return Err(error);
+                    }
+                }
+            }
+            attempt += 1;
+            std::thread::sleep(RETRY_DELAY * attempt);
+        }
+    }
+
+    /// Проверка доступности: HEAD-запрос к URL
+    fn probe(&self) -> Result<PluginStatus, StatusError> {
+        let agent = self.agent(self.timeout.min(MAX_PROBE_TIMEOUT));
+        let mut request = agent.head(&self.url);
+        for (name, value) in &self.headers {
+            request = request.header(name.as_str(), value.as_str());
+        }
+
+        match request.call() {
+            Ok(response) => match response.status().as_u16() {
+                401 | 403 => Err(StatusError::new(
+                    PluginStatus::AuthFailed,
+                    format!("{} rejected the credentials (HTTP {})", self.url, response.status().as_u16()),
+                )),
+                404 => Err(StatusError::new(PluginStatus::ConnectionFailed, format!("{} not found (HTTP 404)", self.url))),
+                status @ 500..=599 => Err(StatusError::new(
+                    PluginStatus::ConnectionFailed,
+                    format!("{} answered HTTP {}", self.url, status),
+                )),
+                // Остальное (в т.ч. 405 на HEAD) значит, что сервер отвечает
+                _ => Ok(PluginStatus::Ok),
+            },
+            Err(e) => Err(StatusError::new(
+                PluginStatus::ConnectionFailed,
+                format!("{} is unreachable: {}", self.url, e),
+            )),
+        }
+    }
+}
+
+/// Подставить текст и время в шаблон
+///
+/// `{{text}}` заменяется текстом, экранированным для JSON-строки (без кавычек),
+/// `{{timestamp}}` — временем в секундах Unix.
+fn render_template(template: &str, text: &str) -> String {
+    let escaped = serde_json::to_string(text).unwrap_or_default();
+    let escaped = &escaped[1..escaped.len() - 1];
+    let timestamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+    template
+        .replace("{{text}}", escaped)
+        .replace("{{timestamp}}", &timestamp.to_string())
+}
+
+/// `Имя: значение; Имя2: значение2` -> пары
+fn parse_headers(headers: &str) -> Result<Vec<(String, String)>, String> {
+    headers
+        .split(';')
+        .map(str::trim)
+        .filter(|h| !h.is_empty())
+        .map(|header| match header.split_once(':') {
+            Some((name, value)) if !name.trim().is_empty() => Ok((name.trim().to_string(), value.trim().to_string())),
+            _ => Err(format!("Invalid header '{}', expected 'Name: value'", header)),
+        })
+        .collect()
+}
+
+/// Итоги отправки для подробностей статуса
+#[derive(Default)]
+struct Delivery {
+    sent: u64,
+    failed: u64,
+    last_error: Option<String>,
+    /// Статус по последней отправке или проверке и когда он получен
+    health: Option<(Instant, Result<PluginStatus, StatusError>)>,
+}
+
+impl Delivery {
+    /// Статус, если он свежее `HEALTH_TTL`
+    fn fresh_health(&self, now: Instant) -> Option<Result<PluginStatus, StatusError>> {
+        match self.health {
+            Some((at, ref health)) if now.saturating_duration_since(at) < HEALTH_TTL => Some(health.clone()),
+            _ => None,
+        }
+    }
+}
+
+fn lock(delivery: &Mutex<Delivery>) -> MutexGuard<'_, Delivery> {
+    delivery.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+/// Поток отправки одного эндпоинта
+struct Sender {
+    queue: Option<SyncSender<String>>,
+    stopping: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl Sender {
+    fn start(endpoint: Endpoint, delivery: Arc<Mutex<Delivery>>) -> Result<Self, String> {
+        let (queue, messages) = mpsc::sync_channel(QUEUE_SIZE);
+        let stopping = Arc::new(AtomicBool::new(false));
+        let flag = Arc::clone(&stopping);
+        let thread = std::thread::Builder::new()
+            .name("webhook".to_string())
+            .spawn(move || send_loop(endpoint, messages, delivery, flag))
+            .map_err(|e| format!("Failed to start sender thread: {}", e))?;
+        Ok(Sender {
+            queue: Some(queue),
+            stopping,
+            thread: Some(thread),
+        })
+    }
+
+    fn send(&self, text: &str) -> Result<(), String> {
+        let queue = match self.queue {
+            Some(ref queue) => queue,
+            None => return Err("Sender is stopped".to_string()),
+        };
+        match queue.try_send(text.to_string()) {
+            Ok(()) => Ok(()),
+            Err(TrySendError::Full(_)) => Err("Webhook queue is full, message dropped".to_string()),
+            Err(TrySendError::Disconnected(_)) => Err("Sender thread has stopped".to_string()),
+        }
+    }
+}
+
+impl Drop for Sender {
+    fn drop(&mut self) {
+        self.stopping.store(true, Ordering::Release);
+        self.queue = None;
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+fn send_loop(endpoint: Endpoint, messages: Receiver<String>, delivery: Arc<Mutex<Delivery>>, stopping: Arc<AtomicBool>) {
+    for text in messages {
+        if stopping.load(Ordering::Acquire) {
+            break;
+        }
+        let result = endpoint.deliver(&text, &stopping);
+        let mut delivery = lock(&delivery);
+        match result {
+            Ok(()) => {
+                delivery.sent += 1;
+                delivery.last_error = None;
+                delivery.health = Some((Instant::now(), Ok(PluginStatus::Ok)));
+            }
+            Err(e) => {
+                eprintln!("[Webhook] delivery failed: {}", e.message);
+                delivery.failed += 1;
+                delivery.last_error = Some(e.message.clone());
+                delivery.health = Some((Instant::now(), Err(e)));
+            }
+        }
+    }
+}
+
+/// Состояние плагина
+struct Webhook {
+    endpoint: Option<Endpoint>,
+    sender: Option<Sender>,
+    delivery: Arc<Mutex<Delivery>>,
+}
+
+impl Plugin for Webhook {
+    type Config = WebhookConfig;
+
+    const NAME: &'static str = "Webhook";
+    const VERSION: &'static str = "1.0.0";
+    const CAPABILITIES: u64 = capabilities::CONFIG | capabilities::STATUS | capabilities::TEXT;
+
+    fn new(_host: Option<HostServices>) -> Self {
+        Webhook {
+            endpoint: None,
+            sender: None,
+            delivery: Arc::new(Mutex::new(Delivery::default())),
+        }
+    }
+
+    /// JSON схема конфигурации
+    fn config_schema() -> serde_json::Value {
+        serde_json::json!({
+            "type": "object",
+            "properties": {
+                "url": {
+                    "type": "string",
+                    "title": "URL",
+                    "description": "Where each spoken text is POSTed"
+                },
+                "body_template": {
+                    "type": "string",
+                    "title": "Body template",
+                    "description": "JSON body; {{text}} is the text escaped for a JSON string, {{timestamp}} is Unix time",
+                    "default": DEFAULT_TEMPLATE
+                },
+                "headers": {
+                    "type": "string",
+                    "title": "Headers",
+                    "description": "Extra headers as 'Name: value', separated by ';'",
+                    "default": ""
+                },
+                "bearer_token": {
+                    "type": "string",
+                    "title": "Bearer token",
+                    "format": "password",
+                    "default": ""
+                },
+                "timeout_secs": {
+                    "type": "integer",
+                    "title": "Timeout, s",
+                    "default": 5,
+                    "minimum": 1,
+                    "maximum": 30
+                },
+                "retries": {
+                    "type": "integer",
+                    "title": "Retries",
+                    "description": "Extra attempts on network errors, HTTP 429 and 5xx",
+                    "default": 2,
+                    "minimum": 0,
+                    "maximum": 5
+                }
+            },
+            "required": ["url"]
+        })
+    }
+
+    fn configure(&mut self, config: WebhookConfig) -> Result<(), String> {
+        let endpoint = Endpoint::from_config(config)?;
+        eprintln!("[Webhook] url: {}", endpoint.url);
+
+        // Старый поток доотправляет текущее сообщение и завершается
+        self.sender = None;
+        *lock(&self.delivery) = Delivery::default();
+        self.sender = Some(Sender::start(endpoint.clone(), Arc::clone(&self.delivery))?);
+        self.endpoint = Some(endpoint);
+        Ok(())
+    }
+
+    fn status(&mut self) -> Result<PluginStatus, StatusError> {
+        let endpoint = match self.endpoint {
+            Some(ref endpoint) => endpoint,
+            None => return Ok(PluginStatus::NotConfigured),
+        };
+        if let Some(health) = lock(&self.delivery).fresh_health(Instant::now()) {
+            return health;
+        }
+
+        let health = endpoint.probe();
+        lock(&self.delivery).health = Some((Instant::now(), health.clone()));
+        health
+    }
+
+    /// Подробности статуса: куда и сколько отправлено
+    fn status_detail(&self) -> Option<String> {
+        let endpoint = match self.endpoint {
+            Some(ref endpoint) => endpoint,
+            None => return Some("URL is not set".to_string()),
+        };
+        let delivery = lock(&self.delivery);
+        let mut detail = format!("{}: sent {}, failed {}", endpoint.url, delivery.sent, delivery.failed);
+        if let Some(ref error) = delivery.last_error {
+            detail.push_str(&format!(" (last error: {})", error));
+        }
+        Some(detail)
+    }
+
+    fn on_text(&mut self, text: &str) -> Result<(), String> {
+        let sender = match self.sender {
+            Some(ref sender) => sender,
+            None => return Err("Plugin not configured".to_string()),
+        };
+        // Сообщение теряется, а ошибка идёт в счёт сбоев; через HEALTH_TTL
+        // следующее сообщение снова пробует отправку
+        if let Some(Err(e)) = lock(&self.delivery).fresh_health(Instant::now()) {
+            return Err(format!("Webhook is failing: {}", e.message));
+        }
+        sender.send(text)
+    }
+}
+
+export_plugin!(Webhook);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render_template() {
+        let body = render_template(r#"{"content": "🔊 {{text}}"}"#, "Say \"hi\"\n");
+        let value: serde_json::Value = serde_json::from_str(&body).unwrap();
+        assert_eq!(value["content"], "🔊 Say \"hi\"\n");
+    }
+
+    #[test]
+    fn test_endpoint_from_config() {
+        let config = |json: serde_json::Value| serde_json::from_value::<WebhookConfig>(json).unwrap();
+
+        let endpoint = Endpoint::from_config(config(serde_json::json!({
+            "url": "https://example.com/hook",
+            "headers": "X-Source: app-tts; X-Empty:",
+            "bearer_token": "secret"
+        })))
+        .unwrap();
+        assert_eq!(endpoint.template, DEFAULT_TEMPLATE);
+        assert_eq!(
+            endpoint.headers,
+            vec![
+                ("X-Source".to_string(), "app-tts".to_string()),
+                ("X-Empty".to_string(), String::new()),
+                ("Authorization".to_string(), "Bearer secret".to_string()),
+            ]
+        );
+
+        let err = Endpoint::from_config(config(serde_json::json!({}))).err().unwrap();
+        assert_eq!(err, "url is required");
+        let err = Endpoint::from_config(config(serde_json::json!({ "url": "ftp://example.com" }))).err().unwrap();
+        assert!(err.starts_with("url must start with"));
+        let err = Endpoint::from_config(config(serde_json::json!({ "url": "http://x", "headers": "nocolon" })))
+            .err()
+            .unwrap();
+        assert!(err.starts_with("Invalid header"));
+        let err = Endpoint::from_config(config(serde_json::json!({ "url": "http://x", "body_template": "{{text}}" })))
+            .err()
+            .unwrap();
+        assert!(err.starts_with("body_template is not valid JSON"));
+    }
+}
@@ -0,0 +1,267 @@
+// src-tauri/src/audio_format.rs
+//! Audio container/codec detection and decoding into rodio sources
+
+use rodio::buffer::SamplesBuffer;
+use rodio::{Decoder, Source};
+use std::io::Cursor;
+use std::time::Duration;
+
+/// Opus always decodes at 48 kHz
+const OPUS_SAMPLE_RATE: u32 = 48000;
+/// Largest Opus frame (120 ms at 48 kHz) per channel
+const OPUS_MAX_FRAME: usize = 5760;
+
+/// Format of synthesized audio data
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AudioFormat {
+    Mp3,
+    /// Opus in an Ogg container
+    Opus,
+    /// AAC in an ADTS stream
+    Aac,
+    Flac,
+    Wav,
+    /// Ogg container, Vorbis or Opus (detected from the stream header)
+    Ogg,
+    /// Raw signed 16-bit little-endian PCM without a header
+    Pcm { sample_rate: u32, channels: u16 },
+}
+
+impl AudioFormat {
+    /// Detect format from an HTTP Content-Type header (parameters like `; codecs=` are ignored)
+    pub fn from_content_type(content_type: &str) -> Option<Self> {
+        let mime = content_type
+            .split(';')
+            .next()
+            .unwrap_or("")
+            .trim()
+            .to_ascii_lowercase();
+
+        match mime.as_str() {
+            "audio/mpeg" | "audio/mp3" | "audio/mpeg3" => Some(AudioFormat::Mp3),
+            "audio/wav" | "audio/wave" | "audio/x-wav" | "audio/vnd.wave" => Some(AudioFormat::Wav),
+            "audio/ogg" | "application/ogg" | "audio/vorbis" => Some(AudioFormat::Ogg),
+            "audio/opus" => Some(AudioFormat::Opus),
+            "audio/flac" | "audio/x-flac" => Some(AudioFormat::Flac),
+            "audio/aac" | "audio/aacp" | "audio/x-aac" => Some(AudioFormat::Aac),
+            _ => None,
+        }
+    }
+
+    /// Detect format from the first bytes of the data (None if not recognized)
+    pub fn detect(audio_data: &[u8]) -> Option<Self> {
+        match audio_data {
+            [b'R', b'I', b'F', b'F', _, _, _, _, b'W', b'A', b'V', b'E', ..] => Some(AudioFormat::Wav),
+            [b'O', b'g', b'g', b'S', ..] => Some(AudioFormat::Ogg),
+            [b'f', b'L', b'a', b'C', ..] => Some(AudioFormat::Flac),
+            [b'I', b'D', b'3', ..] => Some(AudioFormat::Mp3),
+            // ADTS sync word with layer 0, then MPEG audio frame sync
+            [0xff, second, ..] if second & 0xf6 == 0xf0 => Some(AudioFormat::Aac),
+            [0xff, second, ..] if second & 0xe0 == 0xe0 => Some(AudioFormat::Mp3),
+            _ => None,
+        }
+    }
+
+    /// Short format name ("mp3", "pcm", ...)
+    pub fn name(&self) -> &'static str {
+        match self {
+            AudioFormat::Mp3 => "mp3",
+            AudioFormat::Opus => "opus",
+            AudioFormat::Aac => "aac",
+            AudioFormat::Flac => "flac",
+            AudioFormat::Wav => "wav",
+            AudioFormat::Ogg => "ogg",
+            AudioFormat::Pcm { .. } => "pcm",
+        }
+    }
+}
+
+/// Decoded audio ready to be appended to a sink
+pub type DecodedSource = Box<dyn Source<Item = f32> + Send>;
+
+/// Decode audio data of the given format into a playable source
+pub fn decode(audio_data: Vec<u8>, format: AudioFormat) -> Result<DecodedSource, String> {
+    match format {
+        AudioFormat::Mp3 => decoded(Decoder::new_mp3(Cursor::new(audio_data))),
+        AudioFormat::Aac => decoded(Decoder::new_aac(Cursor::new(audio_data))),
+        AudioFormat::Flac => decoded(Decoder::new_flac(Cursor::new(audio_data))),
+        AudioFormat::Wav => decoded(Decoder::new_wav(Cursor::new(audio_data))),
+        AudioFormat::Opus => decode_ogg_opus(&audio_data),
+        AudioFormat::Ogg => {
+            if is_ogg_opus(&audio_data) {
+                decode_ogg_opus(&audio_data)
+            } else {
+                decoded(Decoder::new_vorbis(Cursor::new(audio_data)))
+            }
+        }
+        AudioFormat::Pcm { sample_rate, channels } => decode_pcm(&audio_data, sample_rate, channels),
+    }
+}
+
+/// Playback duration of encoded audio (decodes the data unless it is raw PCM)
+pub fn duration(audio_data: &[u8], format: AudioFormat) -> Option<Duration> {
+    let (samples, sample_rate, channels) = match format {
+        AudioFormat::Pcm { sample_rate, channels } => (audio_data.len() / 2, sample_rate, channels),
+        _ => {
+            let source = decode(audio_data.to_vec(), format).ok()?;
+            let sample_rate = source.sample_rate();
+            let channels = source.channels();
+            (source.count(), sample_rate, channels)
+        }
+    };
+
+    if sample_rate == 0 || channels == 0 {
+        return None;
+    }
+    let frames = (samples / channels as usize) as u64;
+    Some(Duration::from_micros(frames * 1_000_000 / sample_rate as u64))
+}
+
+fn decoded(decoder: Result<Decoder<Cursor<Vec<u8>>>, rodio::decoder::DecoderError>) -> Result<DecodedSource, String> {
+    decoder
+        .map(|d| Box::new(d.convert_samples::<f32>()) as DecodedSource)
+        .map_err(|e| e.to_string())
+}
+
+/// Convert raw 16-bit little-endian PCM into a source
+fn decode_pcm(audio_data: &[u8], sample_rate: u32, channels: u16) -> Result<DecodedSource, String> {
+    if sample_rate == 0 || channels == 0 {
+        return Err(format!("Invalid PCM parameters: {} Hz, {} channels", sample_rate, channels));
+    }
+
+    let samples = pcm_samples(audio_data);
+    Ok(Box::new(SamplesBuffer::new(channels, sample_rate, samples).convert_samples::<f32>()))
+}
+
+/// Split bytes into i16 samples (a trailing odd byte is dropped)
+fn pcm_samples(audio_data: &[u8]) -> Vec<i16> {
+    audio_data
+        .chunks_exact(2)
+        .map(|b| i16::from_le_bytes([b[0], b[1]]))
+        .collect()
+}
+
+/// Check whether an Ogg stream carries Opus (first packet is `OpusHead`)
+fn is_ogg_opus(audio_data: &[u8]) -> bool {
+    let mut reader = ogg::PacketReader::new(Cursor::new(audio_data));
+    matches!(reader.read_packet(), Ok(Some(packet)) if packet.data.starts_with(b"OpusHead"))
+}
+
+/// Decode an Ogg Opus stream into 48 kHz PCM
+fn decode_ogg_opus(audio_data: &[u8]) -> Result<DecodedSource, String> {
+    use audiopus::coder::Decoder as OpusDecoder;
+    use audiopus::packet::Packet;
+    use audiopus::{Channels, MutSignals, SampleRate};
+
+    let mut reader = ogg::PacketReader::new(Cursor::new(audio_data));
+
+    // Identification header: "OpusHead", version, channel count, pre-skip
+    let head = reader
+        .read_packet()
+        .map_err(|e| format!("Failed to read Ogg stream: {}", e))?
+        .ok_or_else(|| "Empty Ogg stream".to_string())?;
+    if head.data.len() < 19 || !head.data.starts_with(b"OpusHead") {
+        return Err("Ogg stream is not Opus".to_string());
+    }
+    let channel_count = head.data[9];
+    let pre_skip = u16::from_le_bytes([head.data[10], head.data[11]]) as usize;
+
+    let channels = match channel_count {
+        1 => Channels::Mono,
+        2 => Channels::Stereo,
+        n => return Err(format!("Unsupported Opus channel count: {}", n)),
+    };
+    let mut decoder = OpusDecoder::new(SampleRate::Hz48000, channels)
+        .map_err(|e| format!("Failed to create Opus decoder: {}", e))?;
+
+    let mut samples: Vec<i16> = Vec::new();
+    let mut frame = vec![0i16; OPUS_MAX_FRAME * channel_count as usize];
+
+    while let Some(packet) = reader
+        .read_packet()
+        .map_err(|e| format!("Failed to read Ogg stream: {}", e))?
+    {
+        // Skip the comment header
+        if packet.data.starts_with(b"OpusTags") {
+            continue;
+        }
+
+        let input = Packet::try_from(&packet.data[..])
+            .map_err(|e| format!("Invalid Opus packet: {}", e))?;
+        let output = MutSignals::try_from(&mut frame[..])
+            .map_err(|e| format!("Invalid Opus buffer: {}", e))?;
+        let decoded = decoder
+            .decode(Some(input), output, false)
+            .map_err(|e| format!("Failed to decode Opus packet: {}", e))?;
+
+        samples.extend_from_slice(&frame[..decoded * channel_count as usize]);
+    }
+
+    // Drop encoder priming samples
+    let skip = (pre_skip * channel_count as usize).min(samples.len());
+    samples.drain(..skip);
+
+    Ok(Box::new(
+        SamplesBuffer::new(channel_count as u16, OPUS_SAMPLE_RATE, samples).convert_samples::<f32>(),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_from_content_type() {
+        assert_eq!(AudioFormat::from_content_type("audio/mpeg"), Some(AudioFormat::Mp3));
+        assert_eq!(AudioFormat::from_content_type("audio/wav"), Some(AudioFormat::Wav));
+        assert_eq!(AudioFormat::from_content_type("audio/x-wav"), Some(AudioFormat::Wav));
+        assert_eq!(AudioFormat::from_content_type("Audio/OGG; codecs=opus"), Some(AudioFormat::Ogg));
+        assert_eq!(AudioFormat::from_content_type("application/json"), None);
+    }
+
+    #[test]
+    fn test_detect() {
+        assert_eq!(AudioFormat::detect(b"RIFF\x24\x00\x00\x00WAVEfmt "), Some(AudioFormat::Wav));
+        assert_eq!(AudioFormat::detect(b"OggS\x00\x02"), Some(AudioFormat::Ogg));
+        assert_eq!(AudioFormat::detect(b"fLaC\x00"), Some(AudioFormat::Flac));
+        assert_eq!(AudioFormat::detect(b"ID3\x04\x00"), Some(AudioFormat::Mp3));
+        assert_eq!(AudioFormat::detect(&[0xff, 0xfb, 0x90, 0x00]), Some(AudioFormat::Mp3));
+        assert_eq!(AudioFormat::detect(&[0xff, 0xf1, 0x50, 0x80]), Some(AudioFormat::Aac));
+        assert_eq!(AudioFormat::detect(b"{\"error\": 1}"), None);
+        assert_eq!(AudioFormat::detect(&[]), None);
+    }
+
+    #[test]
+    fn test_pcm_samples() {
+        assert_eq!(pcm_samples(&[0x01, 0x00, 0xff, 0xff, 0x7f]), vec![1, -1]);
+    }
+
+    #[test]
+    fn test_decode_pcm() {
+        let source = decode(vec![0x00, 0x40, 0x00, 0xc0], AudioFormat::Pcm { sample_rate: 24000, channels: 1 }).unwrap();
+        assert_eq!(source.sample_rate(), 24000);
+        assert_eq!(source.channels(), 1);
+        let samples: Vec<f32> = source.collect();
+        assert_eq!(samples, vec![0.5, -0.5]);
+    }
+
+    #[test]
+    fn test_pcm_duration() {
+        // 24000 Hz mono, 16-bit: 48000 bytes = 1 second
+        let format = AudioFormat::Pcm { sample_rate: 24000, channels: 1 };
+        assert_eq!(duration(&vec![0; 48000], format), Some(Duration::from_secs(1)));
+        assert_eq!(duration(&[0; 4], AudioFormat::Pcm { sample_rate: 0, channels: 1 }), None);
+        assert_eq!(duration(&[1, 2, 3, 4], AudioFormat::Mp3), None);
+    }
+
+    #[test]
+    fn test_decode_pcm_invalid_params() {
+        assert!(decode(vec![0, 0], AudioFormat::Pcm { sample_rate: 0, channels: 1 }).is_err());
+    }
+
+    #[test]
+    fn test_decode_garbage_fails() {
+        assert!(decode(vec![1, 2, 3, 4], AudioFormat::Wav).is_err());
+        assert!(decode(vec![1, 2, 3, 4], AudioFormat::Opus).is_err());
+    }
+}
@@ -3,12 +3,21 @@
 
 use cpal::traits::{DeviceTrait, HostTrait};
 use cpal::Device;
-use rodio::{Decoder, OutputStream, Sink, Source};
-use std::io::Cursor;
-use std::sync::atomic::{AtomicBool, Ordering};
-use std::sync::Arc;
+use crate::audio_format::{self, AudioFormat, DecodedSource};
+use rodio::{OutputStream, Sink, Source};
+use serde::Serialize;
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use std::sync::{Arc, Barrier};
 use std::thread;
 use std::sync::Mutex as StdMutex;
+use std::time::{Duration, Instant};
+
+/// How often playback threads poll the sink and report levels (~20 times per second)
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+/// How long the playback position may stand still before it counts as an underrun
+const STALL_THRESHOLD: Duration = Duration::from_millis(200);
+/// How long a device may take to start consuming audio before it's reported
+const START_TIMEOUT: Duration = Duration::from_millis(1000);
 
 /// Handle to control background playback
 #[derive(Clone)]
@@ -32,39 +41,314 @@ impl PlaybackHandle {
     }
 }
 
+/// Which output a playback thread is feeding
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OutputChannel {
+    Speaker,
+    VirtualMic,
+}
+
+/// Live volume levels shared between the TTS engine and playback threads
+///
+/// Values are stored as f32 bits in atomics so that sliders can change them
+/// while a message is playing; playback threads re-read them on every poll.
+#[derive(Debug, Clone)]
+pub struct VolumeControl {
+    master: Arc<AtomicU32>,
+    speaker: Arc<AtomicU32>,
+    virtual_mic: Arc<AtomicU32>,
+    speaker_muted: Arc<AtomicBool>,
+    virtual_mic_muted: Arc<AtomicBool>,
+}
+
+impl VolumeControl {
+    pub fn new() -> Self {
+        Self {
+            master: Arc::new(AtomicU32::new(1.0f32.to_bits())),
+            speaker: Arc::new(AtomicU32::new(1.0f32.to_bits())),
+            virtual_mic: Arc::new(AtomicU32::new(1.0f32.to_bits())),
+            speaker_muted: Arc::new(AtomicBool::new(false)),
+            virtual_mic_muted: Arc::new(AtomicBool::new(false)),
+        }
+    }
+
+    /// Set master volume multiplier (0.0 - 1.0)
+    pub fn set_master(&self, volume: f32) {
+        self.master.store(volume.clamp(0.0, 1.0).to_bits(), Ordering::Release);
+    }
+
+    /// Set volume of a single output (0.0 - 1.0)
+    pub fn set_volume(&self, channel: OutputChannel, volume: f32) {
+        let bits = volume.clamp(0.0, 1.0).to_bits();
+        match channel {
+            OutputChannel::Speaker => self.speaker.store(bits, Ordering::Release),
+            OutputChannel::VirtualMic => self.virtual_mic.store(bits, Ordering::Release),
+        }
+    }
+
+    /// Mute or unmute a single output
+    pub fn set_muted(&self, channel: OutputChannel, muted: bool) {
+        match channel {
+            OutputChannel::Speaker => self.speaker_muted.store(muted, Ordering::Release),
+            OutputChannel::VirtualMic => self.virtual_mic_muted.store(muted, Ordering::Release),
+        }
+    }
+
+    pub fn master(&self) -> f32 {
+        f32::from_bits(self.master.load(Ordering::Acquire))
+    }
+
+    pub fn volume(&self, channel: OutputChannel) -> f32 {
+        let bits = match channel {
+            OutputChannel::Speaker => self.speaker.load(Ordering::Acquire),
+            OutputChannel::VirtualMic => self.virtual_mic.load(Ordering::Acquire),
+        };
+        f32::from_bits(bits)
+    }
+
+    pub fn is_muted(&self, channel: OutputChannel) -> bool {
+        match channel {
+            OutputChannel::Speaker => self.speaker_muted.load(Ordering::Acquire),
+            OutputChannel::VirtualMic => self.virtual_mic_muted.load(Ordering::Acquire),
+        }
+    }
+
+    /// Volume that should be applied to the sink right now
+    pub fn effective(&self, channel: OutputChannel) -> f32 {
+        if self.is_muted(channel) {
+            0.0
+        } else {
+            self.master() * self.volume(channel)
+        }
+    }
+}
+
+impl Default for VolumeControl {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 /// Configuration for audio output to a specific device
 #[derive(Debug, Clone)]
 pub struct OutputConfig {
     pub device_id: Option<String>,
-    pub volume: f32,  // 0.0 - 1.0
+    pub channel: OutputChannel,
+    /// Silence inserted before audio on this output (ms), to line it up with other outputs
+    pub delay_ms: u32,
 }
 
-impl Default for OutputConfig {
-    fn default() -> Self {
+impl OutputConfig {
+    pub fn speaker(device_id: Option<String>) -> Self {
+        Self {
+            device_id,
+            channel: OutputChannel::Speaker,
+            delay_ms: 0,
+        }
+    }
+
+    pub fn virtual_mic(device_id: Option<String>) -> Self {
         Self {
-            device_id: None,
-            volume: 1.0,
+            device_id,
+            channel: OutputChannel::VirtualMic,
+            delay_ms: 0,
         }
     }
+
+    /// Set delay offset for this output
+    pub fn with_delay(mut self, delay_ms: u32) -> Self {
+        self.delay_ms = delay_ms;
+        self
+    }
+}
+
+/// Output whose device has been resolved and is ready for a playback thread
+struct ResolvedOutput {
+    device: Device,
+    config: OutputConfig,
+    reporter: OutputReporter,
 }
 
 /// Callback type for playback completion notification
 pub type PlaybackCompleteCallback = Arc<StdMutex<Box<dyn FnOnce() + Send>>>;
 
-/// Simple audio player for MP3 playback with dual output support
+/// Signal level and health of one output, reported periodically during playback
+#[derive(Debug, Clone, Serialize)]
+pub struct OutputLevels {
+    pub channel: OutputChannel,
+    pub device: String,
+    /// RMS level of the last interval after volume (0.0 - 1.0)
+    pub rms: f32,
+    /// Peak level of the last interval after volume (0.0 - 1.0)
+    pub peak: f32,
+    /// Number of underruns (device stopped pulling audio) since playback started
+    pub underruns: u32,
+}
+
+/// Stream problem on one output (device missing, stream failure, underrun)
+#[derive(Debug, Clone, Serialize)]
+pub struct OutputError {
+    pub channel: OutputChannel,
+    pub device: String,
+    pub error: String,
+}
+
+/// Events reported by playback threads
+#[derive(Debug, Clone)]
+pub enum AudioEvent {
+    Levels(OutputLevels),
+    Error(OutputError),
+}
+
+/// Callback type for level/error reporting (called from playback threads)
+pub type AudioEventCallback = Arc<dyn Fn(AudioEvent) + Send + Sync>;
+
+/// Sample statistics accumulated by `LevelMeter` between two polls
+#[derive(Debug, Default)]
+struct LevelWindow {
+    sum_squares: f64,
+    peak: f32,
+    samples: u64,
+}
+
+/// Number of samples the meter accumulates locally before publishing
+const METER_FLUSH_SAMPLES: u32 = 256;
+
+/// Source adapter that measures RMS/peak of the samples pulled by the output
+struct LevelMeter<S> {
+    inner: S,
+    window: Arc<StdMutex<LevelWindow>>,
+    sum_squares: f64,
+    peak: f32,
+    pending: u32,
+}
+
+impl<S> LevelMeter<S> {
+    fn new(inner: S, window: Arc<StdMutex<LevelWindow>>) -> Self {
+        Self {
+            inner,
+            window,
+            sum_squares: 0.0,
+            peak: 0.0,
+            pending: 0,
+        }
+    }
+
+    fn flush(&mut self) {
+        if self.pending == 0 {
+            return;
+        }
+        if let Ok(mut window) = self.window.lock() {
+            window.sum_squares += self.sum_squares;
+            window.peak = window.peak.max(self.peak);
+            window.samples += self.pending as u64;
+        }
+        self.sum_squares = 0.0;
+        self.peak = 0.0;
+        self.pending = 0;
+    }
+}
+
+impl<S: Source<Item = f32>> Iterator for LevelMeter<S> {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32> {
+        let sample = self.inner.next();
+        match sample {
+            Some(value) => {
+                self.sum_squares += (value as f64) * (value as f64);
+                self.peak = self.peak.max(value.abs());
+                self.pending += 1;
+                if self.pending >= METER_FLUSH_SAMPLES {
+                    self.flush();
+                }
+            }
+            None => self.flush(),
+        }
+        sample
+    }
+}
+
+impl<S: Source<Item = f32>> Source for LevelMeter<S> {
+    fn current_frame_len(&self) -> Option<usize> {
+        self.inner.current_frame_len()
+    }
+
+    fn channels(&self) -> u16 {
+        self.inner.channels()
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.inner.sample_rate()
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        self.inner.total_duration()
+    }
+}
+
+/// Sends level/error events for one output, if a callback is set
+#[derive(Clone)]
+struct OutputReporter {
+    channel: OutputChannel,
+    device: String,
+    callback: Option<AudioEventCallback>,
+}
+
+impl OutputReporter {
+    fn levels(&self, rms: f32, peak: f32, underruns: u32) {
+        if let Some(ref callback) = self.callback {
+            callback(AudioEvent::Levels(OutputLevels {
+                channel: self.channel,
+                device: self.device.clone(),
+                rms,
+                peak,
+                underruns,
+            }));
+        }
+    }
+
+    fn error(&self, error: String) {
+        eprintln!("[AudioPlayer] {:?} output '{}': {}", self.channel, self.device, error);
+        if let Some(ref callback) = self.callback {
+            callback(AudioEvent::Error(OutputError {
+                channel: self.channel,
+                device: self.device.clone(),
+                error,
+            }));
+        }
+    }
+}
+
+/// Simple audio player for synthesized audio playback with dual output support
 pub struct AudioPlayer {
     current_handle: Option<PlaybackHandle>,
     completion_callback: Option<PlaybackCompleteCallback>,
+    event_callback: Option<AudioEventCallback>,
+    volume: VolumeControl,
 }
 
 impl AudioPlayer {
     pub fn new() -> Self {
+        Self::with_volume_control(VolumeControl::new())
+    }
+
+    /// Create a player whose sinks follow the given live volume levels
+    pub fn with_volume_control(volume: VolumeControl) -> Self {
         Self {
             current_handle: None,
             completion_callback: None,
+            event_callback: None,
+            volume,
         }
     }
 
+    /// Set a callback receiving level meters and stream errors of all outputs
+    pub fn set_event_callback(&mut self, callback: AudioEventCallback) {
+        self.event_callback = Some(callback);
+    }
+
     /// Set a callback to be invoked when playback completes
     pub fn set_completion_callback(&mut self, callback: Box<dyn FnOnce() + Send>) {
         self.completion_callback = Some(Arc::new(StdMutex::new(callback)));
@@ -91,13 +375,13 @@ impl AudioPlayer {
     }
 
     /// Get device for playback, falling back to default if needed
-    fn get_device(device_id: &Option<String>) -> Result<Device, String> {
+    fn get_device(device_id: &Option<String>, reporter: &OutputReporter) -> Result<Device, String> {
         match device_id {
             Some(id) => {
                 if let Some(device) = Self::find_device_by_name(id) {
                     Ok(device)
                 } else {
-                    eprintln!("[AudioPlayer] Device '{}' not found, using default", id);
+                    reporter.error(format!("Device '{}' not found, using default", id));
                     let host = cpal::default_host();
                     host.default_output_device()
                         .ok_or_else(|| "No default output device".to_string())
@@ -111,57 +395,130 @@ impl AudioPlayer {
         }
     }
 
-    /// Play MP3 audio data to a single device asynchronously
+    /// Open stream and sink on a device and decode audio for it
+    fn open_output(
+        device: &Device,
+        audio_data: Vec<u8>,
+        format: AudioFormat,
+    ) -> Result<(OutputStream, Sink, DecodedSource), String> {
+        // Create stream and sink in this thread (they're not Send)
+        let (stream, stream_handle) = OutputStream::try_from_device(device)
+            .map_err(|e| format!("Failed to create output stream: {}", e))?;
+
+        let sink = Sink::try_new(&stream_handle)
+            .map_err(|e| format!("Failed to create sink: {}", e))?;
+
+        // Decode from memory
+        let source = audio_format::decode(audio_data, format)
+            .map_err(|e| format!("Failed to decode audio: {}", e))?;
+
+        Ok((stream, sink, source))
+    }
+
+    /// Play encoded audio data to a single device asynchronously
+    ///
+    /// The thread prepares its output, then waits on `start` so that all outputs
+    /// of one playback begin at the same moment.
     fn play_to_device(
-        device: Device,
+        output: ResolvedOutput,
         audio_data: Vec<u8>,
-        volume: f32,
+        format: AudioFormat,
+        volume: VolumeControl,
         handle: PlaybackHandle,
+        start: Arc<Barrier>,
     ) -> thread::JoinHandle<()> {
         thread::spawn(move || {
+            let ResolvedOutput { device, config, reporter } = output;
+            let channel = config.channel;
             let device_name = device.name().unwrap_or_default();
             eprintln!("[AudioPlayer] Playback thread starting for device: {}", device_name);
 
-            // Create stream and sink in this thread (they're not Send)
-            let (stream, stream_handle) = match OutputStream::try_from_device(&device) {
-                Ok(s) => s,
-                Err(e) => {
-                    eprintln!("[AudioPlayer] Failed to create output stream for '{}': {}", device_name, e);
-                    return;
-                }
-            };
-
-            let sink = match Sink::try_new(&stream_handle) {
-                Ok(s) => s,
-                Err(e) => {
-                    eprintln!("[AudioPlayer] Failed to create sink: {}", e);
-                    return;
-                }
-            };
+            let opened = Self::open_output(&device, audio_data, format);
 
-            // Decode MP3 from memory
-            let cursor = Cursor::new(audio_data);
+            // Every thread must reach the barrier, even if its output failed
+            start.wait();
 
-            // Rodio's Decoder auto-detects format, works with MP3
-            let source = match Decoder::new(cursor) {
-                Ok(s) => s,
+            let (stream, sink, source) = match opened {
+                Ok(o) => o,
                 Err(e) => {
-                    eprintln!("[AudioPlayer] Failed to decode audio: {}", e);
+                    reporter.error(e);
                     return;
                 }
             };
 
-            // Apply volume
-            let source = source.amplify(volume);
+            // Measure what the output actually pulls from the sink
+            let window = Arc::new(StdMutex::new(LevelWindow::default()));
+            let source = LevelMeter::new(source, Arc::clone(&window))
+                .delay(Duration::from_millis(config.delay_ms as u64));
+
+            // Volume is applied on the sink (not baked into the source),
+            // so it can follow slider and mute changes during playback
+            let mut applied_volume = volume.effective(channel);
+            sink.set_volume(applied_volume);
 
             // Append to sink and play
             sink.append(source);
 
+            let started_at = Instant::now();
+            let mut last_position = Duration::ZERO;
+            let mut last_progress = started_at;
+            let mut started = false;
+            let mut stalled = false;
+            let mut start_reported = false;
+            let mut underruns: u32 = 0;
+
             // Keep stream alive until playback finishes or stop is requested
             while !sink.empty() && !handle.should_stop() {
-                std::thread::sleep(std::time::Duration::from_millis(100));
+                std::thread::sleep(POLL_INTERVAL);
+
+                let current_volume = volume.effective(channel);
+                if current_volume != applied_volume {
+                    sink.set_volume(current_volume);
+                    applied_volume = current_volume;
+                }
+
+                // Report levels of the samples consumed since the last poll
+                let (rms, peak) = match window.lock() {
+                    Ok(mut w) => {
+                        let rms = if w.samples > 0 {
+                            (w.sum_squares / w.samples as f64).sqrt() as f32
+                        } else {
+                            0.0
+                        };
+                        let peak = w.peak;
+                        *w = LevelWindow::default();
+                        (rms, peak)
+                    }
+                    Err(_) => (0.0, 0.0),
+                };
+
+                // Detect a device that stopped (or never started) pulling audio
+                let position = sink.get_pos();
+                let now = Instant::now();
+                if position > last_position {
+                    last_position = position;
+                    last_progress = now;
+                    started = true;
+                    stalled = false;
+                } else if started && !stalled && now.duration_since(last_progress) >= STALL_THRESHOLD {
+                    stalled = true;
+                    underruns += 1;
+                    reporter.error(format!("Underrun: output stalled at {:.1}s", position.as_secs_f32()));
+                } else if !started && !start_reported && now.duration_since(started_at) >= START_TIMEOUT {
+                    start_reported = true;
+                    reporter.error("Device is not consuming audio".to_string());
+                }
+
+                reporter.levels(
+                    (rms * applied_volume).min(1.0),
+                    (peak * applied_volume).min(1.0),
+                    underruns,
+                );
             }
 
+            // Drop meters back to zero once this output is done
+            reporter.levels(0.0, 0.0, underruns);
+
             if handle.should_stop() {
                 eprintln!("[AudioPlayer] Playback stopped by request for device: {}", device_name);
             } else {
@@ -174,20 +531,23 @@ impl AudioPlayer {
         })
     }
 
-    /// Play MP3 audio data asynchronously to multiple outputs (speaker + virtual mic)
+    /// Play audio data asynchronously to multiple outputs (speaker + virtual mic)
     ///
     /// # Arguments
-    /// * `audio_data` - MP3 audio data bytes
+    /// * `audio_data` - Encoded audio data bytes
+    /// * `format` - Format of `audio_data`
     /// * `speaker_config` - Speaker output configuration (None = disabled)
     /// * `virtual_mic_config` - Virtual mic output configuration (None = disabled)
-    pub fn play_mp3_async_dual(
+    pub fn play_async_dual(
         &mut self,
         audio_data: Vec<u8>,
+        format: AudioFormat,
         speaker_config: Option<OutputConfig>,
         virtual_mic_config: Option<OutputConfig>,
     ) -> Result<(), String> {
-        eprintln!("[AudioPlayer] play_mp3_async_dual START, {} bytes, speaker={:?}, virtual_mic={:?}",
+        eprintln!("[AudioPlayer] play_async_dual START, {} bytes ({:?}), speaker={:?}, virtual_mic={:?}",
             audio_data.len(),
+            format,
             speaker_config.as_ref().map(|c| &c.device_id),
             virtual_mic_config.as_ref().map(|c| &c.device_id)
         );
@@ -209,19 +569,27 @@ impl AudioPlayer {
 
         let mut handles = vec![];
 
-        // Play to speaker if enabled
-        if let Some(config) = speaker_config {
-            let device = Self::get_device(&config.device_id)?;
-            eprintln!("[AudioPlayer] Starting speaker playback: '{}'", device.name().unwrap_or_default());
-            let audio_data_clone = audio_data.clone();
-            handles.push(Self::play_to_device(device, audio_data_clone, config.volume, handle.clone()));
+        // Resolve all devices first, so the start barrier knows how many outputs will play
+        let mut outputs = Vec::new();
+        for config in [speaker_config, virtual_mic_config].into_iter().flatten() {
+            let mut reporter = self.reporter(&config);
+            let device = Self::get_device(&config.device_id, &reporter)?;
+            reporter.device = device.name().unwrap_or_default();
+            outputs.push(ResolvedOutput { device, config, reporter });
         }
 
-        // Play to virtual mic if enabled
-        if let Some(config) = virtual_mic_config {
-            let device = Self::get_device(&config.device_id)?;
-            eprintln!("[AudioPlayer] Starting virtual mic playback: '{}'", device.name().unwrap_or_default());
-            handles.push(Self::play_to_device(device, audio_data, config.volume, handle.clone()));
+        let start = Arc::new(Barrier::new(outputs.len()));
+        for output in outputs {
+            eprintln!("[AudioPlayer] Starting {:?} playback: '{}' (delay {} ms)",
+                output.config.channel, output.reporter.device, output.config.delay_ms);
+            handles.push(Self::play_to_device(
+                output,
+                audio_data.clone(),
+                format,
+                self.volume.clone(),
+                handle.clone(),
+                Arc::clone(&start),
+            ));
         }
 
         // Spawn a thread to wait for all playback threads and call completion callback when done
@@ -242,10 +610,19 @@ impl AudioPlayer {
             }
         });
 
-        eprintln!("[AudioPlayer] play_mp3_async_dual END (background playback started)");
+        eprintln!("[AudioPlayer] play_async_dual END (background playback started)");
         Ok(())
     }
 
+    /// Build a level/error reporter for an output (device name is filled in once resolved)
+    fn reporter(&self, config: &OutputConfig) -> OutputReporter {
+        OutputReporter {
+            channel: config.channel,
+            device: config.device_id.clone().unwrap_or_else(|| "default".to_string()),
+            callback: self.event_callback.clone(),
+        }
+    }
+
     /// Stop playback
     pub fn stop(&mut self) {
         eprintln!("[AudioPlayer] Stopping playback");
@@ -1,8 +1,11 @@
-use crate::state::{AppState, HotkeyMode, InputLanguage, KeyEvent, TtsStatus, TtsMessage, TtsMessageStatus, Voice};
-use crate::openai::{OpenAIConfig, OpenAIVoice};
+use crate::state::{AppState, HotkeyMode, InputLanguage, KeyEvent, TtsProvider, TtsStatus, TtsMessage, TtsMessageStatus, Voice};
+use crate::openai::{OpenAIConfig, OpenAIResponseFormat, OpenAIVoice};
 use crate::localhost::{LocalhostConfig, LocalhostVoice};
-use crate::virtual_mic::{OutputDeviceInfo, VirtualDeviceInfo};
-use crate::plugins::{PluginInfo, SerializablePluginStatus};
+use crate::virtual_mic::{OutputDeviceInfo, VirtualDeviceInfo, MAX_OUTPUT_DELAY_MS};
+use crate::plugins::{
+    run_transforms, ConfigError, FailurePolicy, MessageSource, PluginEvent, PluginInfo, PluginVoice, RoutingRules,
+    SerializablePluginStatus,
+};
 use serde::{Deserialize, Serialize};
 use tauri::Emitter;
 
@@ -476,8 +479,6 @@ pub fn set_continuous_play(state: tauri::State<'_, AppState>, enabled: bool) ->
 /// Set the TTS provider
 #[tauri::command]
 pub fn set_tts_provider(state: tauri::State<'_, AppState>, provider: String) -> Result<(), String> {
-    use crate::state::TtsProvider;
-
     let provider_enum: TtsProvider = provider.clone().into();
 
     let lock_result = state.tts_engine.lock();
@@ -628,6 +629,12 @@ pub fn clear_tts_history(state: tauri::State<'_, AppState>) {
 /// Speak text with TTS and add to history (non-blocking - adds to queue)
 #[tauri::command]
 pub async fn enqueue_tts(state: tauri::State<'_, AppState>, app: tauri::AppHandle, text: String) -> Result<String, String> {
+    enqueue_text(&state, &app, text, MessageSource::Typed)
+}
+
+/// Run plugin transforms, add text to history and start queue processing
+/// Shared by the `enqueue_tts` command and plugins calling `enqueue_text`
+pub fn enqueue_text(state: &AppState, app: &tauri::AppHandle, text: String, source: MessageSource) -> Result<String, String> {
     let start = std::time::Instant::now();
     eprintln!("[enqueue_tts] START");
 
@@ -639,18 +646,38 @@ pub async fn enqueue_tts(state: tauri::State<'_, AppState>, app: tauri::AppHandl
     let app_clone = app.clone();
     eprintln!("[enqueue_tts] After app.clone: {:?}", start.elapsed());
 
-    // Broadcast to plugins first
-    if let Ok(mut plugin_manager) = state.plugin_manager.lock() {
-        if let Some(manager) = plugin_manager.as_mut() {
-            let changed = manager.broadcast_text(&text);
-            if changed {
-                // Emit plugins changed event if any plugin was disabled due to error
-                let plugins = manager.get_plugins();
+    // Run text transforms (each plugin call has a timeout); the chain runs without
+    // the manager lock, so slow plugins don't hold up other plugin commands
+    let mut text = text;
+    let chain = match state.plugin_manager.lock() {
+        Ok(plugin_manager) => plugin_manager.as_ref().map(|manager| manager.transform_workers()),
+        Err(_) => None,
+    };
+    if let Some(chain) = chain {
+        let report = run_transforms(&chain, &text);
+        if !report.steps.is_empty() {
+            let _ = app.emit("plugins:transformed", &report);
+        }
+        if report.steps.iter().any(|s| s.error.is_some()) {
+            // Save and show plugins the failure policy may have disabled
+            let plugins = match state.plugin_manager.lock() {
+                Ok(plugin_manager) => plugin_manager.as_ref().map(|manager| {
+                    let _ = manager.save_state();
+                    manager.get_plugins()
+                }),
+                Err(_) => None,
+            };
+            if let Some(plugins) = plugins {
                 state.emit_plugins_changed(plugins);
             }
         }
+        text = report.text;
+
+        if text.trim().is_empty() {
+            return Err("Text is empty after plugin transforms".to_string());
+        }
     }
-    eprintln!("[enqueue_tts] After plugin broadcast: {:?}", start.elapsed());
+    eprintln!("[enqueue_tts] After plugin transforms: {:?}", start.elapsed());
 
     // Add to history with Queued status
     let message_id = state.add_tts_message(text.clone());
@@ -664,7 +691,7 @@ pub async fn enqueue_tts(state: tauri::State<'_, AppState>, app: tauri::AppHandl
     eprintln!("[enqueue_tts] After emit: {:?}", start.elapsed());
 
     // Clone state for background task
-    let state_clone: AppState = (*state).clone();
+    let state_clone: AppState = state.clone();
     eprintln!("[enqueue_tts] After state.clone(): {:?}", start.elapsed());
 
     // Start queue processing in background if not already running
@@ -678,6 +705,10 @@ pub async fn enqueue_tts(state: tauri::State<'_, AppState>, app: tauri::AppHandl
     }
     eprintln!("[enqueue_tts] After thread spawn: {:?}", start.elapsed());
 
+    // Hand the final text to plugins (queued on their workers, doesn't wait for them)
+    state.broadcast_text_to_plugins(&text, source, Some(message_id.clone()));
+    eprintln!("[enqueue_tts] After plugin broadcast: {:?}", start.elapsed());
+
     // Return message ID immediately (non-blocking)
     eprintln!("[enqueue_tts] END: {:?}", start.elapsed());
     Ok(message_id)
@@ -714,6 +745,10 @@ fn process_tts_queue_sync(state: AppState, app: tauri::AppHandle) {
                     "id": msg_id,
                     "text": msg_text
                 }));
+                state.notify_plugins(PluginEvent::TtsStarted {
+                    id: msg_id.clone(),
+                    text: msg_text.clone(),
+                });
 
                 // Get TTS engine and speak
                 let result = {
@@ -756,6 +791,7 @@ fn process_tts_queue_sync(state: AppState, app: tauri::AppHandle) {
                             state.tts_queue_processing.store(false, std::sync::atomic::Ordering::Release);
                             state.tts_is_speaking.store(false, std::sync::atomic::Ordering::Release);
                             let _ = app.emit("tts:cancelled", serde_json::json!({ "id": msg_id }));
+                            state.notify_plugins(PluginEvent::TtsCancelled { id: msg_id, text: msg_text });
                             return;
                         }
 
@@ -778,6 +814,7 @@ fn process_tts_queue_sync(state: AppState, app: tauri::AppHandle) {
                     state.tts_queue_processing.store(false, std::sync::atomic::Ordering::Release);
                     state.tts_is_speaking.store(false, std::sync::atomic::Ordering::Release);
                     let _ = app.emit("tts:cancelled", serde_json::json!({ "id": msg_id }));
+                    state.notify_plugins(PluginEvent::TtsCancelled { id: msg_id, text: msg_text });
                     break;
                 }
 
@@ -786,6 +823,7 @@ fn process_tts_queue_sync(state: AppState, app: tauri::AppHandle) {
                         state.update_tts_message_status(&msg_id, TtsMessageStatus::Completed);
                         state.set_current_tts_message_id(None);
                         let _ = app.emit("tts:completed", serde_json::json!({ "id": msg_id }));
+                        state.notify_plugins(PluginEvent::TtsCompleted { id: msg_id, text: msg_text });
                     }
                     Err(e) => {
                         eprintln!("TTS error: {}", e);
@@ -796,6 +834,7 @@ fn process_tts_queue_sync(state: AppState, app: tauri::AppHandle) {
                             "id": msg_id,
                             "error": e
                         }));
+                        state.notify_plugins(PluginEvent::TtsFailed { id: msg_id, text: msg_text, error: e });
                     }
                 }
             }
@@ -817,12 +856,15 @@ pub fn cancel_tts_message(state: tauri::State<'_, AppState>, app: tauri::AppHand
 
     match message.status {
         TtsMessageStatus::Queued => {
+            let text = message.text.clone();
             drop(history); // Release lock before updating
             state.update_tts_message_status(&id, TtsMessageStatus::Completed);
             let _ = app.emit("tts:cancelled", serde_json::json!({ "id": id }));
+            state.notify_plugins(PluginEvent::TtsCancelled { id, text });
             Ok(())
         }
         TtsMessageStatus::Playing => {
+            let text = message.text.clone();
             drop(history); // Release lock
             // Stop current playback
             let lock_result = state.tts_engine.lock();
@@ -834,9 +876,14 @@ pub fn cancel_tts_message(state: tauri::State<'_, AppState>, app: tauri::AppHand
                 }
             };
             engine.stop()?;
+            // The queue reports the cancellation of its own message to plugins
+            let from_queue = state.tts_queue_processing.load(std::sync::atomic::Ordering::Acquire);
             state.tts_queue_cancel.store(true, std::sync::atomic::Ordering::Release);
             state.update_tts_message_status(&id, TtsMessageStatus::Completed);
             let _ = app.emit("tts:cancelled", serde_json::json!({ "id": id }));
+            if !from_queue {
+                state.notify_plugins(PluginEvent::TtsCancelled { id, text });
+            }
             Ok(())
         }
         TtsMessageStatus::Completed => {
@@ -861,6 +908,9 @@ pub async fn repeat_tts_message(state: tauri::State<'_, AppState>, app: tauri::A
 
     let text = message.text.clone();
 
+    // Plugins decide by their routing rules whether they want repeats
+    state.broadcast_text_to_plugins(&text, MessageSource::Repeated, Some(id.clone()));
+
     // Update status to playing
     state.update_tts_message_status(&id, TtsMessageStatus::Playing);
     state.set_current_tts_message_id(Some(id.clone()));
@@ -873,6 +923,10 @@ pub async fn repeat_tts_message(state: tauri::State<'_, AppState>, app: tauri::A
         "id": id,
         "text": text.clone()
     }));
+    state.notify_plugins(PluginEvent::TtsStarted {
+        id: id.clone(),
+        text: text.clone(),
+    });
 
     // Speak - handle poisoned mutex
     let lock_result = state.tts_engine.lock();
@@ -885,17 +939,56 @@ pub async fn repeat_tts_message(state: tauri::State<'_, AppState>, app: tauri::A
     };
 
     let result = engine.speak(&text);
+    drop(engine);
 
-    // Only mark as completed if there was an error
-    // For successful playback (OpenAI), completion is handled by callback
-    if result.is_err() {
-        state.update_tts_message_status(&id, TtsMessageStatus::Completed);
+    match result {
+        // Playback may go on in the background (OpenAI): completion is reported when it ends
+        Ok(()) => {
+            let state: AppState = state.inner().clone();
+            std::thread::spawn(move || finish_repeat(state, app, id, text));
+            Ok(())
+        }
+        Err(e) => {
+            state.update_tts_message_status(&id, TtsMessageStatus::Completed);
+            state.set_current_tts_message_id(None);
+            state.tts_is_speaking.store(false, std::sync::atomic::Ordering::Release);
+            let _ = app.emit("tts:completed", serde_json::json!({ "id": id }));
+            state.notify_plugins(PluginEvent::TtsFailed { id, text, error: e.clone() });
+            Err(e)
+        }
+    }
+}
+
+/// Wait until a repeated message has been played, then report it as completed
+fn finish_repeat(state: AppState, app: tauri::AppHandle, id: String, text: String) {
+    // Same limit as the queue: 5 minutes
+    for _ in 0..6000 {
+        let still_speaking = match state.tts_engine.lock() {
+            Ok(engine) => engine.is_speaking(),
+            Err(poisoned) => poisoned.into_inner().is_speaking(),
+        };
+        if !still_speaking {
+            break;
+        }
+        std::thread::sleep(std::time::Duration::from_millis(50));
+    }
+
+    // Cancelled meanwhile: `cancel_tts_message` has reported it already
+    let playing = state
+        .get_tts_history()
+        .iter()
+        .any(|m| m.id == id && m.status == TtsMessageStatus::Playing);
+    if !playing {
+        return;
+    }
+
+    state.update_tts_message_status(&id, TtsMessageStatus::Completed);
+    if state.get_current_tts_message_id().as_deref() == Some(id.as_str()) {
         state.set_current_tts_message_id(None);
         state.tts_is_speaking.store(false, std::sync::atomic::Ordering::Release);
-        let _ = app.emit("tts:completed", serde_json::json!({ "id": id }));
     }
-
-    result
+    let _ = app.emit("tts:completed", serde_json::json!({ "id": id }));
+    state.notify_plugins(PluginEvent::TtsCompleted { id, text });
 }
 
 // === System TTS voice and parameters commands ===
@@ -1043,6 +1136,26 @@ pub fn set_openai_instructions(state: tauri::State<'_, AppState>, instructions:
     result
 }
 
+/// Установить формат аудио OpenAI (mp3, opus, aac, flac, wav, pcm)
+#[tauri::command]
+pub fn set_openai_response_format(state: tauri::State<'_, AppState>, format: String) -> Result<(), String> {
+    let format = OpenAIResponseFormat::try_from(format.as_str())?;
+    let lock_result = state.tts_engine.lock();
+    let engine = match lock_result {
+        Ok(guard) => guard,
+        Err(poisoned) => {
+            eprintln!("TTS engine mutex was poisoned, recovering...");
+            poisoned.into_inner()
+        }
+    };
+    let result = engine.set_openai_response_format(format);
+
+    // Emit config changed event
+    state.emit_tts_config_changed();
+
+    result
+}
+
 /// Установить прокси OpenAI
 #[tauri::command]
 pub fn set_openai_proxy(state: tauri::State<'_, AppState>, host: Option<String>, port: Option<u16>) -> Result<(), String> {
@@ -1223,6 +1336,70 @@ pub fn get_localhost_config(state: tauri::State<'_, AppState>) -> LocalhostConfi
     engine.get_localhost_config()
 }
 
+// === Plugin TTS provider commands ===
+
+/// TTS provider implemented by a plugin
+#[derive(Debug, Serialize)]
+pub struct PluginTtsProvider {
+    /// Provider id for `set_tts_provider` ("plugin:<plugin id>")
+    pub id: String,
+    /// Plugin id for `get_plugin_voices` / `set_plugin_voice`
+    pub plugin: String,
+    pub name: String,
+    /// Selected voice (empty for the plugin's default)
+    pub voice: String,
+}
+
+/// Get plugins that can be used as TTS providers
+#[tauri::command]
+pub fn get_plugin_tts_providers(state: tauri::State<'_, AppState>) -> Result<Vec<PluginTtsProvider>, String> {
+    let providers = match state.plugin_manager.lock() {
+        Ok(plugin_manager) => match *plugin_manager {
+            Some(ref manager) => manager.tts_providers(),
+            None => Vec::new(),
+        },
+        Err(_) => return Err("Failed to lock plugin manager".to_string()),
+    };
+
+    let engine = state.tts_engine.lock()
+        .map_err(|e| format!("Failed to lock TTS engine: {}", e))?;
+    Ok(providers
+        .into_iter()
+        .map(|(plugin, name)| PluginTtsProvider {
+            id: String::from(TtsProvider::Plugin(plugin.clone())),
+            voice: engine.get_plugin_voice(&plugin),
+            plugin,
+            name,
+        })
+        .collect())
+}
+
+/// Get voices of a plugin TTS provider
+#[tauri::command]
+pub fn get_plugin_voices(state: tauri::State<'_, AppState>, plugin: String) -> Result<Vec<PluginVoice>, String> {
+    if let Ok(plugin_manager) = state.plugin_manager.lock() {
+        if let Some(ref manager) = *plugin_manager {
+            return manager.plugin_voices(&plugin);
+        }
+    }
+    Err("Plugin manager not initialized".to_string())
+}
+
+/// Select the voice of a plugin TTS provider
+#[tauri::command]
+pub fn set_plugin_voice(state: tauri::State<'_, AppState>, plugin: String, voice: String) -> Result<(), String> {
+    let lock_result = state.tts_engine.lock();
+    let engine = match lock_result {
+        Ok(guard) => guard,
+        Err(poisoned) => {
+            eprintln!("TTS engine mutex was poisoned, recovering...");
+            poisoned.into_inner()
+        }
+    };
+    engine.set_plugin_voice(&plugin, voice);
+    Ok(())
+}
+
 // === Audio output and virtual mic commands ===
 
 /// Get all audio output devices (for speakers)
@@ -1349,6 +1526,53 @@ pub async fn set_virtual_mic_volume(state: tauri::State<'_, AppState>, volume: f
     Ok(())
 }
 
+/// Set master volume (0-100), multiplied into every output
+#[tauri::command]
+pub async fn set_master_volume(state: tauri::State<'_, AppState>, volume: f32) -> Result<(), String> {
+    if let Ok(mut manager_guard) = state.audio_settings_manager.lock() {
+        if let Some(ref mut manager) = *manager_guard {
+            manager.set_master_volume(volume as u8)?;
+        }
+    }
+    // Also update TtsEngine
+    if let Ok(engine) = state.tts_engine.lock() {
+        engine.set_master_volume(volume / 100.0);
+    }
+    Ok(())
+}
+
+/// Set speaker delay offset (ms) relative to other outputs
+#[tauri::command]
+pub async fn set_speaker_delay(state: tauri::State<'_, AppState>, delay_ms: u32) -> Result<(), String> {
+    let delay_ms = delay_ms.min(MAX_OUTPUT_DELAY_MS);
+    if let Ok(mut manager_guard) = state.audio_settings_manager.lock() {
+        if let Some(ref mut manager) = *manager_guard {
+            manager.set_speaker_delay(delay_ms)?;
+        }
+    }
+    // Also update TtsEngine
+    if let Ok(engine) = state.tts_engine.lock() {
+        engine.set_speaker_delay(delay_ms);
+    }
+    Ok(())
+}
+
+/// Set virtual mic delay offset (ms) relative to other outputs
+#[tauri::command]
+pub async fn set_virtual_mic_delay(state: tauri::State<'_, AppState>, delay_ms: u32) -> Result<(), String> {
+    let delay_ms = delay_ms.min(MAX_OUTPUT_DELAY_MS);
+    if let Ok(mut manager_guard) = state.audio_settings_manager.lock() {
+        if let Some(ref mut manager) = *manager_guard {
+            manager.set_virtual_mic_delay(delay_ms)?;
+        }
+    }
+    // Also update TtsEngine
+    if let Ok(engine) = state.tts_engine.lock() {
+        engine.set_virtual_mic_delay(delay_ms);
+    }
+    Ok(())
+}
+
 /// Get audio settings
 #[tauri::command]
 pub fn get_audio_settings(state: tauri::State<'_, AppState>) -> Result<crate::virtual_mic::AudioSettings, String> {
@@ -1374,24 +1598,27 @@ pub fn get_plugins(state: tauri::State<'_, AppState>) -> Result<Vec<PluginInfo>,
 }
 
 /// Set plugin configuration
+///
+/// A config that doesn't match the plugin's schema is rejected with per-field errors.
 #[tauri::command]
 pub fn set_plugin_config(
     state: tauri::State<'_, AppState>,
-    name: String,
+    id: String,
     config: serde_json::Value,
-) -> Result<(), String> {
+) -> Result<(), ConfigError> {
     if let Ok(mut plugin_manager) = state.plugin_manager.lock() {
         if let Some(ref mut manager) = *plugin_manager {
-            return match manager.set_plugin_config(&name, &config) {
+            return match manager.set_plugin_config(&id, &config) {
                 Ok(()) => {
                     // Emit plugins changed event
                     let plugins = manager.get_plugins();
                     state.emit_plugins_changed(plugins);
                     Ok(())
                 }
+                Err(e) if !e.fields.is_empty() => Err(e),
                 Err(e) => {
-                    // Disable plugin on config error
-                    let _ = manager.toggle_plugin(&name, false);
+                    // Disable plugin if it rejected the config
+                    let _ = manager.toggle_plugin(&id, false);
                     // Emit plugins changed event after disabling
                     let plugins = manager.get_plugins();
                     state.emit_plugins_changed(plugins);
@@ -1400,19 +1627,19 @@ pub fn set_plugin_config(
             };
         }
     }
-    Err("Plugin manager not initialized".to_string())
+    Err("Plugin manager not initialized".to_string().into())
 }
 
 /// Toggle plugin enabled state
 #[tauri::command]
 pub fn toggle_plugin(
     state: tauri::State<'_, AppState>,
-    name: String,
+    id: String,
     enabled: bool,
 ) -> Result<(), String> {
     if let Ok(mut plugin_manager) = state.plugin_manager.lock() {
         if let Some(ref mut manager) = *plugin_manager {
-            let result = manager.toggle_plugin(&name, enabled);
+            let result = manager.toggle_plugin(&id, enabled);
             if result.is_ok() {
                 // Emit plugins changed event
                 let plugins = manager.get_plugins();
@@ -1424,15 +1651,141 @@ pub fn toggle_plugin(
     Err("Plugin manager not initialized".to_string())
 }
 
+/// Change how many failures a plugin may have before it is disabled
+#[tauri::command]
+pub fn set_plugin_failure_policy(
+    state: tauri::State<'_, AppState>,
+    id: String,
+    policy: FailurePolicy,
+) -> Result<(), String> {
+    if let Ok(mut plugin_manager) = state.plugin_manager.lock() {
+        if let Some(ref mut manager) = *plugin_manager {
+            let result = manager.set_failure_policy(&id, policy);
+            if result.is_ok() {
+                state.emit_plugins_changed(manager.get_plugins());
+            }
+            return result;
+        }
+    }
+    Err("Plugin manager not initialized".to_string())
+}
+
+/// Unload a plugin and load it again from its file, re-applying its saved config
+#[tauri::command]
+pub fn reload_plugin(state: tauri::State<'_, AppState>, id: String) -> Result<(), String> {
+    if let Ok(mut plugin_manager) = state.plugin_manager.lock() {
+        if let Some(ref mut manager) = *plugin_manager {
+            let result = manager.reload_plugin(&id);
+            // A failed reload leaves the plugin in the list of failed ones
+            state.emit_plugins_changed(manager.get_plugins());
+            return result;
+        }
+    }
+    Err("Plugin manager not initialized".to_string())
+}
+
+/// Unload a plugin until it is reloaded or the app restarts
+#[tauri::command]
+pub fn unload_plugin(state: tauri::State<'_, AppState>, id: String) -> Result<(), String> {
+    if let Ok(mut plugin_manager) = state.plugin_manager.lock() {
+        if let Some(ref mut manager) = *plugin_manager {
+            let result = manager.unload_plugin(&id);
+            state.emit_plugins_changed(manager.get_plugins());
+            return result;
+        }
+    }
+    Err("Plugin manager not initialized".to_string())
+}
+
+/// Copy a plugin library or plugin folder into the plugins directory and load it
+/// Returns the id of the installed plugin
+#[tauri::command]
+pub fn install_plugin(state: tauri::State<'_, AppState>, path: String) -> Result<String, String> {
+    if let Ok(mut plugin_manager) = state.plugin_manager.lock() {
+        if let Some(ref mut manager) = *plugin_manager {
+            let result = manager.install_plugin(std::path::Path::new(path.trim()));
+            state.emit_plugins_changed(manager.get_plugins());
+            return result;
+        }
+    }
+    Err("Plugin manager not initialized".to_string())
+}
+
+/// Unload a plugin and delete its files
+#[tauri::command]
+pub fn uninstall_plugin(state: tauri::State<'_, AppState>, id: String) -> Result<(), String> {
+    if let Ok(mut plugin_manager) = state.plugin_manager.lock() {
+        if let Some(ref mut manager) = *plugin_manager {
+            let result = manager.uninstall_plugin(&id);
+            state.emit_plugins_changed(manager.get_plugins());
+            return result;
+        }
+    }
+    Err("Plugin manager not initialized".to_string())
+}
+
+/// Set the order in which text-transforming plugins run
+#[tauri::command]
+pub fn set_plugin_transform_order(
+    state: tauri::State<'_, AppState>,
+    order: Vec<String>,
+) -> Result<(), String> {
+    if let Ok(mut plugin_manager) = state.plugin_manager.lock() {
+        if let Some(ref mut manager) = *plugin_manager {
+            manager.set_transform_order(order)?;
+            let plugins = manager.get_plugins();
+            state.emit_plugins_changed(plugins);
+            return Ok(());
+        }
+    }
+    Err("Plugin manager not initialized".to_string())
+}
+
+/// Change which spoken messages a plugin receives
+#[tauri::command]
+pub fn set_plugin_routing(
+    state: tauri::State<'_, AppState>,
+    id: String,
+    rules: RoutingRules,
+) -> Result<(), String> {
+    if let Ok(mut plugin_manager) = state.plugin_manager.lock() {
+        if let Some(ref mut manager) = *plugin_manager {
+            let result = manager.set_routing_rules(&id, rules);
+            if result.is_ok() {
+                state.emit_plugins_changed(manager.get_plugins());
+            }
+            return result;
+        }
+    }
+    Err("Plugin manager not initialized".to_string())
+}
+
+/// Set the order in which plugins get messages, audio and events
+#[tauri::command]
+pub fn set_plugin_order(
+    state: tauri::State<'_, AppState>,
+    order: Vec<String>,
+) -> Result<(), String> {
+    if let Ok(mut plugin_manager) = state.plugin_manager.lock() {
+        if let Some(ref mut manager) = *plugin_manager {
+            manager.set_plugin_order(order)?;
+            let plugins = manager.get_plugins();
+            state.emit_plugins_changed(plugins);
+            return Ok(());
+        }
+    }
+    Err("Plugin manager not initialized".to_string())
+}
+
 /// Check plugin status
 #[tauri::command]
 pub fn check_plugin_status(
     state: tauri::State<'_, AppState>,
-    name: String,
+    id: String,
 ) -> Result<SerializablePluginStatus, String> {
     if let Ok(plugin_manager) = state.plugin_manager.lock() {
         if let Some(ref manager) = *plugin_manager {
-            let status = manager.check_plugin_status(&name)?;
+            let status = manager.check_plugin_status(&id)?;
             return Ok(status.into());
         }
     }
@@ -1,4 +1,5 @@
 // src-tauri/src/localhost.rs
+use crate::audio_format::AudioFormat;
 use serde::{Deserialize, Serialize};
 use std::fs;
 use std::path::PathBuf;
@@ -263,7 +264,7 @@ impl LocalhostClient {
     }
 
     /// Синтезировать речь с помощью локального сервера
-    pub async fn synthesize(&self, text: &str) -> Result<Vec<u8>, String> {
+    pub async fn synthesize(&self, text: &str) -> Result<(Vec<u8>, AudioFormat), String> {
         if text.is_empty() {
             return Err("Text cannot be empty".to_string());
         }
@@ -349,7 +350,13 @@ impl LocalhostClient {
 
         eprintln!("[Localhost] Content-Type: {}", content_type);
 
-        if !content_type.contains("audio") && !content_type.contains("mpeg") {
+        // Определяем формат по Content-Type (MP3, WAV, OGG и др.); другие audio/*
+        // и application/octet-stream распознаём по самим данным
+        let declared = AudioFormat::from_content_type(&content_type);
+        let audio_like = content_type.contains("audio")
+            || content_type.contains("mpeg")
+            || content_type.contains("octet-stream");
+        if declared.is_none() && !audio_like {
             eprintln!("[Localhost] Unexpected content type! Reading response body...");
             // We might have gotten a JSON error response with 200 OK status
             let body = response.text().await
@@ -378,7 +385,16 @@ impl LocalhostClient {
 
         eprintln!("[Localhost] Successfully received {} bytes of audio data", audio_data.len());
 
-        Ok(audio_data)
+        let format = match declared {
+            Some(format) => format,
+            None => {
+                let format = AudioFormat::detect(&audio_data).unwrap_or(AudioFormat::Mp3);
+                eprintln!("[Localhost] Format detected from data: {}", format.name());
+                format
+            }
+        };
+
+        Ok((audio_data, format))
     }
 
     // Геттеры и сеттеры для настроек
@@ -10,6 +10,7 @@ mod tts;
 mod virtual_mic;   // Virtual microphone and dual output
 mod plugins;       // Plugin system
 mod audio_player;  // Rodio audio player
+mod audio_format;  // Audio format detection and decoding
 
 use commands::{
     clear_active_window_keys, clear_keys, get_active_window_keys, get_input_language,
@@ -26,20 +27,30 @@ use commands::{
     get_system_voices, set_system_voice, set_tts_rate, set_tts_pitch, set_tts_volume,
     // OpenAI TTS commands
     get_openai_voices, set_openai_voice, set_openai_speed,
-    set_openai_instructions, set_openai_proxy, get_openai_config,
+    set_openai_instructions, set_openai_response_format, set_openai_proxy, get_openai_config,
     // Localhost TTS commands
     get_localhost_voices, refresh_localhost_voices, test_localhost_connection,
     set_localhost_port, set_localhost_token, set_localhost_voice, get_localhost_config,
+    // Plugin TTS provider commands
+    get_plugin_tts_providers, get_plugin_voices, set_plugin_voice,
     // Audio output and virtual mic commands
     get_output_devices, get_virtual_mic_devices, set_speaker_device, set_speaker_enabled,
     set_speaker_volume, set_virtual_mic_device, enable_virtual_mic, disable_virtual_mic,
-    set_virtual_mic_volume, get_audio_settings,
+    set_virtual_mic_volume, set_master_volume, set_speaker_delay, set_virtual_mic_delay, get_audio_settings,
     // Plugin commands
-    get_plugins, set_plugin_config, toggle_plugin, check_plugin_status,
+    get_plugins, set_plugin_config, toggle_plugin, check_plugin_status, set_plugin_transform_order,
+    reload_plugin, unload_plugin, install_plugin, uninstall_plugin, set_plugin_failure_policy,
+    set_plugin_routing, set_plugin_order,
 };
+use audio_player::AudioEvent;
+use plugins::{EnqueueLimiter, HostRequest, MessageSource, PluginEvent};
 use state::AppState;
 use state::AppStateEvent;
 use tauri::{Emitter, Manager};
+use std::sync::Arc;
+
+/// Plugin texts waiting to be enqueued before new ones are dropped
+const PLUGIN_TEXT_QUEUE: usize = 32;
 
 fn main() {
     // Create the shared application state
@@ -101,6 +112,7 @@ fn main() {
             set_openai_voice,
             set_openai_speed,
             set_openai_instructions,
+            set_openai_response_format,
             set_openai_proxy,
             get_openai_config,
             // Localhost TTS commands
@@ -111,6 +123,10 @@ fn main() {
             set_localhost_token,
             set_localhost_voice,
             get_localhost_config,
+            // Plugin TTS provider commands
+            get_plugin_tts_providers,
+            get_plugin_voices,
+            set_plugin_voice,
             // Audio output and virtual mic commands
             get_output_devices,
             get_virtual_mic_devices,
@@ -121,12 +137,23 @@ fn main() {
             enable_virtual_mic,
             disable_virtual_mic,
             set_virtual_mic_volume,
+            set_master_volume,
+            set_speaker_delay,
+            set_virtual_mic_delay,
             get_audio_settings,
             // Plugin commands
             get_plugins,
             set_plugin_config,
             toggle_plugin,
             check_plugin_status,
+            set_plugin_transform_order,
+            reload_plugin,
+            unload_plugin,
+            install_plugin,
+            uninstall_plugin,
+            set_plugin_failure_policy,
+            set_plugin_routing,
+            set_plugin_order,
         ])
         // Setup on window initialization
         .setup(move |app| {
@@ -189,6 +216,25 @@ fn main() {
             // Enumerate virtual mic devices (silently)
             let _virtual_mics = find_virtual_devices();
 
+            // Forward output level meters and stream errors to the UI
+            if let Ok(engine) = app_state.tts_engine.lock() {
+                let audio_handle = app.handle().clone();
+                engine.set_audio_event_callback(Arc::new(move |event| match event {
+                    AudioEvent::Levels(levels) => {
+                        let _ = audio_handle.emit("audio:levels", levels);
+                    }
+                    AudioEvent::Error(error) => {
+                        let _ = audio_handle.emit("audio:output_error", error);
+                    }
+                }));
+
+                // Let plugins see synthesized audio before it is played
+                let audio_state = app_state.clone();
+                engine.set_synthesized_audio_callback(Arc::new(move |audio_data, format| {
+                    audio_state.broadcast_audio_to_plugins(audio_data, format);
+                }));
+            }
+
             match AudioSettingsManager::new(config_dir.clone()) {
                 Ok(manager) => {
                     // Load settings into TtsEngine
@@ -196,9 +242,12 @@ fn main() {
                     if let Ok(engine) = app_state.tts_engine.lock() {
                         engine.set_speaker_device(settings.speaker_device.clone());
                         engine.set_speaker_enabled(settings.speaker_enabled);
-                        engine.set_speaker_volume(settings.speaker_volume as f32 / 100.0);
+                        engine.set_speaker_volume(manager.speaker_volume_f32());
                         engine.set_virtual_mic_device(settings.virtual_mic_device.clone());
-                        engine.set_virtual_mic_volume(settings.virtual_mic_volume as f32 / 100.0);
+                        engine.set_virtual_mic_volume(manager.virtual_mic_volume_f32());
+                        engine.set_master_volume(manager.master_volume_f32());
+                        engine.set_speaker_delay(settings.speaker_delay_ms);
+                        engine.set_virtual_mic_delay(settings.virtual_mic_delay_ms);
                     }
 
                     if let Ok(mut audio_manager) = app_state.audio_settings_manager.lock() {
@@ -216,8 +265,21 @@ fn main() {
                 .unwrap_or_default();
             let plugins_dir = exe_dir.join("plugins");
 
-            match plugins::PluginManager::new(plugins_dir) {
+            match plugins::PluginManager::new(plugins_dir.clone()) {
                 Ok(mut manager) => {
+                    // Plugin requests go through the event channel (it is set up below,
+                    // requests arriving earlier are dropped)
+                    let request_sender = Arc::clone(&app_state.event_sender);
+                    manager.set_host(
+                        plugins::PluginHost::new(config_dir.join("plugin-data")).with_sender(Arc::new(move |request| {
+                            if let Ok(sender) = request_sender.lock() {
+                                if let Some(ref tx) = *sender {
+                                    let _ = tx.send(AppStateEvent::PluginRequest(request));
+                                }
+                            }
+                        })),
+                    );
+
                     match manager.load_all() {
                         Ok(count) => {
                             eprintln!("Loaded {} plugin(s)", count);
@@ -229,6 +291,32 @@ fn main() {
                     if let Ok(mut plugin_manager) = app_state.plugin_manager.lock() {
                         *plugin_manager = Some(manager);
                     }
+
+                    // Plugins can act as TTS providers
+                    if let Ok(engine) = app_state.tts_engine.lock() {
+                        engine.set_plugin_manager(Arc::clone(&app_state.plugin_manager));
+                    }
+
+                    // Pick up plugins added, updated or removed while the app runs
+                    let watched_manager = Arc::clone(&app_state.plugin_manager);
+                    let change_sender = Arc::clone(&app_state.event_sender);
+                    let watcher = plugins::watcher::spawn_watcher(&plugins_dir, move |paths| {
+                        let plugins = match watched_manager.lock() {
+                            Ok(mut plugin_manager) => match *plugin_manager {
+                                Some(ref mut manager) if manager.handle_changes(&paths) => manager.get_plugins(),
+                                _ => return,
+                            },
+                            Err(_) => return,
+                        };
+                        if let Ok(sender) = change_sender.lock() {
+                            if let Some(ref tx) = *sender {
+                                let _ = tx.send(AppStateEvent::PluginsChanged(plugins));
+                            }
+                        }
+                    });
+                    if let Err(e) = watcher {
+                        eprintln!("Failed to watch plugins directory: {}", e);
+                    }
                 }
                 Err(e) => {
                     eprintln!("Failed to initialize plugin manager: {}", e);
@@ -245,14 +333,30 @@ fn main() {
                 *sender = Some(event_tx);
             }
 
+            // Texts enqueued by plugins are spoken on their own thread: transforms and
+            // synthesis can take seconds and must not hold up the other events
+            let (plugin_text_tx, plugin_text_rx) = mpsc::sync_channel::<(String, String)>(PLUGIN_TEXT_QUEUE);
+            let enqueue_app = app.handle().clone();
+            let enqueue_state = app_state.clone();
+            std::thread::spawn(move || {
+                for (plugin, text) in plugin_text_rx {
+                    if let Err(e) = commands::enqueue_text(&enqueue_state, &enqueue_app, text, MessageSource::Api) {
+                        eprintln!("[Event] Plugin '{}' enqueue failed: {}", plugin, e);
+                    }
+                }
+            });
+
             // Spawn event handler thread
             let app_handle = app.handle().clone();
+            let event_state = app_state.clone();
             std::thread::spawn(move || {
+                let mut enqueue_limiter = EnqueueLimiter::default();
                 for event in event_rx {
                     match event {
                         AppStateEvent::BlockingChanged(enabled) => {
                             eprintln!("[Event] BlockingChanged: {}", enabled);
                             let _ = app_handle.emit("blocking_changed", enabled);
+                            event_state.notify_plugins(PluginEvent::BlockingChanged(enabled));
                         }
                         AppStateEvent::CapsLockChanged(enabled) => {
                             eprintln!("[Event] CapsLockChanged: {}", enabled);
@@ -285,6 +389,7 @@ fn main() {
                         }
                         AppStateEvent::TtsProviderChanged(provider) => {
                             eprintln!("[Event] TtsProviderChanged: {}", provider);
+                            event_state.notify_plugins(PluginEvent::ProviderChanged(provider.clone()));
                             let _ = app_handle.emit("tts_provider_changed", provider);
                         }
                         AppStateEvent::TtsConfigChanged => {
@@ -303,6 +408,44 @@ fn main() {
                             eprintln!("[Event] ShowWindowRequested");
                             let _ = app_handle.emit("show_window_requested", ());
                         }
+                        AppStateEvent::PluginRequest(HostRequest::EnqueueText { plugin, text }) => {
+                            eprintln!("[Event] Plugin '{}' enqueued text", plugin);
+                            if !enqueue_limiter.allow(&plugin, std::time::Instant::now()) {
+                                eprintln!("[Event] Plugin '{}' enqueues too often, text dropped", plugin);
+                            } else if plugin_text_tx.try_send((plugin.clone(), text)).is_err() {
+                                eprintln!("[Event] Plugin '{}' text dropped: too many texts waiting", plugin);
+                            }
+                        }
+                        AppStateEvent::PluginRequest(HostRequest::PluginFailed { plugin, error }) => {
+                            eprintln!("[Event] Plugin '{}' failed: {}", plugin, error);
+                            if let Ok(plugin_manager) = event_state.plugin_manager.lock() {
+                                if let Some(ref manager) = *plugin_manager {
+                                    if let Err(e) = manager.save_state() {
+                                        eprintln!("[Event] Failed to save plugin state: {}", e);
+                                    }
+                                    let _ = app_handle.emit("plugins_changed", manager.get_plugins());
+                                }
+                            }
+                        }
+                        AppStateEvent::PluginRequest(HostRequest::PluginRecovered { plugin }) => {
+                            eprintln!("[Event] Plugin '{}' was enabled again", plugin);
+                            if let Ok(plugin_manager) = event_state.plugin_manager.lock() {
+                                if let Some(ref manager) = *plugin_manager {
+                                    if let Err(e) = manager.save_state() {
+                                        eprintln!("[Event] Failed to save plugin state: {}", e);
+                                    }
+                                    let _ = app_handle.emit("plugins_changed", manager.get_plugins());
+                                }
+                            }
+                        }
+                        AppStateEvent::PluginRequest(HostRequest::Notification { plugin, title, body }) => {
+                            eprintln!("[Event] Plugin '{}' notification: {}", plugin, title);
+                            let _ = app_handle.emit("plugins:notification", serde_json::json!({
+                                "plugin": plugin,
+                                "title": title,
+                                "body": body
+                            }));
+                        }
                     }
                 }
                 eprintln!("[Event] Event handler thread exiting");
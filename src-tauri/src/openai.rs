@@ -1,4 +1,5 @@
 // src-tauri/src/openai.rs
+use crate::audio_format::AudioFormat;
 use serde::{Deserialize, Serialize};
 use std::fs;
 use std::path::PathBuf;
@@ -28,12 +29,75 @@ pub struct OpenAIConfig {
     pub timeout: u64,
     #[serde(default)]
     pub instructions: String,
+    #[serde(default)]
+    pub response_format: OpenAIResponseFormat,
 }
 
 fn default_timeout() -> u64 {
     20
 }
 
+/// Формат аудио, запрашиваемый у OpenAI (`response_format`)
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum OpenAIResponseFormat {
+    #[default]
+    Mp3,
+    Opus,
+    Aac,
+    Flac,
+    Wav,
+    Pcm,
+}
+
+/// OpenAI отдаёт PCM как 24 кГц, 16 бит, моно
+const OPENAI_PCM_SAMPLE_RATE: u32 = 24000;
+
+impl OpenAIResponseFormat {
+    /// Значение параметра `response_format` в запросе
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            OpenAIResponseFormat::Mp3 => "mp3",
+            OpenAIResponseFormat::Opus => "opus",
+            OpenAIResponseFormat::Aac => "aac",
+            OpenAIResponseFormat::Flac => "flac",
+            OpenAIResponseFormat::Wav => "wav",
+            OpenAIResponseFormat::Pcm => "pcm",
+        }
+    }
+
+    /// Формат для декодирования в AudioPlayer
+    pub fn audio_format(&self) -> AudioFormat {
+        match self {
+            OpenAIResponseFormat::Mp3 => AudioFormat::Mp3,
+            OpenAIResponseFormat::Opus => AudioFormat::Opus,
+            OpenAIResponseFormat::Aac => AudioFormat::Aac,
+            OpenAIResponseFormat::Flac => AudioFormat::Flac,
+            OpenAIResponseFormat::Wav => AudioFormat::Wav,
+            OpenAIResponseFormat::Pcm => AudioFormat::Pcm {
+                sample_rate: OPENAI_PCM_SAMPLE_RATE,
+                channels: 1,
+            },
+        }
+    }
+}
+
+impl TryFrom<&str> for OpenAIResponseFormat {
+    type Error = String;
+
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        match value.to_lowercase().as_str() {
+            "mp3" => Ok(OpenAIResponseFormat::Mp3),
+            "opus" => Ok(OpenAIResponseFormat::Opus),
+            "aac" => Ok(OpenAIResponseFormat::Aac),
+            "flac" => Ok(OpenAIResponseFormat::Flac),
+            "wav" => Ok(OpenAIResponseFormat::Wav),
+            "pcm" => Ok(OpenAIResponseFormat::Pcm),
+            other => Err(format!("Unsupported response format: {}", other)),
+        }
+    }
+}
+
 impl Default for OpenAIConfig {
     fn default() -> Self {
         Self {
@@ -45,6 +109,7 @@ impl Default for OpenAIConfig {
             speed: 1.0,
             timeout: 20,
             instructions: String::new(),
+            response_format: OpenAIResponseFormat::default(),
         }
     }
 }
@@ -163,8 +228,8 @@ impl OpenAIClient {
         ]
     }
 
-    /// Синтезировать речь с помощью OpenAI API
-    pub async fn synthesize(&self, text: &str) -> Result<Vec<u8>, String> {
+    /// Синтезировать речь с помощью OpenAI API (аудио в формате из настроек)
+    pub async fn synthesize(&self, text: &str) -> Result<(Vec<u8>, AudioFormat), String> {
         if text.is_empty() {
             return Err("Text cannot be empty".to_string());
         }
@@ -200,7 +265,7 @@ impl OpenAIClient {
             } else {
                 Some(self.data.config.instructions.clone())
             },
-            response_format: Some("mp3".to_string()),
+            response_format: Some(self.data.config.response_format.as_str().to_string()),
             speed: if (self.data.config.speed - 1.0).abs() < 0.001 {
                 None
             } else {
@@ -261,7 +326,7 @@ impl OpenAIClient {
 
         eprintln!("[OpenAI] Received {} bytes of audio data, content-type: {}", audio_data.len(), content_type);
 
-        Ok(audio_data)
+        Ok((audio_data, self.data.config.response_format.audio_format()))
     }
 
     // Геттеры и сеттеры для настроек
@@ -297,6 +362,11 @@ impl OpenAIClient {
         let _ = self.save_file();
     }
 
+    pub fn set_response_format(&mut self, format: OpenAIResponseFormat) {
+        self.data.config.response_format = format;
+        let _ = self.save_file();
+    }
+
     pub fn get_config(&self) -> &OpenAIConfig {
         &self.data.config
     }
@@ -0,0 +1,54 @@
+//! Plugin backends: how the host talks to a plugin
+//!
+//! [`LoadedPlugin`](super::types::LoadedPlugin) keeps the host-side state (config,
+//! enabled flag, errors) and checks capabilities; the backend only performs the
+//! calls. Native plugins are DLLs called through the vtable (`dynamic.rs`),
+//! process plugins are executables spoken to over JSON-RPC (`process.rs`).
+
+use super::routing::TextMessage;
+use super::types::{AudioClip, PluginEvent, PluginVoice, SynthesizedAudio};
+use plugins_api::PluginStatus;
+
+/// Calls into a loaded plugin
+///
+/// Only operations covered by the plugin's capabilities are called.
+pub trait PluginBackend: Send {
+    /// Plugin API shown in the UI, e.g. "3.6"
+    fn api_version(&self) -> String;
+
+    fn check_status(&self) -> PluginStatus;
+
+    /// Last error text reported by the plugin itself
+    fn plugin_error(&self) -> Option<String>;
+
+    /// Free-form status text reported by the plugin
+    fn status_detail(&self) -> Option<String>;
+
+    fn set_config(&mut self, config: &serde_json::Value) -> Result<(), String>;
+
+    fn on_text(&mut self, text: &str) -> Result<(), String>;
+
+    /// Spoken text with its details; backends that can't pass them on get only the text
+    fn on_message(&mut self, message: &TextMessage) -> Result<(), String> {
+        self.on_text(&message.text)
+    }
+
+    /// Returns `Ok(None)` if the plugin left the text unchanged
+    fn transform_text(&mut self, _text: &str) -> Result<Option<String>, String> {
+        Ok(None)
+    }
+
+    fn voices(&self) -> Vec<PluginVoice> {
+        Vec::new()
+    }
+
+    fn synthesize(&mut self, _text: &str, _voice: &str) -> Result<SynthesizedAudio, String> {
+        Err("Plugin is not a TTS provider".to_string())
+    }
+
+    fn on_audio(&mut self, _clip: &AudioClip) -> Result<(), String> {
+        Ok(())
+    }
+
+    fn on_event(&self, _event: &PluginEvent) {}
+}
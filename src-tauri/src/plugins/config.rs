@@ -1,6 +1,6 @@
 //! Plugin configuration management
 
-use super::types::{LoadedPlugin, PluginsConfigFile};
+use super::types::{PluginInfo, PluginsConfigFile};
 use std::fs;
 use std::path::PathBuf;
 
@@ -55,40 +55,79 @@ impl PluginConfigManager {
     }
 
     /// Get saved config for a plugin
-    pub fn get_plugin_config(&self, name: &str) -> Option<super::types::PluginConfig> {
+    ///
+    /// Settings are keyed by plugin id; files written before plugins had ids
+    /// keyed them by name, those entries are picked up until the next save.
+    pub fn get_plugin_config(&self, id: &str, name: &str) -> Option<super::types::PluginConfig> {
         let config = self.load_config().ok()?;
-        config.plugins.get(name).cloned()
+        config.plugins.get(id).or_else(|| config.plugins.get(name)).cloned()
     }
 
     /// Save config for a single plugin
-    pub fn save_plugin_config(&self, plugin: &LoadedPlugin) -> Result<(), String> {
+    pub fn save_plugin_config(&self, plugin: &PluginInfo) -> Result<(), String> {
         let mut config = self.load_config()?;
-        config.plugins.insert(
-            plugin.name.clone(),
-            super::types::PluginConfig {
-                enabled: plugin.enabled,
-                config: plugin.config.clone(),
-                last_error: plugin.last_error.clone(),
-            },
-        );
+        insert_plugin(&mut config, plugin);
         self.save_config(&config)
     }
 
-    /// Save all plugin states
-    pub fn save_all_from_manager(&self, plugins: &[LoadedPlugin]) -> Result<(), String> {
-        let mut config = PluginsConfigFile::default();
+    /// Get user-defined transform order
+    pub fn get_transform_order(&self) -> Vec<String> {
+        self.load_config()
+            .map(|c| c.transform_order)
+            .unwrap_or_default()
+    }
+
+    /// Save transform order
+    pub fn save_transform_order(&self, order: &[String]) -> Result<(), String> {
+        let mut config = self.load_config()?;
+        config.transform_order = order.to_vec();
+        self.save_config(&config)
+    }
+
+    /// Get user-defined execution order
+    pub fn get_plugin_order(&self) -> Vec<String> {
+        self.load_config()
+            .map(|c| c.plugin_order)
+            .unwrap_or_default()
+    }
+
+    /// Save execution order
+    pub fn save_plugin_order(&self, order: &[String]) -> Result<(), String> {
+        let mut config = self.load_config()?;
+        config.plugin_order = order.to_vec();
+        self.save_config(&config)
+    }
+
+    /// Save all plugin states (plugin orders are kept)
+    pub fn save_all_from_manager(&self, plugins: &[PluginInfo]) -> Result<(), String> {
+        let saved = self.load_config().unwrap_or_default();
+        let mut config = PluginsConfigFile {
+            transform_order: saved.transform_order,
+            plugin_order: saved.plugin_order,
+            ..Default::default()
+        };
 
         for plugin in plugins {
-            config.plugins.insert(
-                plugin.name.clone(),
-                super::types::PluginConfig {
-                    enabled: plugin.enabled,
-                    config: plugin.config.clone(),
-                    last_error: plugin.last_error.clone(),
-                },
-            );
+            insert_plugin(&mut config, plugin);
         }
 
         self.save_config(&config)
     }
 }
+
+/// Store a plugin's state under its id, replacing an entry saved under its name
+fn insert_plugin(config: &mut PluginsConfigFile, plugin: &PluginInfo) {
+    if plugin.name != plugin.id {
+        config.plugins.remove(&plugin.name);
+    }
+    config.plugins.insert(
+        plugin.id.clone(),
+        super::types::PluginConfig {
+            enabled: plugin.enabled,
+            config: plugin.config.clone(),
+            last_error: plugin.last_error.clone(),
+            failure_policy: plugin.failure_policy.clone(),
+            routing: plugin.routing.clone(),
+        },
+    );
+}
@@ -1,70 +1,403 @@
 //! Dynamic library loader for plugins
 
-use super::types::LoadedPlugin;
+use super::backend::PluginBackend;
+use super::host::{HostServicesHandle, PluginHost};
+use super::routing::TextMessage;
+use super::types::{AudioClip, LoadedPlugin, PluginEvent, PluginVoice, SynthesizedAudio};
 use libloading::{Library, Symbol};
-use plugins_api::{c_str_to_string, PluginVTable};
+use plugins_api::{
+    capabilities, check_api_version, read_vtable, unpack_api_version,
+    GetPluginVTableFn, PluginApiVersionFn, PluginAudio, PluginAudioBuffer, PluginStatus, PluginStr,
+    PluginString, PluginVTable, PLUGIN_API_VERSION_SYMBOL, PLUGIN_VTABLE_SYMBOL,
+    TRANSFORM_REPLACED, TRANSFORM_UNCHANGED,
+};
+use std::ffi::c_void;
 use std::path::{Path, PathBuf};
 
-/// Function pointer type for getting plugin vtable
-type GetVTable = extern "C" fn() -> *const PluginVTable;
-
 /// Load a single plugin from a DLL file
-pub fn load_plugin(path: &Path) -> Result<LoadedPlugin, String> {
+///
+/// `id` is the plugin id its host services (data folder, key-value store) belong to.
+pub fn load_plugin(path: &Path, id: &str, host: &PluginHost) -> Result<LoadedPlugin, String> {
     unsafe {
         // Load the DLL
         let library = Library::new(path)
             .map_err(|e| format!("Failed to load DLL: {}", e))?;
 
+        // Check ABI version before touching the vtable
+        let api_version_fn: Symbol<PluginApiVersionFn> = library.get(PLUGIN_API_VERSION_SYMBOL)
+            .map_err(|_| "Missing plugin_api_version export (plugin built for API v1, rebuild it against the current plugins-api)".to_string())?;
+        let api_version = api_version_fn();
+        check_api_version(api_version)?;
+
         // Get the get_plugin_vtable function
-        let get_vtable: Symbol<GetVTable> = library.get(b"get_plugin_vtable")
+        let get_vtable: Symbol<GetPluginVTableFn> = library.get(PLUGIN_VTABLE_SYMBOL)
             .map_err(|e| format!("Missing get_plugin_vtable export: {}", e))?;
 
-        // Get the vtable pointer
-        let vtable_ptr = get_vtable();
-        if vtable_ptr.is_null() {
-            return Err("get_plugin_vtable returned null".to_string());
-        }
+        // Copy the vtable (entries the plugin doesn't know about stay empty)
+        let vtable = read_vtable(get_vtable())?;
 
-        // Copy the vtable
-        let vtable = *vtable_ptr;
+        // Only trust capabilities backed by functions the plugin actually provides
+        let mut capabilities = vtable.capabilities;
+        if vtable.set_config.is_none() {
+            capabilities &= !capabilities::CONFIG;
+        }
+        if vtable.check_status.is_none() {
+            capabilities &= !capabilities::STATUS;
+        }
+        if vtable.on_text.is_none() {
+            capabilities &= !capabilities::TEXT;
+        }
+        if vtable.transform_text.is_none() {
+            capabilities &= !capabilities::TRANSFORM;
+        }
+        let has_event_handlers = vtable.on_tts_started.is_some()
+            || vtable.on_tts_completed.is_some()
+            || vtable.on_tts_failed.is_some()
+            || vtable.on_tts_cancelled.is_some()
+            || vtable.on_provider_changed.is_some()
+            || vtable.on_blocking_changed.is_some();
+        if !has_event_handlers {
+            capabilities &= !capabilities::EVENTS;
+        }
+        if vtable.on_audio.is_none() {
+            capabilities &= !capabilities::AUDIO;
+        }
+        if vtable.synthesize.is_none() || vtable.free_audio.is_none() {
+            capabilities &= !capabilities::SYNTH;
+        }
 
         // Get plugin info (name, version, schema)
-        let name = c_str_to_string((vtable.name)());
-        let version = c_str_to_string((vtable.version)());
+        let name = vtable.take_string((vtable.name)());
+        let version = vtable.take_string((vtable.version)());
 
-        let schema_ptr = (vtable.get_config_schema)();
-        let schema_json = c_str_to_string(schema_ptr);
-        let config_schema: serde_json::Value = serde_json::from_str(&schema_json)
-            .unwrap_or_else(|_| serde_json::json!({}));
+        let config_schema = match vtable.get_config_schema {
+            Some(get_config_schema) => {
+                let schema_json = vtable.take_string(get_config_schema());
+                serde_json::from_str(&schema_json).unwrap_or_else(|_| serde_json::json!({}))
+            }
+            None => serde_json::json!({}),
+        };
 
-        // Initialize the plugin
-        let data = (vtable.init)();
+        // Initialize the plugin (with host services if it wants them)
+        let (data, host_services) = match vtable.init_with_host {
+            Some(init_with_host) => {
+                let services = host.services_for(id);
+                (init_with_host(services.as_ptr()), Some(services))
+            }
+            None => ((vtable.init)(), None),
+        };
         if data.is_null() {
             return Err("Plugin init returned null".to_string());
         }
 
-        Ok(LoadedPlugin {
+        let (major, minor) = unpack_api_version(api_version);
+        eprintln!("[Plugins] Loaded '{}' v{} (API {}.{}, capabilities: {:?})",
+            name, version, major, minor, capabilities::names(capabilities));
+
+        let backend = NativePlugin {
+            name: name.clone(),
             library,
             vtable,
             data,
-            name,
-            version,
-            config_schema,
-            config: serde_json::json!({}),
-            enabled: false,
-            last_error: None,
+            api_version,
+            host_services,
+        };
+        let mut plugin = LoadedPlugin::new(Box::new(backend), path.to_path_buf(), name, version, capabilities, config_schema);
+        plugin.id = id.to_string();
+        Ok(plugin)
+    }
+}
+
+/// Plugin DLL called through its vtable
+pub struct NativePlugin {
+    /// Plugin name (for log messages)
+    name: String,
+    /// Library handle (kept to prevent unloading)
+    #[allow(dead_code)]
+    library: Library,
+    vtable: PluginVTable,
+    /// Opaque plugin data pointer
+    data: *mut c_void,
+    /// Packed ABI version the plugin was built against
+    api_version: u32,
+    /// Services passed to `init_with_host` (kept alive until `destroy` returns)
+    #[allow(dead_code)]
+    host_services: Option<HostServicesHandle>,
+}
+
+// SAFETY: NativePlugin is Send because it is owned by a single worker thread
+// (see `worker.rs`) and the plugin vtable functions are only called from there
+unsafe impl Send for NativePlugin {}
+
+impl NativePlugin {
+    /// Read an optional plugin-owned string (None if the function is absent or returns empty)
+    fn plugin_string(&self, f: Option<extern "C" fn(*mut c_void) -> PluginString>) -> Option<String> {
+        let f = f?;
+        let text = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            // SAFETY: the string comes from this vtable and is freed exactly once
+            unsafe { self.vtable.take_string(f(self.data)) }
+        }))
+        .ok()?;
+        let text = text.trim().to_string();
+        if text.is_empty() {
+            None
+        } else {
+            Some(text)
+        }
+    }
+
+    /// Error for a failed call: the plugin's own message if it has one, otherwise the return code
+    fn call_error(&self, function: &str, code: i32) -> String {
+        self.plugin_error()
+            .unwrap_or_else(|| format!("Plugin {} returned error code: {}", function, code))
+    }
+}
+
+impl PluginBackend for NativePlugin {
+    fn api_version(&self) -> String {
+        let (major, minor) = unpack_api_version(self.api_version);
+        format!("{}.{}", major, minor)
+    }
+
+    fn check_status(&self) -> PluginStatus {
+        match self.vtable.check_status {
+            Some(check_status) => check_status(self.data),
+            None => PluginStatus::Ok,
+        }
+    }
+
+    fn plugin_error(&self) -> Option<String> {
+        self.plugin_string(self.vtable.get_last_error)
+    }
+
+    fn status_detail(&self) -> Option<String> {
+        self.plugin_string(self.vtable.get_status_detail)
+    }
+
+    fn set_config(&mut self, config: &serde_json::Value) -> Result<(), String> {
+        let set_config = match self.vtable.set_config {
+            Some(f) => f,
+            None => return Ok(()),
+        };
+
+        let json = serde_json::to_string(config)
+            .map_err(|e| format!("Failed to serialize config: {}", e))?;
+
+        let result = set_config(self.data, PluginStr::new(&json));
+
+        if result == 0 {
+            Ok(())
+        } else {
+            Err(self.call_error("set_config", result))
+        }
+    }
+
+    fn on_text(&mut self, text: &str) -> Result<(), String> {
+        let on_text = match self.vtable.on_text {
+            Some(f) => f,
+            None => return Ok(()),
+        };
+
+        // Use catch_unwind to prevent plugin panics from crashing the app
+        std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            let result = on_text(self.data, PluginStr::new(text));
+
+            if result == 0 {
+                Ok(())
+            } else {
+                Err(self.call_error("on_text", result))
+            }
+        }))
+        .unwrap_or_else(|_| {
+            Err("Plugin panicked during on_text".to_string())
+        })
+    }
+
+    fn on_message(&mut self, message: &TextMessage) -> Result<(), String> {
+        // Plugins built before API 3.6 only get the text
+        let on_message = match self.vtable.on_message {
+            Some(f) => f,
+            None => return self.on_text(&message.text),
+        };
+        let details = message.details().to_string();
+
+        std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            let result = on_message(self.data, PluginStr::new(&message.text), PluginStr::new(&details));
+
+            if result == 0 {
+                Ok(())
+            } else {
+                Err(self.call_error("on_message", result))
+            }
+        }))
+        .unwrap_or_else(|_| {
+            Err("Plugin panicked during on_message".to_string())
+        })
+    }
+
+    fn transform_text(&mut self, text: &str) -> Result<Option<String>, String> {
+        let transform_text = match self.vtable.transform_text {
+            Some(f) => f,
+            None => return Ok(None),
+        };
+
+        std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            let mut out = PluginString::empty();
+            let result = transform_text(self.data, PluginStr::new(text), &mut out);
+            // SAFETY: `out` is either empty or a string returned by this plugin
+            let new_text = unsafe { self.vtable.take_string(out) };
+
+            match result {
+                TRANSFORM_REPLACED => Ok(Some(new_text)),
+                TRANSFORM_UNCHANGED => Ok(None),
+                code => Err(self.call_error("transform_text", code)),
+            }
+        }))
+        .unwrap_or_else(|_| {
+            Err("Plugin panicked during transform_text".to_string())
+        })
+    }
+
+    fn voices(&self) -> Vec<PluginVoice> {
+        let json = match self.plugin_string(self.vtable.get_voices) {
+            Some(json) => json,
+            None => return Vec::new(),
+        };
+        match serde_json::from_str(&json) {
+            Ok(voices) => voices,
+            Err(e) => {
+                eprintln!("[Plugins] Plugin '{}' returned invalid voice list: {}", self.name, e);
+                Vec::new()
+            }
+        }
+    }
+
+    fn synthesize(&mut self, text: &str, voice: &str) -> Result<SynthesizedAudio, String> {
+        let (synthesize, free_audio) = match (self.vtable.synthesize, self.vtable.free_audio) {
+            (Some(synthesize), Some(free_audio)) => (synthesize, free_audio),
+            _ => return Err(format!("Plugin '{}' is not a TTS provider", self.name)),
+        };
+
+        std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            let mut out = PluginAudioBuffer::empty();
+            let result = synthesize(self.data, PluginStr::new(text), PluginStr::new(voice), &mut out);
+
+            // Copy the audio and give the buffer back to the plugin
+            // SAFETY: `out` is either empty or a buffer returned by this plugin
+            let data = unsafe { out.to_vec() };
+            let audio = SynthesizedAudio {
+                data,
+                format: out.format,
+                sample_rate: out.sample_rate,
+                channels: out.channels,
+            };
+            if !out.ptr.is_null() {
+                free_audio(out);
+            }
+
+            if result == 0 {
+                Ok(audio)
+            } else {
+                Err(self.call_error("synthesize", result))
+            }
+        }))
+        .unwrap_or_else(|_| {
+            Err("Plugin panicked during synthesize".to_string())
         })
     }
+
+    fn on_audio(&mut self, clip: &AudioClip) -> Result<(), String> {
+        let on_audio = match self.vtable.on_audio {
+            Some(f) => f,
+            None => return Ok(()),
+        };
+
+        // Borrowed from `clip`, which outlives the call
+        let audio = PluginAudio {
+            data: clip.data.as_ptr(),
+            len: clip.data.len(),
+            format: PluginStr::new(&clip.format),
+            sample_rate: clip.sample_rate,
+            channels: clip.channels,
+            duration_ms: clip.duration_ms,
+            message_id: PluginStr::new(&clip.message_id),
+        };
+
+        std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            let result = on_audio(self.data, &audio);
+
+            if result == 0 {
+                Ok(())
+            } else {
+                Err(self.call_error("on_audio", result))
+            }
+        }))
+        .unwrap_or_else(|_| {
+            Err("Plugin panicked during on_audio".to_string())
+        })
+    }
+
+    fn on_event(&self, event: &PluginEvent) {
+        let vtable = &self.vtable;
+        let data = self.data;
+        let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| match event {
+            PluginEvent::TtsStarted { id, text } => {
+                if let Some(f) = vtable.on_tts_started {
+                    f(data, PluginStr::new(id), PluginStr::new(text));
+                }
+            }
+            PluginEvent::TtsCompleted { id, text } => {
+                if let Some(f) = vtable.on_tts_completed {
+                    f(data, PluginStr::new(id), PluginStr::new(text));
+                }
+            }
+            PluginEvent::TtsFailed { id, text, error } => {
+                if let Some(f) = vtable.on_tts_failed {
+                    f(data, PluginStr::new(id), PluginStr::new(text), PluginStr::new(error));
+                }
+            }
+            PluginEvent::TtsCancelled { id, text } => {
+                if let Some(f) = vtable.on_tts_cancelled {
+                    f(data, PluginStr::new(id), PluginStr::new(text));
+                }
+            }
+            PluginEvent::ProviderChanged(provider) => {
+                if let Some(f) = vtable.on_provider_changed {
+                    f(data, PluginStr::new(provider));
+                }
+            }
+            PluginEvent::BlockingChanged(blocking) => {
+                if let Some(f) = vtable.on_blocking_changed {
+                    f(data, *blocking);
+                }
+            }
+        }));
+
+        if result.is_err() {
+            eprintln!("[Plugins] Plugin '{}' panicked while handling {:?}", self.name, event);
+        }
+    }
+}
+
+impl Drop for NativePlugin {
+    fn drop(&mut self) {
+        (self.vtable.destroy)(self.data);
+    }
 }
 
-/// Scan directory for plugin DLLs
+/// Whether a file is a library for this platform (`.dll`, `.so` or `.dylib`)
+pub fn is_plugin_library(path: &Path) -> bool {
+    path.extension().and_then(|s| s.to_str()) == Some(std::env::consts::DLL_EXTENSION)
+}
+
+/// Scan directory for plugin libraries
 pub fn scan_plugins_dir(dir: &Path) -> Vec<PathBuf> {
     let mut plugins = Vec::new();
 
     if let Ok(entries) = std::fs::read_dir(dir) {
         for entry in entries.flatten() {
             let path = entry.path();
-            if path.extension().and_then(|s| s.to_str()) == Some("dll") {
+            if is_plugin_library(&path) {
                 plugins.push(path);
             }
         }
@@ -74,9 +407,82 @@ pub fn scan_plugins_dir(dir: &Path) -> Vec<PathBuf> {
 }
 
 #[cfg(test)]
-mod tests {
+pub(super) mod tests {
     use super::*;
 
+    fn test_host() -> PluginHost {
+        PluginHost::new(std::env::temp_dir().join("plugin-host-test"))
+    }
+
+    /// Repository root (tests run from `src-tauri` and, in the host simulator,
+    /// from `plugins/host-simulator`)
+    pub(in crate::plugins) fn repo_root() -> PathBuf {
+        Path::new(env!("CARGO_MANIFEST_DIR"))
+            .ancestors()
+            .find(|dir| dir.join("plugins").join("Cargo.toml").is_file())
+            .map(Path::to_path_buf)
+            .expect("plugins workspace not found above the crate")
+    }
+
+    /// Example plugin from the `plugins` workspace, built first (None if the build fails)
+    pub(in crate::plugins) fn example_plugin() -> Option<PathBuf> {
+        let workspace = repo_root().join("plugins");
+        let target = workspace.join("target");
+        let cargo = std::env::var("CARGO").unwrap_or_else(|_| "cargo".to_string());
+        let built = std::process::Command::new(cargo)
+            .args(["build", "-p", "file-logger-plugin", "--target-dir"])
+            .arg(&target)
+            .current_dir(&workspace)
+            .status()
+            .map(|status| status.success())
+            .unwrap_or(false);
+        if !built {
+            eprintln!("Failed to build the example plugin in {}", workspace.display());
+            return None;
+        }
+
+        let path = target.join("debug").join(libloading::library_filename("file_logger_plugin"));
+        if path.exists() {
+            Some(path)
+        } else {
+            None
+        }
+    }
+
+    #[test]
+    fn test_load_example_plugin_repeatedly() {
+        let path = example_plugin().expect("example plugin was not built");
+        let temp = tempfile::tempdir().unwrap();
+        let log_path = temp.path().join("log.txt");
+
+        for i in 0..50 {
+            let mut plugin = load_plugin(&path, "file-logger", &test_host()).unwrap();
+            assert_eq!(plugin.name, "File Logger");
+            assert_eq!(plugin.id, "file-logger");
+
+            plugin.set_config(&serde_json::json!({ "file_path": log_path })).unwrap();
+            plugin.set_enabled(true);
+            plugin.on_text(&format!("message {}", i)).unwrap();
+
+            let info = plugin.info();
+            assert!(info.status_detail.unwrap().contains("log.txt"));
+        }
+
+        let log = std::fs::read_to_string(&log_path).unwrap();
+        assert!(log.contains("message 0"));
+        assert!(log.contains("message 49"));
+    }
+
+    #[test]
+    fn test_example_plugin_error_text() {
+        let path = example_plugin().expect("example plugin was not built");
+
+        let mut plugin = load_plugin(&path, "file-logger", &test_host()).unwrap();
+        let err = plugin.set_config(&serde_json::json!({})).unwrap_err();
+        assert!(err.contains("file_path is required"));
+        assert_eq!(plugin.last_error.as_deref(), Some(err.as_str()));
+    }
+
     #[test]
     fn test_scan_empty_dir() {
         let temp = tempfile::tempdir().unwrap();
@@ -84,14 +490,25 @@ mod tests {
         assert_eq!(plugins.len(), 0);
     }
 
+    #[test]
+    fn test_load_not_a_library() {
+        let temp = tempfile::tempdir().unwrap();
+        let path = temp.path().join("broken.dll");
+        std::fs::write(&path, b"fake dll").unwrap();
+
+        let err = load_plugin(&path, "file-logger", &test_host()).err().unwrap();
+        assert!(err.starts_with("Failed to load DLL"));
+    }
+
     #[test]
     fn test_scan_with_dll() {
         let temp = tempfile::tempdir().unwrap();
-        std::fs::write(temp.path().join("test.dll"), b"fake dll").unwrap();
+        let library = libloading::library_filename("test");
+        std::fs::write(temp.path().join(&library), b"fake dll").unwrap();
         std::fs::write(temp.path().join("test.txt"), b"not a dll").unwrap();
 
         let plugins = scan_plugins_dir(temp.path());
         assert_eq!(plugins.len(), 1);
-        assert!(plugins[0].ends_with("test.dll"));
+        assert!(plugins[0].ends_with(&library));
     }
 }
@@ -0,0 +1,330 @@
+//! Host services offered to plugins (log, TTS queue, data dir, notifications, key-value store)
+
+use plugins_api::{log_level, HostServices, PluginStr, PluginString, KV_FOUND, KV_NOT_FOUND};
+use std::collections::{HashMap, VecDeque};
+use std::ffi::c_void;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// Key-value store file inside the plugin's data directory
+const KV_FILE: &str = "kv.json";
+
+/// How many texts one plugin may enqueue per [`ENQUEUE_WINDOW`]
+pub const ENQUEUE_LIMIT: usize = 10;
+
+/// Window of the per-plugin enqueue limit
+pub const ENQUEUE_WINDOW: Duration = Duration::from_secs(10);
+
+/// Request from a plugin that has to be handled by the app
+#[derive(Debug, Clone)]
+pub enum HostRequest {
+    /// Add text to the TTS queue
+    EnqueueText { plugin: String, text: String },
+    /// Show a notification in the UI
+    Notification { plugin: String, title: String, body: String },
+    /// A plugin was disabled after a failed call nobody was waiting for
+    PluginFailed { plugin: String, error: String },
+    /// A plugin disabled by its failure policy is OK again and was enabled
+    PluginRecovered { plugin: String },
+}
+
+/// Delivers plugin requests to the app (may be called from any thread)
+pub type HostRequestSender = Arc<dyn Fn(HostRequest) + Send + Sync>;
+
+/// Creates per-plugin host contexts
+#[derive(Clone)]
+pub struct PluginHost {
+    /// Parent of the per-plugin data directories
+    data_root: PathBuf,
+    sender: Option<HostRequestSender>,
+}
+
+impl PluginHost {
+    pub fn new(data_root: PathBuf) -> Self {
+        Self {
+            data_root,
+            sender: None,
+        }
+    }
+
+    /// Set where plugin requests (enqueue, notifications) are delivered
+    pub fn with_sender(mut self, sender: HostRequestSender) -> Self {
+        self.sender = Some(sender);
+        self
+    }
+
+    /// Where plugin requests are delivered (None if nobody listens)
+    pub fn sender(&self) -> Option<HostRequestSender> {
+        self.sender.clone()
+    }
+
+    /// Data folder of one plugin (not created yet)
+    pub fn data_dir(&self, plugin: &str) -> PathBuf {
+        self.data_root.join(dir_name(plugin))
+    }
+
+    /// Host services for one plugin
+    pub fn services_for(&self, plugin: &str) -> HostServicesHandle {
+        let data_dir = self.data_dir(plugin);
+        let kv = std::fs::read_to_string(data_dir.join(KV_FILE))
+            .ok()
+            .and_then(|content| serde_json::from_str(&content).ok())
+            .unwrap_or_default();
+
+        let context = Box::new(HostContext {
+            plugin: plugin.to_string(),
+            data_dir,
+            kv: Mutex::new(kv),
+            sender: self.sender.clone(),
+        });
+        let services = Box::new(HostServices {
+            struct_size: std::mem::size_of::<HostServices>(),
+            context: &*context as *const HostContext as *mut c_void,
+            log: host_log,
+            enqueue_text: host_enqueue_text,
+            get_data_dir: host_get_data_dir,
+            emit_notification: host_emit_notification,
+            kv_get: host_kv_get,
+            kv_set: host_kv_set,
+            kv_delete: host_kv_delete,
+            free_string: host_free_string,
+        });
+
+        HostServicesHandle { context, services }
+    }
+}
+
+/// Host services of a loaded plugin; must outlive the plugin's `destroy`
+pub struct HostServicesHandle {
+    #[allow(dead_code)]
+    context: Box<HostContext>,
+    services: Box<HostServices>,
+}
+
+impl HostServicesHandle {
+    /// Pointer passed to `init_with_host`
+    pub fn as_ptr(&self) -> *const HostServices {
+        &*self.services
+    }
+}
+
+/// Per-plugin state behind `HostServices::context`
+struct HostContext {
+    plugin: String,
+    data_dir: PathBuf,
+    kv: Mutex<HashMap<String, String>>,
+    sender: Option<HostRequestSender>,
+}
+
+impl HostContext {
+    /// SAFETY: `context` must come from a live `HostServicesHandle`
+    unsafe fn from_ptr<'a>(context: *mut c_void) -> &'a HostContext {
+        &*(context as *const HostContext)
+    }
+
+    fn send(&self, request: HostRequest) -> bool {
+        match self.sender {
+            Some(ref sender) => {
+                sender(request);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Write the key-value store to disk
+    fn save_kv(&self, kv: &HashMap<String, String>) -> Result<(), String> {
+        std::fs::create_dir_all(&self.data_dir)
+            .map_err(|e| format!("Failed to create plugin data dir: {}", e))?;
+        let content = serde_json::to_string_pretty(kv)
+            .map_err(|e| format!("Failed to serialize plugin store: {}", e))?;
+        std::fs::write(self.data_dir.join(KV_FILE), content)
+            .map_err(|e| format!("Failed to write plugin store: {}", e))
+    }
+
+    fn update_kv(&self, f: impl FnOnce(&mut HashMap<String, String>)) -> i32 {
+        let mut kv = self.kv.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        f(&mut kv);
+        match self.save_kv(&kv) {
+            Ok(()) => 0,
+            Err(e) => {
+                eprintln!("[Plugin {}] {}", self.plugin, e);
+                -1
+            }
+        }
+    }
+}
+
+/// Per-plugin limit on texts added to the TTS queue, so a plugin that enqueues
+/// in a loop can't flood the queue
+#[derive(Default)]
+pub struct EnqueueLimiter {
+    /// When each plugin's recent texts were accepted
+    accepted: HashMap<String, VecDeque<Instant>>,
+}
+
+impl EnqueueLimiter {
+    /// Whether `plugin` may enqueue a text now (the text is counted if so)
+    pub fn allow(&mut self, plugin: &str, now: Instant) -> bool {
+        let accepted = self.accepted.entry(plugin.to_string()).or_default();
+        while let Some(&oldest) = accepted.front() {
+            if now.saturating_duration_since(oldest) < ENQUEUE_WINDOW {
+                break;
+            }
+            accepted.pop_front();
+        }
+        if accepted.len() >= ENQUEUE_LIMIT {
+            return false;
+        }
+        accepted.push_back(now);
+        true
+    }
+}
+
+/// Directory name for a plugin (path separators and other odd characters replaced)
+fn dir_name(plugin: &str) -> String {
+    plugin
+        .chars()
+        .map(|c| if c.is_alphanumeric() || c == '-' || c == '_' { c } else { '_' })
+        .collect()
+}
+
+extern "C" fn host_log(context: *mut c_void, level: u32, message: PluginStr) {
+    let context = unsafe { HostContext::from_ptr(context) };
+    let level = match level {
+        log_level::ERROR => "ERROR",
+        log_level::WARN => "WARN",
+        log_level::INFO => "INFO",
+        _ => "DEBUG",
+    };
+    eprintln!("[Plugin {}] {}: {}", context.plugin, level, unsafe { message.as_str() });
+}
+
+extern "C" fn host_enqueue_text(context: *mut c_void, text: PluginStr) -> i32 {
+    let context = unsafe { HostContext::from_ptr(context) };
+    let text = unsafe { text.as_str() }.into_owned();
+    if text.trim().is_empty() {
+        return -1;
+    }
+
+    let request = HostRequest::EnqueueText {
+        plugin: context.plugin.clone(),
+        text,
+    };
+    if context.send(request) {
+        0
+    } else {
+        -1
+    }
+}
+
+extern "C" fn host_get_data_dir(context: *mut c_void) -> PluginString {
+    let context = unsafe { HostContext::from_ptr(context) };
+    if let Err(e) = std::fs::create_dir_all(&context.data_dir) {
+        eprintln!("[Plugin {}] Failed to create data dir: {}", context.plugin, e);
+    }
+    PluginString::from_string(context.data_dir.to_string_lossy().to_string())
+}
+
+extern "C" fn host_emit_notification(context: *mut c_void, title: PluginStr, body: PluginStr) {
+    let context = unsafe { HostContext::from_ptr(context) };
+    context.send(HostRequest::Notification {
+        plugin: context.plugin.clone(),
+        title: unsafe { title.as_str() }.into_owned(),
+        body: unsafe { body.as_str() }.into_owned(),
+    });
+}
+
+extern "C" fn host_kv_get(context: *mut c_void, key: PluginStr, out: *mut PluginString) -> i32 {
+    let context = unsafe { HostContext::from_ptr(context) };
+    let key = unsafe { key.as_str() };
+    let kv = context.kv.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+
+    match kv.get(key.as_ref()) {
+        Some(value) => {
+            if !out.is_null() {
+                unsafe { *out = PluginString::from_string(value.clone()) };
+            }
+            KV_FOUND
+        }
+        None => KV_NOT_FOUND,
+    }
+}
+
+extern "C" fn host_kv_set(context: *mut c_void, key: PluginStr, value: PluginStr) -> i32 {
+    let context = unsafe { HostContext::from_ptr(context) };
+    let key = unsafe { key.as_str() }.into_owned();
+    let value = unsafe { value.as_str() }.into_owned();
+    context.update_kv(|kv| {
+        kv.insert(key, value);
+    })
+}
+
+extern "C" fn host_kv_delete(context: *mut c_void, key: PluginStr) -> i32 {
+    let context = unsafe { HostContext::from_ptr(context) };
+    let key = unsafe { key.as_str() };
+    context.update_kv(|kv| {
+        kv.remove(key.as_ref());
+    })
+}
+
+extern "C" fn host_free_string(_context: *mut c_void, s: PluginString) {
+    // SAFETY: the buffer was allocated by `PluginString::from_string` in this module
+    drop(unsafe { s.into_string() });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_enqueue_limit() {
+        let mut limiter = EnqueueLimiter::default();
+        let start = Instant::now();
+        for _ in 0..ENQUEUE_LIMIT {
+            assert!(limiter.allow("chat", start));
+        }
+        assert!(!limiter.allow("chat", start + Duration::from_secs(1)));
+        // Other plugins have their own limit
+        assert!(limiter.allow("timer", start + Duration::from_secs(1)));
+        // Texts older than the window no longer count
+        for _ in 0..ENQUEUE_LIMIT {
+            assert!(limiter.allow("chat", start + ENQUEUE_WINDOW));
+        }
+        assert!(!limiter.allow("chat", start + ENQUEUE_WINDOW));
+    }
+
+    #[test]
+    fn test_host_services() {
+        let temp = tempfile::tempdir().unwrap();
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let sink = Arc::clone(&requests);
+        let host = PluginHost::new(temp.path().to_path_buf())
+            .with_sender(Arc::new(move |request| sink.lock().unwrap().push(request)));
+
+        let handle = host.services_for("Chat/Reader");
+        let services = unsafe { *handle.as_ptr() };
+
+        assert!(services.data_dir().ends_with("Chat_Reader"));
+        assert!(services.kv_set("token", "abc"));
+        assert_eq!(services.kv_get("token").as_deref(), Some("abc"));
+        assert_eq!(services.kv_get("missing"), None);
+
+        // Enqueue works from plugin-owned threads
+        std::thread::spawn(move || services.enqueue_text("hello")).join().unwrap();
+        assert!(!services.enqueue_text("  "));
+        assert!(matches!(
+            requests.lock().unwrap().as_slice(),
+            [HostRequest::EnqueueText { plugin, text }] if plugin == "Chat/Reader" && text == "hello"
+        ));
+
+        // The store survives a restart
+        drop(handle);
+        let handle = host.services_for("Chat/Reader");
+        let services = unsafe { *handle.as_ptr() };
+        assert_eq!(services.kv_get("token").as_deref(), Some("abc"));
+        assert!(services.kv_delete("token"));
+        assert_eq!(services.kv_get("token"), None);
+    }
+}
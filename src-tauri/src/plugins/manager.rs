@@ -1,12 +1,43 @@
 //! Plugin manager - handles loading, config, and broadcasting
+//!
+//! Plugins run on their own worker threads (see `worker.rs`): broadcasts are
+//! queued and return immediately, calls that need an answer have a timeout.
 
 use super::config::PluginConfigManager;
-use super::dynamic::{load_plugin, scan_plugins_dir};
-use super::types::{LoadedPlugin, PluginInfo};
+use super::dynamic::{is_plugin_library, load_plugin, scan_plugins_dir};
+use super::host::PluginHost;
+use super::manifest::{default_plugin_id, scan_manifests, PluginManifest, MANIFEST_FILE};
+use super::policy::FailurePolicy;
+use super::process::load_process_plugin;
+use super::routing::{RoutingRules, TextMessage};
+use super::schema;
+use super::types::{
+    AudioClip, ConfigError, FailedPlugin, LoadedPlugin, PluginEvent, PluginInfo, PluginMetadata, PluginVoice,
+    TransformReport, TransformStep,
+};
+use super::worker::PluginWorker;
+use plugins_api::capabilities;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime};
+
+/// How long unloading waits for a plugin to finish its current call
+const UNLOAD_TIMEOUT: Duration = Duration::from_secs(5);
 
 /// Manages all loaded plugins
 pub struct PluginManager {
-    plugins: Vec<LoadedPlugin>,
+    plugins: Vec<PluginWorker>,
+    /// Libraries that were found but failed to load (shown in UI with the reason)
+    failed: Vec<FailedPlugin>,
+    /// User-defined order of text transforms (plugin ids)
+    transform_order: Vec<String>,
+    /// User-defined order in which plugins get messages, audio and events (plugin ids)
+    plugin_order: Vec<String>,
+    /// Services offered to plugins that use `init_with_host`
+    host: PluginHost,
+    /// Modification time of every library/manifest seen, to tell real changes
+    /// from watcher events caused by the manager itself
+    stamps: HashMap<PathBuf, Option<SystemTime>>,
     config_manager: PluginConfigManager,
 }
 
@@ -14,122 +45,719 @@ impl PluginManager {
     /// Create new plugin manager
     pub fn new(plugins_dir: std::path::PathBuf) -> Result<Self, String> {
         let config_manager = PluginConfigManager::new(plugins_dir.clone())?;
+        let transform_order = config_manager.get_transform_order();
+        let plugin_order = config_manager.get_plugin_order();
         Ok(Self {
             plugins: Vec::new(),
+            failed: Vec::new(),
+            transform_order,
+            plugin_order,
+            host: PluginHost::new(plugins_dir.join("data")),
+            stamps: HashMap::new(),
             config_manager,
         })
     }
 
+    /// Set host services for plugins loaded afterwards
+    pub fn set_host(&mut self, host: PluginHost) {
+        self.host = host;
+    }
+
     /// Load all plugins from directory
     pub fn load_all(&mut self) -> Result<usize, String> {
-        let plugin_paths = scan_plugins_dir(&self.config_manager.plugins_dir());
         let mut loaded = 0;
+        for source in self.scan_sources() {
+            if self.load_source(&source).is_ok() {
+                loaded += 1;
+            }
+        }
+
+        // Save updated config
+        self.save_state()?;
+
+        Ok(loaded)
+    }
+
+    /// Plugin libraries and manifests in the plugins directory
+    fn scan_sources(&self) -> Vec<PathBuf> {
+        let plugins_dir = self.config_manager.plugins_dir();
+        let mut sources = scan_plugins_dir(&plugins_dir);
+        // Process plugins live in their own folders next to a manifest
+        sources.extend(scan_manifests(&plugins_dir));
+        sources
+    }
+
+    /// Load one plugin from a library or manifest and apply its saved config
+    /// A plugin that fails to load is listed with the reason
+    fn load_source(&mut self, source: &Path) -> Result<String, String> {
+        self.failed.retain(|f| f.path != source);
+        self.stamps.insert(source.to_path_buf(), source_stamp(source));
+
+        let result = load_any(source, &self.host).and_then(|mut plugin| {
+            if let Some(existing) = self.plugins.iter().find(|p| p.id() == plugin.id) {
+                return Err(format!(
+                    "A plugin with id '{}' is already loaded from {}",
+                    plugin.id,
+                    existing.source().display()
+                ));
+            }
 
-        for path in plugin_paths {
-            match load_plugin(&path) {
-                Ok(mut plugin) => {
-                    // Load saved config
-                    if let Some(saved_config) = self.config_manager.get_plugin_config(&plugin.name) {
-                        plugin.enabled = saved_config.enabled;
-                        plugin.last_error = saved_config.last_error;
-
-                        // Set config if available
-                        if !saved_config.config.is_null() {
-                            let _ = plugin.set_config(&saved_config.config);
-                        }
-                    }
-
-                    self.plugins.push(plugin);
-                    loaded += 1;
+            // Load saved config
+            if let Some(saved_config) = self.config_manager.get_plugin_config(&plugin.id, &plugin.name) {
+                plugin.enabled = saved_config.enabled;
+                plugin.last_error = saved_config.last_error;
+                plugin.failure_policy = saved_config.failure_policy;
+                if let Err(e) = plugin.set_routing(saved_config.routing) {
+                    eprintln!("[Plugins] '{}': saved routing rules ignored: {}", plugin.id, e);
+                }
+                // Disabled for failures before the restart: it gets another chance later
+                if !plugin.enabled && plugin.last_error.is_some() {
+                    plugin.failures.postpone(&plugin.failure_policy, Instant::now());
                 }
-                Err(e) => {
-                    eprintln!("Failed to load plugin {:?}: {}", path.display(), e);
+
+                // Set config if available (with defaults of fields added since it was saved)
+                if !saved_config.config.is_null() {
+                    let config = schema::validate_config(&plugin.config_schema, &saved_config.config)
+                        .unwrap_or(saved_config.config);
+                    let _ = plugin.set_config(&config);
                 }
             }
+
+            PluginWorker::spawn(plugin, &self.host)
+        });
+
+        match result {
+            Ok(worker) => {
+                let id = worker.id().to_string();
+                self.plugins.push(worker);
+                Ok(id)
+            }
+            Err(e) => {
+                eprintln!("Failed to load plugin {:?}: {}", source.display(), e);
+                self.failed.push(FailedPlugin {
+                    path: source.to_path_buf(),
+                    id: source_id(source),
+                    file_name: source_label(source),
+                    error: e.clone(),
+                });
+                Err(e)
+            }
         }
+    }
 
-        // Save updated config
-        self.config_manager.save_all_from_manager(&self.plugins)?;
+    /// Find loaded plugin index by id
+    /// Names are accepted too, for references saved before plugins had ids
+    fn plugin_index(&self, id: &str) -> Result<usize, String> {
+        self.plugins
+            .iter()
+            .position(|p| p.id() == id)
+            .or_else(|| self.plugins.iter().position(|p| p.name() == id))
+            .ok_or_else(|| format!("Plugin '{}' not found", id))
+    }
 
-        Ok(loaded)
+    /// Wait until a plugin is unloaded and remove it from the list
+    ///
+    /// A plugin that doesn't stop in time stays listed (marked as failing), so
+    /// no second copy is loaded and its files aren't replaced while it is in use.
+    fn unload_at(&mut self, idx: usize) -> Result<(), String> {
+        // Keep the plugin's settings for the next load
+        let _ = self.save_state();
+        eprintln!("[Plugins] Unloading '{}'", self.plugins[idx].name());
+        self.plugins[idx].stop(UNLOAD_TIMEOUT)?;
+        self.plugins.remove(idx);
+        Ok(())
     }
 
-    /// Get all plugins info
-    pub fn get_plugins(&self) -> Vec<PluginInfo> {
-        self.plugins.iter().map(|p| p.info()).collect()
+    /// Unload a plugin and load it again from the same file
+    fn reload_at(&mut self, idx: usize) -> Result<(), String> {
+        let source = self.plugins[idx].source().to_path_buf();
+        self.unload_at(idx)?;
+        self.load_source(&source)?;
+
+        // Keep its place in the list
+        if let Some(worker) = self.plugins.pop() {
+            self.plugins.insert(idx.min(self.plugins.len()), worker);
+        }
+        self.save_state()
     }
 
-    /// Find plugin index by name
-    fn find_plugin_index(&self, name: &str) -> Option<usize> {
-        self.plugins.iter().position(|p| p.name == name)
+    /// Unload a plugin and load it again (e.g. after its library was updated)
+    /// The saved config from `plugins-config.json` is applied again
+    pub fn reload_plugin(&mut self, id: &str) -> Result<(), String> {
+        let idx = self.plugin_index(id)?;
+        self.reload_at(idx)
     }
 
-    /// Set plugin config
-    pub fn set_plugin_config(&mut self, name: &str, config: &serde_json::Value) -> Result<(), String> {
-        if let Some(idx) = self.find_plugin_index(name) {
-            self.plugins[idx].set_config(config)?;
-            self.config_manager.save_plugin_config(&self.plugins[idx])?;
-            Ok(())
+    /// Unload a plugin until it is reloaded or the app restarts (its files stay)
+    pub fn unload_plugin(&mut self, id: &str) -> Result<(), String> {
+        let idx = self.plugin_index(id)?;
+        self.unload_at(idx)
+    }
+
+    /// Copy a plugin library or plugin folder into the plugins directory and load it
+    /// An installed plugin with the same file name is replaced
+    pub fn install_plugin(&mut self, path: &Path) -> Result<String, String> {
+        let file_name = path
+            .file_name()
+            .ok_or_else(|| format!("Invalid plugin path: {}", path.display()))?;
+        let target = self.config_manager.plugins_dir().join(file_name);
+        let source = if path.is_dir() {
+            if !path.join(MANIFEST_FILE).is_file() {
+                return Err(format!("Folder has no {}", MANIFEST_FILE));
+            }
+            target.join(MANIFEST_FILE)
+        } else if is_plugin_library(path) {
+            target.clone()
         } else {
-            Err(format!("Plugin '{}' not found", name))
+            return Err("Not a plugin: expected a library or a folder with plugin.toml".to_string());
+        };
+
+        // Free the files of the installed version before overwriting them
+        if let Some(idx) = self.plugins.iter().position(|p| p.source() == source) {
+            self.unload_at(idx)?;
+        }
+
+        let same_place = match (path.canonicalize(), target.canonicalize()) {
+            (Ok(from), Ok(to)) => from == to,
+            _ => false,
+        };
+        if !same_place {
+            if path.is_dir() {
+                copy_dir(path, &target)
+            } else {
+                std::fs::copy(path, &target).map(|_| ())
+            }
+            .map_err(|e| format!("Failed to copy plugin: {}", e))?;
         }
+
+        let id = self.load_source(&source)?;
+        self.save_state()?;
+        eprintln!("[Plugins] Installed '{}' from {}", id, path.display());
+        Ok(id)
     }
 
-    /// Toggle plugin enabled state
-    pub fn toggle_plugin(&mut self, name: &str, enabled: bool) -> Result<(), String> {
-        if let Some(idx) = self.find_plugin_index(name) {
-            self.plugins[idx].set_enabled(enabled);
-            self.config_manager.save_plugin_config(&self.plugins[idx])?;
-            Ok(())
+    /// Unload a plugin and delete its files (its saved settings are kept)
+    pub fn uninstall_plugin(&mut self, id: &str) -> Result<(), String> {
+        let idx = self.plugin_index(id)?;
+        let source = self.plugins[idx].source().to_path_buf();
+        self.unload_at(idx)?;
+
+        let result = if is_manifest(&source) {
+            match source.parent() {
+                Some(dir) => std::fs::remove_dir_all(dir),
+                None => Ok(()),
+            }
         } else {
-            Err(format!("Plugin '{}' not found", name))
+            std::fs::remove_file(&source)
+        };
+        self.stamps.remove(&source);
+        result.map_err(|e| format!("Failed to delete plugin files: {}", e))
+    }
+
+    /// React to files changed in the plugins directory (see `watcher.rs`):
+    /// new plugins are loaded, changed ones reloaded, deleted ones unloaded
+    /// Returns true if the plugin list changed
+    pub fn handle_changes(&mut self, paths: &[PathBuf]) -> bool {
+        let mut sources: Vec<PathBuf> = paths.iter().filter_map(|p| self.source_for(p)).collect();
+        sources.sort();
+        sources.dedup();
+
+        let mut changed = false;
+        for source in sources {
+            let loaded = self.plugins.iter().position(|p| p.source() == source);
+            let exists = source.is_file();
+
+            let result = match (loaded, exists) {
+                (Some(idx), false) => {
+                    self.stamps.remove(&source);
+                    self.unload_at(idx)
+                }
+                (None, false) => {
+                    self.stamps.remove(&source);
+                    self.failed.retain(|f| f.path != source);
+                    Ok(())
+                }
+                // Already seen this version (e.g. just installed, or unloaded by the user)
+                (_, true) if self.stamps.get(&source) == Some(&source_stamp(&source)) => continue,
+                (Some(idx), true) => self.reload_at(idx),
+                (None, true) => self.load_source(&source).map(|_| ()),
+            };
+
+            if let Err(e) = result {
+                eprintln!("[Plugins] {}: {}", source.display(), e);
+            }
+            changed = true;
+        }
+
+        if changed {
+            let _ = self.save_state();
+        }
+        changed
+    }
+
+    /// Library or manifest a changed path in the plugins directory belongs to
+    fn source_for(&self, path: &Path) -> Option<PathBuf> {
+        let plugins_dir = self.config_manager.plugins_dir();
+        let relative = path.strip_prefix(&plugins_dir).ok()?;
+        let first = relative.components().next()?;
+        let top = plugins_dir.join(first);
+
+        if top == path && is_plugin_library(path) {
+            return Some(top);
+        }
+        // Anything inside a plugin folder belongs to its manifest
+        let manifest = top.join(MANIFEST_FILE);
+        let known = self.stamps.contains_key(&manifest);
+        if manifest.is_file() || known {
+            Some(manifest)
+        } else {
+            None
         }
     }
 
+    /// Save enabled state, config and errors of all loaded plugins
+    pub fn save_state(&self) -> Result<(), String> {
+        let infos: Vec<PluginInfo> = self.plugins.iter().map(|p| p.info()).collect();
+        self.config_manager.save_all_from_manager(&infos)
+    }
+
+    /// Get all plugins info (loaded plugins first in execution order, then ones
+    /// that failed to load). Secret config values are masked, this is what the UI gets.
+    ///
+    /// Plugins are not asked for their status here, so a busy plugin can't block
+    /// the UI; the info is the one recorded after the plugin's last call.
+    pub fn get_plugins(&self) -> Vec<PluginInfo> {
+        let chain = self.transform_chain();
+        self.execution_order()
+            .into_iter()
+            .map(|idx| {
+                let p = &self.plugins[idx];
+                let mut info = p.info();
+                info.transform_position = chain.iter().position(|&i| i == idx);
+                info.config = schema::mask_secrets(&info.config_schema, &info.config);
+                info
+            })
+            .chain(self.failed.iter().map(|f| f.info()))
+            .collect()
+    }
+
+    /// Find plugin by id
+    fn find_plugin(&self, id: &str) -> Result<&PluginWorker, String> {
+        self.plugin_index(id).map(|idx| &self.plugins[idx])
+    }
+
+    /// Set plugin config
+    ///
+    /// The config is checked against the plugin's schema and completed with
+    /// defaults first; an invalid config never reaches the plugin.
+    pub fn set_plugin_config(&mut self, id: &str, config: &serde_json::Value) -> Result<(), ConfigError> {
+        let plugin = self.find_plugin(id)?;
+        let config = config.clone();
+        let result = plugin.call(move |p| {
+            // Masked secrets come back unchanged from the form
+            let config = schema::unmask_secrets(&p.config_schema, &config, &p.config);
+            let config = schema::validate_config(&p.config_schema, &config).map_err(ConfigError::invalid)?;
+            p.set_config(&config).map_err(ConfigError::from)
+        })?;
+        self.config_manager.save_plugin_config(&plugin.info())?;
+        result
+    }
+
+    /// Toggle plugin enabled state
+    pub fn toggle_plugin(&mut self, id: &str, enabled: bool) -> Result<(), String> {
+        let plugin = self.find_plugin(id)?;
+        plugin.call(move |p| p.set_enabled(enabled))?;
+        self.config_manager.save_plugin_config(&plugin.info())
+    }
+
+    /// Change what happens when the plugin's calls fail
+    pub fn set_failure_policy(&mut self, id: &str, policy: FailurePolicy) -> Result<(), String> {
+        policy.validate()?;
+        let plugin = self.find_plugin(id)?;
+        plugin.call(move |p| p.failure_policy = policy)?;
+        self.config_manager.save_plugin_config(&plugin.info())
+    }
+
+    /// Change which spoken messages the plugin receives
+    pub fn set_routing_rules(&mut self, id: &str, rules: RoutingRules) -> Result<(), String> {
+        // Checked here too, so a bad regex is reported without touching the plugin
+        rules.clone().compile()?;
+        let plugin = self.find_plugin(id)?;
+        plugin.call(move |p| p.set_routing(rules))??;
+        self.config_manager.save_plugin_config(&plugin.info())
+    }
+
     /// Check plugin status
-    pub fn check_plugin_status(&self, name: &str) -> Result<plugins_api::PluginStatus, String> {
-        self.plugins
-            .iter()
-            .find(|p| p.name == name)
-            .map(|p| p.check_status())
-            .ok_or_else(|| format!("Plugin '{}' not found", name))
+    pub fn check_plugin_status(&self, id: &str) -> Result<plugins_api::PluginStatus, String> {
+        self.find_plugin(id)?.call(|p| p.check_status())
     }
 
-    /// Broadcast text to all enabled plugins
-    /// Returns true if any plugin state changed (was disabled due to error)
-    pub fn broadcast_text(&mut self, text: &str) -> bool {
+    /// Queue a spoken message for all enabled plugins whose routing rules accept it
+    /// (does not wait for them)
+    /// Returns true if any plugin state changed (was disabled because it is stuck)
+    pub fn broadcast_text(&mut self, message: &TextMessage) -> bool {
         let mut has_changes = false;
-        for plugin in &mut self.plugins {
-            if !plugin.enabled {
+        for idx in self.execution_order() {
+            let plugin = &self.plugins[idx];
+            if !plugin.is_enabled() || !plugin.has_capability(capabilities::TEXT) {
                 continue;
             }
 
-            match plugin.on_text(text) {
-                Ok(_) => {
-                    plugin.last_error = None;
-                }
-                Err(e) => {
-                    // Disable plugin on error and save error
-                    plugin.set_enabled(false);
-                    plugin.set_error(e.clone());
-                    let _ = self.config_manager.save_plugin_config(plugin);
-                    eprintln!("Plugin '{}' failed: {}, disabling", plugin.name, e);
-                    has_changes = true;
+            let message = message.clone();
+            has_changes |= !plugin.post_with_policy(move |p| {
+                if !p.router.matches(&message) {
+                    return;
                 }
+                // The failure policy decides whether to disable it (the worker reports the change)
+                let _ = p.call_with_policy(|p| p.on_message(&message));
+            });
+        }
+
+        if has_changes {
+            let _ = self.save_state();
+        }
+        has_changes
+    }
+
+    /// Ids and names of enabled plugins that act as TTS providers
+    pub fn tts_providers(&self) -> Vec<(String, String)> {
+        self.plugins
+            .iter()
+            .filter(|p| p.is_enabled() && p.has_capability(capabilities::SYNTH))
+            .map(|p| (p.id().to_string(), p.name().to_string()))
+            .collect()
+    }
+
+    /// Find an enabled TTS provider plugin
+    ///
+    /// The returned handle can be used after the manager lock is released,
+    /// so slow synthesis doesn't hold up other plugin calls.
+    pub fn tts_provider(&self, id: &str) -> Result<PluginWorker, String> {
+        let plugin = self.find_plugin(id)?;
+        if !plugin.has_capability(capabilities::SYNTH) {
+            return Err(format!("Plugin '{}' is not a TTS provider", id));
+        }
+        if !plugin.is_enabled() {
+            return Err(format!("Plugin '{}' is disabled", id));
+        }
+        Ok(plugin.clone())
+    }
+
+    /// Voices of a TTS provider plugin
+    pub fn plugin_voices(&self, id: &str) -> Result<Vec<PluginVoice>, String> {
+        self.tts_provider(id)?.call(|p| p.voices())
+    }
+
+    /// True if any enabled plugin wants synthesized audio
+    pub fn has_audio_listeners(&self) -> bool {
+        self.plugins
+            .iter()
+            .any(|p| p.is_enabled() && p.has_capability(capabilities::AUDIO))
+    }
+
+    /// Queue synthesized audio for all enabled plugins (does not wait for them)
+    /// Returns true if any plugin state changed (was disabled because it is stuck)
+    pub fn broadcast_audio(&mut self, clip: &AudioClip) -> bool {
+        let mut has_changes = false;
+        for idx in self.execution_order() {
+            let plugin = &self.plugins[idx];
+            if !plugin.is_enabled() || !plugin.has_capability(capabilities::AUDIO) {
+                continue;
             }
+
+            let clip = clip.clone();
+            has_changes |= !plugin.post_with_policy(move |p| {
+                let _ = p.call_with_policy(|p| p.on_audio(&clip));
+            });
         }
 
-        // Save updated errors
-        let _ = self.config_manager.save_all_from_manager(&self.plugins);
+        if has_changes {
+            let _ = self.save_state();
+        }
         has_changes
     }
 
-    #[allow(dead_code)]
-    /// Get plugins slice for config manager
-    pub fn get_plugins_slice(&self) -> &[LoadedPlugin] {
-        &self.plugins
+    /// Forward a playback or app state event to all enabled plugins (does not wait for them)
+    pub fn dispatch_event(&self, event: &PluginEvent) {
+        for idx in self.execution_order() {
+            let plugin = &self.plugins[idx];
+            if !plugin.is_enabled() || !plugin.has_capability(capabilities::EVENTS) {
+                continue;
+            }
+
+            let event = event.clone();
+            plugin.post(move |p| p.on_event(&event));
+        }
+    }
+
+    /// Indices of all loaded plugins in the user-defined execution order
+    fn execution_order(&self) -> Vec<usize> {
+        let ids: Vec<&str> = self.plugins.iter().map(|p| p.id()).collect();
+        order_names(&ids, &self.plugin_order)
+            .into_iter()
+            .filter_map(|id| self.plugins.iter().position(|p| p.id() == id))
+            .collect()
+    }
+
+    /// Set the order in which plugins get messages, audio and events
+    pub fn set_plugin_order(&mut self, order: Vec<String>) -> Result<(), String> {
+        self.config_manager.save_plugin_order(&order)?;
+        self.plugin_order = order;
+        Ok(())
+    }
+
+    /// Indices of text-transforming plugins in the order they run
+    fn transform_chain(&self) -> Vec<usize> {
+        let ids: Vec<&str> = self
+            .plugins
+            .iter()
+            .filter(|p| p.has_capability(capabilities::TRANSFORM))
+            .map(|p| p.id())
+            .collect();
+
+        // Orders saved before plugins had ids list names
+        let order: Vec<String> = self
+            .transform_order
+            .iter()
+            .map(|key| match self.plugins.iter().find(|p| p.id() == key || p.name() == key) {
+                Some(plugin) => plugin.id().to_string(),
+                None => key.clone(),
+            })
+            .collect();
+
+        order_names(&ids, &order)
+            .into_iter()
+            .filter_map(|id| self.plugins.iter().position(|p| p.id() == id))
+            .collect()
+    }
+
+    /// Set the order in which text transforms run
+    pub fn set_transform_order(&mut self, order: Vec<String>) -> Result<(), String> {
+        self.config_manager.save_transform_order(&order)?;
+        self.transform_order = order;
+        Ok(())
+    }
+
+    /// Enabled transform plugins in the order they run (see [`run_transforms`])
+    ///
+    /// The handles are cloned so the chain can run without holding the manager lock.
+    pub fn transform_workers(&self) -> Vec<PluginWorker> {
+        self.transform_chain()
+            .into_iter()
+            .map(|idx| &self.plugins[idx])
+            .filter(|plugin| plugin.is_enabled())
+            .cloned()
+            .collect()
     }
 }
 
-// SAFETY: PluginManager is Send because all mutable access is through Mutex
-unsafe impl Send for PluginManager {}
+/// Run text through a chain of transform plugins
+/// A failing or unresponsive plugin is skipped, the text from the previous step is kept
+pub fn run_transforms(chain: &[PluginWorker], text: &str) -> TransformReport {
+    let mut current = text.to_string();
+    let mut steps = Vec::new();
+
+    for plugin in chain {
+        // Disabled by an earlier failure since the chain was taken
+        if !plugin.is_enabled() {
+            continue;
+        }
+
+        let started = Instant::now();
+        let input = current.clone();
+        let result = plugin
+            .call_with_policy(move |p| p.call_with_policy(|p| p.transform_text(&input)))
+            .and_then(|result| result);
+        let duration_ms = started.elapsed().as_secs_f64() * 1000.0;
+
+        let mut step = TransformStep {
+            plugin: plugin.id().to_string(),
+            duration_ms,
+            changed: false,
+            error: None,
+        };
+
+        match result {
+            Ok(Some(new_text)) => {
+                step.changed = new_text != current;
+                current = new_text;
+            }
+            Ok(None) => {}
+            Err(e) => {
+                eprintln!("Plugin '{}' transform failed: {}", plugin.name(), e);
+                step.error = Some(e);
+            }
+        }
+
+        steps.push(step);
+    }
+
+    TransformReport {
+        original: text.to_string(),
+        text: current,
+        steps,
+    }
+}
+
+/// Load a plugin library, or the plugin a folder manifest describes
+fn load_any(source: &Path, host: &PluginHost) -> Result<LoadedPlugin, String> {
+    if !is_manifest(source) {
+        return load_plugin(source, &default_plugin_id(source), host);
+    }
+
+    let manifest = PluginManifest::load(source)?;
+    manifest.check_host_version()?;
+    let dir = source
+        .parent()
+        .ok_or_else(|| "Manifest has no parent folder".to_string())?;
+    let id = manifest.id(source);
+    let mut plugin = match (manifest.library_path(dir), manifest.process.clone(), manifest.wasm_path(dir)) {
+        (Some(library), _, _) => load_plugin(&library, &id, host)?,
+        (None, Some(process), _) => load_process_plugin(source, process)?,
+        (None, None, Some(module)) => load_wasm(source, &module, &manifest, host)?,
+        (None, None, None) => return Err("Manifest has none of `library`, `[process]` and `[wasm]`".to_string()),
+    };
+
+    // The folder is the plugin: watch and uninstall it as a whole
+    plugin.source = source.to_path_buf();
+    plugin.id = id;
+    if let Some(name) = manifest.plugin.name {
+        plugin.name = name;
+    }
+    plugin.metadata = PluginMetadata {
+        description: manifest.plugin.description,
+        author: manifest.plugin.author,
+        homepage: manifest.plugin.homepage,
+    };
+    Ok(plugin)
+}
+
+/// Load the module of a WASM plugin with the grants of its manifest
+#[cfg(feature = "wasm-plugins")]
+fn load_wasm(source: &Path, module: &Path, manifest: &PluginManifest, host: &PluginHost) -> Result<LoadedPlugin, String> {
+    let grants = manifest.grants.clone().unwrap_or_default();
+    super::wasm::load_wasm_plugin(source, module, &manifest.id(source), grants, host)
+}
+
+#[cfg(not(feature = "wasm-plugins"))]
+fn load_wasm(_source: &Path, _module: &Path, _manifest: &PluginManifest, _host: &PluginHost) -> Result<LoadedPlugin, String> {
+    Err("WASM plugins are not supported by this build (enable the wasm-plugins feature)".to_string())
+}
+
+/// Id of a plugin that failed to load (the manifest's one if it can be read)
+fn source_id(source: &Path) -> String {
+    if is_manifest(source) {
+        if let Ok(manifest) = PluginManifest::load(source) {
+            return manifest.id(source);
+        }
+    }
+    default_plugin_id(source)
+}
+
+fn is_manifest(source: &Path) -> bool {
+    source.file_name().and_then(|n| n.to_str()) == Some(MANIFEST_FILE)
+}
+
+/// Name shown for a plugin that failed to load: library file or plugin folder
+fn source_label(source: &Path) -> String {
+    let path = if is_manifest(source) {
+        source.parent().unwrap_or(source)
+    } else {
+        source
+    };
+    path.file_name()
+        .map(|n| n.to_string_lossy().to_string())
+        .unwrap_or_default()
+}
+
+/// Modification time of a plugin: its library, or the newest file in its folder
+fn source_stamp(source: &Path) -> Option<SystemTime> {
+    let modified = |path: &Path| std::fs::metadata(path).and_then(|m| m.modified()).ok();
+    if !is_manifest(source) {
+        return modified(source);
+    }
+
+    let entries = std::fs::read_dir(source.parent()?).ok()?;
+    entries
+        .flatten()
+        .map(|entry| entry.path())
+        .filter(|path| path.is_file())
+        .filter_map(|path| modified(&path))
+        .max()
+}
+
+fn copy_dir(from: &Path, to: &Path) -> std::io::Result<()> {
+    std::fs::create_dir_all(to)?;
+    for entry in std::fs::read_dir(from)? {
+        let entry = entry?;
+        let target = to.join(entry.file_name());
+        if entry.file_type()?.is_dir() {
+            copy_dir(&entry.path(), &target)?;
+        } else {
+            std::fs::copy(entry.path(), target)?;
+        }
+    }
+    Ok(())
+}
+
+/// Sort names by their position in `order`; names not listed keep their relative order at the end
+fn order_names<'a>(names: &[&'a str], order: &[String]) -> Vec<&'a str> {
+    let mut sorted = names.to_vec();
+    sorted.sort_by_key(|name| {
+        order
+            .iter()
+            .position(|o| o == name)
+            .unwrap_or(usize::MAX)
+    });
+    sorted
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_order_names() {
+        let names = ["a", "b", "c", "d"];
+        let order = vec!["c".to_string(), "missing".to_string(), "a".to_string()];
+        assert_eq!(order_names(&names, &order), vec!["c", "a", "b", "d"]);
+        assert_eq!(order_names(&names, &[]), vec!["a", "b", "c", "d"]);
+    }
+
+    #[test]
+    fn test_install_reload_and_watch() {
+        let example = crate::plugins::process::tests::python_example();
+        let temp = tempfile::tempdir().unwrap();
+        let mut manager = PluginManager::new(temp.path().to_path_buf()).unwrap();
+
+        let name = manager.install_plugin(example.parent().unwrap()).unwrap();
+        assert_eq!(name, "python-example");
+        let manifest = temp.path().join("python-example").join(MANIFEST_FILE);
+        assert!(manifest.is_file());
+
+        // The saved config is applied again after a reload
+        manager.set_plugin_config(&name, &serde_json::json!({ "prefix": "#" })).unwrap();
+        manager.reload_plugin(&name).unwrap();
+        manager.check_plugin_status(&name).unwrap();
+        let info = &manager.get_plugins()[0];
+        assert!(info.status_detail.as_deref().unwrap().contains("#"));
+
+        // Events for files the manager has already seen change nothing,
+        // so an unloaded plugin stays unloaded
+        assert!(!manager.handle_changes(&[manifest.clone()]));
+        manager.unload_plugin(&name).unwrap();
+        assert!(manager.get_plugins().is_empty());
+        assert!(!manager.handle_changes(&[manifest.clone()]));
+
+        // Changing a file in its folder loads it again
+        std::thread::sleep(Duration::from_millis(20));
+        let readme = manifest.with_file_name("notes.txt");
+        std::fs::write(&readme, "updated").unwrap();
+        assert!(manager.handle_changes(&[readme]));
+        assert_eq!(manager.get_plugins().len(), 1);
+
+        manager.uninstall_plugin(&name).unwrap();
+        assert!(!manifest.parent().unwrap().exists());
+        assert!(manager.get_plugins().is_empty());
+    }
+}
@@ -0,0 +1,410 @@
+//! Plugin manifests (`plugin.toml`)
+//!
+//! A plugin can live in its own folder inside the plugins directory, next to a
+//! manifest that describes it and tells the host how to load it: a native
+//! library from the folder, a separate process or a sandboxed WASM module.
+//!
+//! ```toml
+//! [plugin]
+//! id = "python-example"          # stable id, settings are saved under it
+//! name = "Python Example"
+//! description = "Prints received text"
+//! author = "Jane Doe"
+//! homepage = "https://example.com"
+//! min_host_version = "0.1.0"
+//! library = "my_plugin"          # native plugin: file name without extension
+//!
+//! [process]                      # ...or a process plugin
+//! command = "python"
+//! args = ["main.py"]
+//!
+//! [wasm]                         # ...or a WASM plugin
+//! module = "my_plugin.wasm"
+//!
+//! [grants]                       # what a WASM plugin may do, shown before enabling it
+//! network = ["api.example.com", "*.example.org"]
+//! filesystem = "logs"            # folder, relative to the plugin's data folder
+//! enqueue = true
+//! ```
+//!
+//! Every field of `[plugin]` is optional; a folder with only `[process]` works.
+
+use serde::{Deserialize, Serialize};
+use std::path::{Component, Path, PathBuf};
+use std::process::Command;
+
+/// Manifest file name inside a plugin folder
+pub const MANIFEST_FILE: &str = "plugin.toml";
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct PluginManifest {
+    #[serde(default)]
+    pub plugin: PluginSection,
+    /// Set for plugins that run as a separate process
+    pub process: Option<ProcessManifest>,
+    /// Set for sandboxed WASM plugins
+    pub wasm: Option<WasmManifest>,
+    /// Rights of a WASM plugin (native and process plugins can do anything anyway)
+    pub grants: Option<PluginGrants>,
+}
+
+/// Identity and description of a plugin (`[plugin]`)
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct PluginSection {
+    /// Stable id, defaults to the folder name
+    pub id: Option<String>,
+    /// Display name, defaults to the name reported by the plugin
+    pub name: Option<String>,
+    pub description: Option<String>,
+    pub author: Option<String>,
+    pub homepage: Option<String>,
+    /// Oldest app version the plugin works with, e.g. "0.2.0"
+    pub min_host_version: Option<String>,
+    /// Native library in the plugin folder; without an extension the
+    /// platform's prefix and extension are added (`lib*.so`, `*.dll`, `lib*.dylib`)
+    pub library: Option<String>,
+}
+
+/// How to start a process plugin
+#[derive(Debug, Clone, Deserialize)]
+pub struct ProcessManifest {
+    /// Executable: a path relative to the plugin folder or a program on PATH
+    pub command: String,
+    /// Programs tried in order if `command` is not installed (e.g. `python3` for `python`)
+    #[serde(default)]
+    pub fallbacks: Vec<String>,
+    #[serde(default)]
+    pub args: Vec<String>,
+}
+
+/// Module of a WASM plugin
+#[derive(Debug, Clone, Deserialize)]
+pub struct WasmManifest {
+    /// `.wasm` file in the plugin folder
+    pub module: String,
+}
+
+/// What a sandboxed plugin is allowed to do; everything else is denied
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PluginGrants {
+    /// Hosts the plugin may send HTTP requests to; "*.example.com" also allows subdomains
+    pub network: Vec<String>,
+    /// Folder the plugin may read and write (relative to its data folder or absolute)
+    pub filesystem: Option<String>,
+    /// May add text to the TTS queue
+    pub enqueue: bool,
+}
+
+impl PluginGrants {
+    /// Whether an HTTP request to `url` is allowed
+    pub fn allows_url(&self, url: &str) -> bool {
+        let rest = match url.split_once("://") {
+            Some((scheme, rest)) if scheme.eq_ignore_ascii_case("http") || scheme.eq_ignore_ascii_case("https") => rest,
+            _ => return false,
+        };
+        let authority = rest.split(['/', '?', '#']).next().unwrap_or_default();
+        // "user@host" could hide the real host from a naive check
+        if authority.contains('@') {
+            return false;
+        }
+        let host = match authority.rsplit_once(':') {
+            Some((host, port)) if port.chars().all(|c| c.is_ascii_digit()) => host,
+            _ => authority,
+        };
+        self.allows_host(host)
+    }
+
+    /// Whether `host` is in the network grant
+    pub fn allows_host(&self, host: &str) -> bool {
+        let host = host.trim_end_matches('.').to_ascii_lowercase();
+        !host.is_empty()
+            && self.network.iter().any(|allowed| {
+                let allowed = allowed.to_ascii_lowercase();
+                match allowed.strip_prefix("*.") {
+                    Some(domain) => host == domain || host.ends_with(&format!(".{}", domain)),
+                    None => host == allowed,
+                }
+            })
+    }
+
+    /// Folder the plugin may use, if it was granted one
+    pub fn filesystem_dir(&self, data_dir: &Path) -> Option<PathBuf> {
+        let dir = Path::new(self.filesystem.as_ref()?);
+        if dir.is_absolute() {
+            Some(dir.to_path_buf())
+        } else if dir.components().all(|c| matches!(c, Component::Normal(_) | Component::CurDir)) {
+            Some(data_dir.join(dir))
+        } else {
+            // "../" would leave the data folder without the user seeing an absolute path
+            None
+        }
+    }
+}
+
+impl PluginManifest {
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let content = std::fs::read_to_string(path)
+            .map_err(|e| format!("Failed to read manifest: {}", e))?;
+        let manifest: Self = toml::from_str(&content)
+            .map_err(|e| format!("Failed to parse manifest: {}", e))?;
+        manifest.validate()?;
+        Ok(manifest)
+    }
+
+    fn validate(&self) -> Result<(), String> {
+        if let Some(ref id) = self.plugin.id {
+            let valid = !id.is_empty()
+                && id.chars().all(|c| c.is_ascii_alphanumeric() || "-_.".contains(c));
+            if !valid {
+                return Err(format!("Invalid plugin id '{}': use letters, digits, '-', '_' and '.'", id));
+            }
+        }
+        let kinds = [self.plugin.library.is_some(), self.process.is_some(), self.wasm.is_some()];
+        match kinds.iter().filter(|&&kind| kind).count() {
+            0 => return Err("Manifest has none of `library`, `[process]` and `[wasm]`".to_string()),
+            1 => {}
+            _ => return Err("Manifest has more than one of `library`, `[process]` and `[wasm]`".to_string()),
+        }
+        if self.grants.is_some() && self.wasm.is_none() {
+            return Err("`[grants]` only apply to WASM plugins, native and process plugins are not sandboxed".to_string());
+        }
+        if let Some(dir) = self.grants.as_ref().and_then(|g| g.filesystem.as_ref()) {
+            let relative = Path::new(dir);
+            if !relative.is_absolute() && relative.components().any(|c| matches!(c, Component::ParentDir)) {
+                return Err(format!("Invalid filesystem grant '{}': use a folder inside the data folder or an absolute path", dir));
+            }
+        }
+        Ok(())
+    }
+
+    /// Stable plugin id: from the manifest or the plugin folder name
+    pub fn id(&self, manifest_path: &Path) -> String {
+        match self.plugin.id {
+            Some(ref id) => id.clone(),
+            None => default_plugin_id(manifest_path),
+        }
+    }
+
+    /// Path of the native library for plugins that have one
+    pub fn library_path(&self, dir: &Path) -> Option<PathBuf> {
+        let library = self.plugin.library.as_ref()?;
+        if Path::new(library).extension().is_some() {
+            Some(dir.join(library))
+        } else {
+            Some(dir.join(libloading::library_filename(library)))
+        }
+    }
+
+    /// Path of the module for WASM plugins
+    pub fn wasm_path(&self, dir: &Path) -> Option<PathBuf> {
+        self.wasm.as_ref().map(|wasm| dir.join(&wasm.module))
+    }
+
+    /// Error if the app is older than the plugin requires
+    pub fn check_host_version(&self) -> Result<(), String> {
+        let required = match self.plugin.min_host_version {
+            Some(ref version) => version,
+            None => return Ok(()),
+        };
+        let host = env!("CARGO_PKG_VERSION");
+        match (parse_version(required), parse_version(host)) {
+            (Some(required_parts), Some(host_parts)) if host_parts < required_parts => Err(format!(
+                "Plugin requires app version {} or newer (current: {})",
+                required, host
+            )),
+            (None, _) => Err(format!("Invalid min_host_version '{}'", required)),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Id of a plugin without a manifest id: the plugin folder name for a manifest,
+/// otherwise the library file name without the platform's prefix and extension
+pub fn default_plugin_id(source: &Path) -> String {
+    let lossy = |name: &std::ffi::OsStr| name.to_string_lossy().to_string();
+    if source.file_name().and_then(|n| n.to_str()) == Some(MANIFEST_FILE) {
+        return source
+            .parent()
+            .and_then(|dir| dir.file_name())
+            .map(lossy)
+            .unwrap_or_default();
+    }
+
+    let stem = source.file_stem().map(lossy).unwrap_or_default();
+    let prefix = std::env::consts::DLL_PREFIX;
+    match stem.strip_prefix(prefix) {
+        Some(rest) if !prefix.is_empty() && !rest.is_empty() => rest.to_string(),
+        _ => stem,
+    }
+}
+
+/// "1.2.3" -> [1, 2, 3] (pre-release suffixes are ignored)
+fn parse_version(version: &str) -> Option<Vec<u64>> {
+    let core = version.trim().split(['-', '+']).next()?;
+    let mut parts: Vec<u64> = core
+        .split('.')
+        .map(|part| part.parse().ok())
+        .collect::<Option<_>>()?;
+    parts.resize(3, 0);
+    Some(parts)
+}
+
+impl ProcessManifest {
+    /// Command that starts the plugin, run from its folder
+    pub fn command(&self, dir: &Path) -> Command {
+        self.command_for(&self.command, dir)
+    }
+
+    /// `command`, then its fallbacks
+    pub fn commands(&self, dir: &Path) -> Vec<Command> {
+        std::iter::once(&self.command)
+            .chain(&self.fallbacks)
+            .map(|program| self.command_for(program, dir))
+            .collect()
+    }
+
+    fn command_for(&self, program: &str, dir: &Path) -> Command {
+        let local = dir.join(program);
+        let program = if local.is_file() {
+            local.into_os_string()
+        } else {
+            program.into()
+        };
+
+        let mut command = Command::new(program);
+        command.args(&self.args).current_dir(dir);
+        command
+    }
+}
+
+/// Scan the plugins directory for plugin folders with a manifest
+pub fn scan_manifests(dir: &Path) -> Vec<PathBuf> {
+    let mut manifests = Vec::new();
+
+    if let Ok(entries) = std::fs::read_dir(dir) {
+        for entry in entries.flatten() {
+            let path = entry.path().join(MANIFEST_FILE);
+            if path.is_file() {
+                manifests.push(path);
+            }
+        }
+    }
+
+    manifests
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_scan_and_parse_manifest() {
+        let temp = tempfile::tempdir().unwrap();
+        let plugin_dir = temp.path().join("echo");
+        std::fs::create_dir(&plugin_dir).unwrap();
+        std::fs::create_dir(temp.path().join("data")).unwrap();
+        std::fs::write(
+            plugin_dir.join(MANIFEST_FILE),
+            "[process]\ncommand = \"echo.exe\"\nargs = [\"--stdio\"]\n",
+        )
+        .unwrap();
+        std::fs::write(plugin_dir.join("echo.exe"), b"").unwrap();
+
+        let manifests = scan_manifests(temp.path());
+        assert_eq!(manifests, vec![plugin_dir.join(MANIFEST_FILE)]);
+
+        let manifest = PluginManifest::load(&manifests[0]).unwrap();
+        assert_eq!(manifest.id(&manifests[0]), "echo");
+        assert!(manifest.library_path(&plugin_dir).is_none());
+        let process = manifest.process.unwrap();
+        assert_eq!(process.args, vec!["--stdio"]);
+        // Executables shipped with the plugin are found in its folder
+        let command = process.command(&plugin_dir);
+        assert_eq!(Path::new(command.get_program()), plugin_dir.join("echo.exe"));
+
+        let process = ProcessManifest {
+            command: "python".to_string(),
+            fallbacks: vec!["python3".to_string()],
+            args: Vec::new(),
+        };
+        assert_eq!(process.command(&plugin_dir).get_program(), "python");
+        let programs: Vec<_> = process.commands(&plugin_dir).iter().map(|c| c.get_program().to_owned()).collect();
+        assert_eq!(programs, ["python", "python3"]);
+    }
+
+    #[test]
+    fn test_manifest_metadata() {
+        let manifest: PluginManifest = toml::from_str(
+            "[plugin]\nid = \"file-logger\"\nname = \"Журнал\"\nauthor = \"me\"\nlibrary = \"file_logger_plugin\"\n",
+        )
+        .unwrap();
+        manifest.validate().unwrap();
+        assert_eq!(manifest.id(Path::new("plugins/logger/plugin.toml")), "file-logger");
+        assert_eq!(manifest.plugin.author.as_deref(), Some("me"));
+        assert_eq!(
+            manifest.library_path(Path::new("dir")).unwrap(),
+            Path::new("dir").join(libloading::library_filename("file_logger_plugin"))
+        );
+
+        // Only one way to run a plugin
+        let both: PluginManifest =
+            toml::from_str("[plugin]\nlibrary = \"a.dll\"\n[process]\ncommand = \"a\"\n").unwrap();
+        assert!(both.validate().is_err());
+        let bad_id: PluginManifest =
+            toml::from_str("[plugin]\nid = \"my plugin\"\nlibrary = \"a.dll\"\n").unwrap();
+        assert!(bad_id.validate().is_err());
+
+        // Without a manifest id the file name is used, without "lib" and extension
+        let file = libloading::library_filename("file_logger_plugin");
+        assert_eq!(default_plugin_id(Path::new(&file)), "file_logger_plugin");
+    }
+
+    #[test]
+    fn test_host_version_check() {
+        let manifest = |version: &str| PluginManifest {
+            plugin: PluginSection {
+                min_host_version: Some(version.to_string()),
+                ..Default::default()
+            },
+            process: None,
+            wasm: None,
+            grants: None,
+        };
+        assert!(manifest("0.0.1").check_host_version().is_ok());
+        assert!(manifest(env!("CARGO_PKG_VERSION")).check_host_version().is_ok());
+        assert!(manifest("999.0").check_host_version().is_err());
+        assert!(manifest("latest").check_host_version().is_err());
+        assert_eq!(parse_version("1.2.3-beta"), Some(vec![1, 2, 3]));
+    }
+
+    #[test]
+    fn test_wasm_manifest_grants() {
+        let manifest: PluginManifest = toml::from_str(
+            "[wasm]\nmodule = \"echo.wasm\"\n[grants]\nnetwork = [\"api.example.com\", \"*.example.org\"]\nfilesystem = \"logs\"\n",
+        )
+        .unwrap();
+        manifest.validate().unwrap();
+        assert_eq!(manifest.wasm_path(Path::new("dir")).unwrap(), Path::new("dir").join("echo.wasm"));
+
+        let grants = manifest.grants.unwrap();
+        assert!(!grants.enqueue);
+        assert!(grants.allows_url("https://api.example.com/v1/chat"));
+        assert!(grants.allows_url("http://API.example.com:8080"));
+        assert!(grants.allows_url("https://cdn.example.org/x"));
+        assert!(grants.allows_url("https://example.org"));
+        assert!(!grants.allows_url("https://example.com/"));
+        assert!(!grants.allows_url("https://api.example.com.evil.net/"));
+        assert!(!grants.allows_url("https://api.example.com@evil.net/"));
+        assert!(!grants.allows_url("file:///etc/passwd"));
+        assert_eq!(grants.filesystem_dir(Path::new("data")), Some(Path::new("data").join("logs")));
+
+        // Grants are only for sandboxed plugins and can't escape the data folder
+        let native: PluginManifest =
+            toml::from_str("[plugin]\nlibrary = \"a.dll\"\n[grants]\nenqueue = true\n").unwrap();
+        assert!(native.validate().is_err());
+        let escape: PluginManifest =
+            toml::from_str("[wasm]\nmodule = \"a.wasm\"\n[grants]\nfilesystem = \"../..\"\n").unwrap();
+        assert!(escape.validate().is_err());
+    }
+}
@@ -1,9 +1,27 @@
 //! Plugin system for dynamic loading of extensions
 
+pub mod backend;
 pub mod config;
 pub mod dynamic;
+pub mod host;
 pub mod manager;
+pub mod manifest;
+pub mod policy;
+pub mod process;
+pub mod routing;
+pub mod schema;
 pub mod types;
+#[cfg(feature = "wasm-plugins")]
+pub mod wasm;
+pub mod watcher;
+pub mod worker;
 
-pub use manager::PluginManager;
-pub use types::{PluginInfo, SerializablePluginStatus};
+pub use host::{EnqueueLimiter, HostRequest, HostRequestSender, PluginHost};
+pub use manager::{run_transforms, PluginManager};
+pub use policy::FailurePolicy;
+pub use routing::{MessageSource, RoutingRules, TextMessage};
+pub use types::{AudioClip, ConfigError, PluginEvent, PluginInfo, PluginVoice, SerializablePluginStatus};
+pub use worker::PluginWorker;
+
+/// Plugin manager shared between the app state and the TTS engine
+pub type SharedPluginManager = std::sync::Arc<std::sync::Mutex<Option<PluginManager>>>;
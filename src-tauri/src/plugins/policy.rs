@@ -0,0 +1,266 @@
+//! What happens when a plugin call fails
+//!
+//! A failed call is retried a few times; only then does it count as an error.
+//! A plugin is disabled once it runs out of its error budget (too many errors
+//! within a time window) and is enabled again after a growing pause, as soon
+//! as its `check_status` reports Ok.
+
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+/// Most retries a policy may ask for (each one delays the plugin's queue)
+pub const MAX_RETRIES: u32 = 5;
+
+/// Pause before the first retry; later retries wait proportionally longer
+const RETRY_DELAY: Duration = Duration::from_millis(100);
+
+/// Upper bound for the pause before re-enabling (unless the policy asks for more)
+const MAX_REENABLE_DELAY: Duration = Duration::from_secs(600);
+
+/// Longest pause before re-enabling a policy may ask for (one day)
+pub const MAX_REENABLE_AFTER_SECS: u64 = 24 * 60 * 60;
+
+/// Per-plugin failure policy, saved with the plugin's settings
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct FailurePolicy {
+    /// Extra attempts for a failed call before it counts as an error
+    pub retries: u32,
+    /// Errors within `window_secs` that disable the plugin
+    pub max_errors: u32,
+    pub window_secs: u64,
+    /// Pause before an auto-disabled plugin is enabled again (doubles each
+    /// time it fails again); 0 keeps it disabled until the user enables it
+    pub reenable_after_secs: u64,
+}
+
+impl Default for FailurePolicy {
+    fn default() -> Self {
+        Self {
+            retries: 1,
+            max_errors: 3,
+            window_secs: 60,
+            reenable_after_secs: 30,
+        }
+    }
+}
+
+impl FailurePolicy {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.retries > MAX_RETRIES {
+            return Err(format!("At most {} retries are allowed", MAX_RETRIES));
+        }
+        if self.max_errors == 0 {
+            return Err("The error budget must allow at least one error".to_string());
+        }
+        if self.window_secs == 0 {
+            return Err("The error window must be at least one second".to_string());
+        }
+        if self.reenable_after_secs > MAX_REENABLE_AFTER_SECS {
+            return Err(format!("The pause before enabling again can be at most {} seconds", MAX_REENABLE_AFTER_SECS));
+        }
+        Ok(())
+    }
+
+    /// Pause before the given retry (1-based)
+    pub fn retry_delay(&self, attempt: u32) -> Duration {
+        RETRY_DELAY * attempt
+    }
+
+    /// How long a call may take with all its retries, if each attempt may take `timeout`
+    pub fn call_budget(&self, timeout: Duration) -> Duration {
+        let delays: Duration = (1..=self.retries).map(|attempt| self.retry_delay(attempt)).sum();
+        timeout * (self.retries + 1) + delays
+    }
+}
+
+/// Failure counters shown in the UI
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct FailureStats {
+    /// Errors since the plugin was loaded (after retries)
+    pub total_errors: u64,
+    /// Errors within the policy's window
+    pub recent_errors: u32,
+    /// Retries made since the plugin was loaded
+    pub retries: u64,
+    /// How many times the policy disabled the plugin
+    pub auto_disabled: u32,
+    /// Seconds until the next attempt to enable the plugin again
+    pub reenable_in_secs: Option<u64>,
+}
+
+/// Failure bookkeeping of one loaded plugin
+#[derive(Debug, Default)]
+pub struct FailureTracker {
+    recent: VecDeque<Instant>,
+    total_errors: u64,
+    retries: u64,
+    auto_disabled: u32,
+    /// Disables in a row without a successful call in between
+    consecutive_disables: u32,
+    /// Set while the plugin is disabled by the policy
+    reenable_at: Option<Instant>,
+}
+
+impl FailureTracker {
+    pub fn record_retry(&mut self) {
+        self.retries += 1;
+    }
+
+    /// Count an error; returns true if the plugin ran out of its error budget
+    pub fn record_error(&mut self, policy: &FailurePolicy, now: Instant) -> bool {
+        self.total_errors += 1;
+        self.recent.push_back(now);
+        self.forget_old(policy, now);
+        self.recent.len() >= policy.max_errors as usize
+    }
+
+    pub fn record_success(&mut self) {
+        self.consecutive_disables = 0;
+    }
+
+    /// The plugin was disabled because of failures
+    pub fn disabled(&mut self, policy: &FailurePolicy, now: Instant) {
+        self.auto_disabled += 1;
+        self.postpone(policy, now);
+    }
+
+    /// Plan the next attempt to enable the plugin, each one after a longer pause
+    ///
+    /// Policies from the config file are not validated, so a pause too long to
+    /// represent falls back to [`MAX_REENABLE_DELAY`].
+    pub fn postpone(&mut self, policy: &FailurePolicy, now: Instant) {
+        self.consecutive_disables += 1;
+        self.reenable_at = match policy.reenable_after_secs {
+            0 => None,
+            secs => {
+                let base = Duration::from_secs(secs);
+                let factor = 1u32 << (self.consecutive_disables - 1).min(16);
+                let pause = base.checked_mul(factor).unwrap_or(Duration::MAX).min(base.max(MAX_REENABLE_DELAY));
+                now.checked_add(pause).or_else(|| now.checked_add(MAX_REENABLE_DELAY))
+            }
+        };
+    }
+
+    /// The user enabled or disabled the plugin: no automatic re-enable
+    pub fn reset_disable(&mut self) {
+        self.reenable_at = None;
+    }
+
+    /// Time to check whether the auto-disabled plugin can run again
+    pub fn reenable_due(&self, now: Instant) -> bool {
+        match self.reenable_at {
+            Some(at) => now >= at,
+            None => false,
+        }
+    }
+
+    /// When the worker should look at the plugin again (None if it needn't)
+    pub fn reenable_at(&self) -> Option<Instant> {
+        self.reenable_at
+    }
+
+    /// The plugin was enabled again: it gets a fresh error budget
+    pub fn reenabled(&mut self) {
+        self.reenable_at = None;
+        self.recent.clear();
+    }
+
+    pub fn stats(&self, policy: &FailurePolicy, now: Instant) -> FailureStats {
+        let window = Duration::from_secs(policy.window_secs);
+        FailureStats {
+            total_errors: self.total_errors,
+            recent_errors: self
+                .recent
+                .iter()
+                .filter(|&&at| now.duration_since(at) < window)
+                .count() as u32,
+            retries: self.retries,
+            auto_disabled: self.auto_disabled,
+            reenable_in_secs: self
+                .reenable_at
+                .map(|at| at.saturating_duration_since(now).as_secs()),
+        }
+    }
+
+    fn forget_old(&mut self, policy: &FailurePolicy, now: Instant) {
+        let window = Duration::from_secs(policy.window_secs);
+        while let Some(&oldest) = self.recent.front() {
+            if now.duration_since(oldest) < window {
+                break;
+            }
+            self.recent.pop_front();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_error_budget_and_backoff() {
+        let policy = FailurePolicy {
+            retries: 0,
+            max_errors: 2,
+            window_secs: 10,
+            reenable_after_secs: 5,
+        };
+        let mut tracker = FailureTracker::default();
+        let start = Instant::now();
+
+        // Errors far enough apart stay within the budget
+        assert!(!tracker.record_error(&policy, start));
+        assert!(!tracker.record_error(&policy, start + Duration::from_secs(11)));
+        assert!(tracker.record_error(&policy, start + Duration::from_secs(12)));
+
+        let now = start + Duration::from_secs(12);
+        tracker.disabled(&policy, now);
+        assert!(!tracker.reenable_due(now + Duration::from_secs(4)));
+        assert!(tracker.reenable_due(now + Duration::from_secs(5)));
+
+        // Still failing: the pause doubles
+        tracker.postpone(&policy, now + Duration::from_secs(5));
+        assert!(!tracker.reenable_due(now + Duration::from_secs(14)));
+        assert!(tracker.reenable_due(now + Duration::from_secs(15)));
+
+        tracker.reenabled();
+        let stats = tracker.stats(&policy, now + Duration::from_secs(15));
+        assert_eq!(stats.total_errors, 3);
+        assert_eq!(stats.recent_errors, 0);
+        assert_eq!(stats.auto_disabled, 1);
+        assert_eq!(stats.reenable_in_secs, None);
+
+        // Every attempt may use the full timeout, plus the pauses between them
+        assert_eq!(policy.call_budget(Duration::from_secs(3)), Duration::from_secs(3));
+        let retrying = FailurePolicy { retries: 2, ..policy.clone() };
+        assert_eq!(retrying.call_budget(Duration::from_secs(3)), Duration::from_millis(9300));
+
+        assert!(FailurePolicy { max_errors: 0, ..policy.clone() }.validate().is_err());
+        assert!(FailurePolicy { retries: MAX_RETRIES + 1, ..policy }.validate().is_err());
+    }
+
+    #[test]
+    fn test_huge_reenable_pause() {
+        let policy = FailurePolicy {
+            reenable_after_secs: 1_000_000_000_000_000,
+            ..FailurePolicy::default()
+        };
+        assert!(policy.validate().is_err());
+        assert!(FailurePolicy { reenable_after_secs: MAX_REENABLE_AFTER_SECS, ..policy.clone() }.validate().is_ok());
+
+        // Saved policies skip validation: the pause is capped instead of overflowing
+        let mut tracker = FailureTracker::default();
+        let now = Instant::now();
+        for _ in 0..20 {
+            tracker.postpone(&policy, now);
+            let at = tracker.reenable_at().unwrap();
+            assert!(at > now);
+        }
+
+        let endless = FailurePolicy { reenable_after_secs: u64::MAX, ..policy };
+        tracker.postpone(&endless, now);
+        assert!(tracker.reenable_at().unwrap() > now);
+    }
+}
@@ -0,0 +1,647 @@
+//! Out-of-process plugins: an executable spoken to over JSON-RPC 2.0 on stdio
+//!
+//! The host writes one request per line to the plugin's stdin and reads one
+//! response per line from its stdout; stderr goes to the app log. A crashing
+//! plugin can't take the app down: its process is restarted with backoff and
+//! gets its config again.
+//!
+//! Methods called by the host:
+//! - `initialize` -> `{ name, version, capabilities, config_schema }`
+//! - `set_config { config }`
+//! - `check_status` -> `{ status, detail?, error? }`
+//! - `on_text { text, message? }` (`message`: id, source, provider and voice)
+//! - `shutdown` (notification, the plugin should exit)
+
+use super::backend::PluginBackend;
+use super::manifest::ProcessManifest;
+use super::routing::TextMessage;
+use super::types::LoadedPlugin;
+use plugins_api::{capabilities, PluginStatus};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Child, ChildStdin, ChildStdout, Stdio};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, RecvTimeoutError, Sender};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+/// Operations a process plugin can provide
+const SUPPORTED_CAPABILITIES: u64 = capabilities::CONFIG | capabilities::STATUS | capabilities::TEXT;
+
+/// How long a started process may take to answer `initialize`
+const INIT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// How long a running process may take to answer a call
+/// (shorter than the worker timeout, so a slow process is reported by the backend)
+const RPC_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// How long the process gets to exit after `shutdown`
+const SHUTDOWN_GRACE: Duration = Duration::from_secs(1);
+
+/// Restart delays: doubled after each crash up to the maximum
+const INITIAL_BACKOFF: Duration = Duration::from_secs(1);
+const MAX_BACKOFF: Duration = Duration::from_secs(60);
+
+/// A process that ran this long without crashing restarts with the initial delay
+const STABLE_RUN: Duration = Duration::from_secs(30);
+
+/// Reply to `initialize`
+#[derive(Debug, Deserialize)]
+struct Hello {
+    name: String,
+    #[serde(default)]
+    version: String,
+    #[serde(default)]
+    capabilities: Vec<String>,
+    #[serde(default)]
+    config_schema: serde_json::Value,
+}
+
+/// Reply to `check_status`
+#[derive(Debug, Deserialize)]
+struct StatusReply {
+    status: String,
+    #[serde(default)]
+    detail: Option<String>,
+    #[serde(default)]
+    error: Option<String>,
+}
+
+enum RpcError {
+    /// The process is not running (crashed or restarting)
+    Down(String),
+    /// The call failed or timed out
+    Failed(String),
+}
+
+impl From<RpcError> for String {
+    fn from(error: RpcError) -> Self {
+        match error {
+            RpcError::Down(e) | RpcError::Failed(e) => e,
+        }
+    }
+}
+
+/// Pipes of the running process
+#[derive(Default)]
+struct Connection {
+    child: Option<Child>,
+    stdin: Option<ChildStdin>,
+    /// Requests waiting for a reply, by id
+    pending: HashMap<u64, Sender<Result<serde_json::Value, String>>>,
+    next_id: u64,
+    /// When the next start is attempted (None while running)
+    restart_at: Option<Instant>,
+    /// Handshake done, calls from the host may go through
+    ready: bool,
+}
+
+/// State shared with the supervisor and reader threads
+struct ProcessShared {
+    /// Plugin folder name (for log messages)
+    label: String,
+    dir: PathBuf,
+    manifest: ProcessManifest,
+    connection: Mutex<Connection>,
+    /// Last config, sent again after a restart
+    config: Mutex<Option<serde_json::Value>>,
+    stopping: AtomicBool,
+}
+
+impl ProcessShared {
+    fn connection(&self) -> MutexGuard<'_, Connection> {
+        self.connection.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    fn stopping(&self) -> bool {
+        self.stopping.load(Ordering::Acquire)
+    }
+
+    /// Start the process and return its stdout
+    fn spawn(&self) -> Result<ChildStdout, String> {
+        let mut child = self.start_process()?;
+        let stdout = child.stdout.take()
+            .ok_or_else(|| "Plugin process has no stdout".to_string())?;
+
+        // Whatever the plugin prints to stderr ends up in the app log
+        if let Some(stderr) = child.stderr.take() {
+            let label = self.label.clone();
+            std::thread::spawn(move || {
+                for line in BufReader::new(stderr).lines().map_while(Result::ok) {
+                    eprintln!("[Plugin {}] {}", label, line);
+                }
+            });
+        }
+
+        let mut connection = self.connection();
+        connection.stdin = child.stdin.take();
+        connection.child = Some(child);
+        connection.restart_at = None;
+        connection.ready = false;
+        Ok(stdout)
+    }
+
+    /// Start the first of the manifest's commands that is installed
+    fn start_process(&self) -> Result<Child, String> {
+        let mut not_found = None;
+        for mut command in self.manifest.commands(&self.dir) {
+            command.stdin(Stdio::piped()).stdout(Stdio::piped()).stderr(Stdio::piped());
+            #[cfg(target_os = "windows")]
+            {
+                use std::os::windows::process::CommandExt;
+                // CREATE_NO_WINDOW: don't flash a console window for every start
+                command.creation_flags(0x0800_0000);
+            }
+
+            match command.spawn() {
+                Ok(child) => return Ok(child),
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => not_found = Some(e),
+                Err(e) => {
+                    let program = command.get_program().to_string_lossy().into_owned();
+                    return Err(format!("Failed to start plugin process '{}': {}", program, e));
+                }
+            }
+        }
+        let error = not_found.map(|e| e.to_string()).unwrap_or_default();
+        Err(format!("Failed to start plugin process '{}': {}", self.manifest.command, error))
+    }
+
+    /// Why calls can't be made right now
+    fn down_reason(connection: &Connection) -> String {
+        if connection.child.is_some() && !connection.ready {
+            return "Plugin process is starting".to_string();
+        }
+        match connection.restart_at {
+            Some(at) => format!(
+                "Plugin process is restarting (next attempt in {} s)",
+                at.saturating_duration_since(Instant::now()).as_secs() + 1
+            ),
+            None => "Plugin process is not running".to_string(),
+        }
+    }
+
+    fn write_line(connection: &mut Connection, message: &serde_json::Value) -> Result<(), RpcError> {
+        let stdin = match connection.stdin.as_mut() {
+            Some(stdin) => stdin,
+            None => return Err(RpcError::Down(Self::down_reason(connection))),
+        };
+        writeln!(stdin, "{}", message)
+            .and_then(|_| stdin.flush())
+            .map_err(|e| RpcError::Down(format!("Failed to write to plugin process: {}", e)))
+    }
+
+    /// Call a method and wait for the reply
+    fn request(&self, method: &str, params: serde_json::Value, timeout: Duration) -> Result<serde_json::Value, RpcError> {
+        self.send_request(method, params, timeout, false)
+    }
+
+    /// `handshake` calls go through before the process is ready
+    fn send_request(
+        &self,
+        method: &str,
+        params: serde_json::Value,
+        timeout: Duration,
+        handshake: bool,
+    ) -> Result<serde_json::Value, RpcError> {
+        let (reply, result) = mpsc::channel();
+        let id = {
+            let mut connection = self.connection();
+            if !connection.ready && !handshake {
+                return Err(RpcError::Down(Self::down_reason(&connection)));
+            }
+            let id = connection.next_id;
+            connection.next_id += 1;
+
+            let message = serde_json::json!({
+                "jsonrpc": "2.0",
+                "id": id,
+                "method": method,
+                "params": params,
+            });
+            Self::write_line(&mut connection, &message)?;
+            connection.pending.insert(id, reply);
+            id
+        };
+
+        match result.recv_timeout(timeout) {
+            Ok(Ok(value)) => Ok(value),
+            Ok(Err(e)) => Err(RpcError::Failed(e)),
+            Err(RecvTimeoutError::Timeout) => {
+                self.connection().pending.remove(&id);
+                Err(RpcError::Failed(format!(
+                    "Plugin process did not answer '{}' within {} ms",
+                    method,
+                    timeout.as_millis()
+                )))
+            }
+            Err(RecvTimeoutError::Disconnected) => Err(RpcError::Down("Plugin process exited".to_string())),
+        }
+    }
+
+    /// Send a method without waiting for a reply
+    fn notify(&self, method: &str) -> Result<(), RpcError> {
+        let message = serde_json::json!({ "jsonrpc": "2.0", "method": method });
+        Self::write_line(&mut self.connection(), &message)
+    }
+
+    /// Handshake with a freshly started process
+    fn initialize(&self) -> Result<Hello, String> {
+        let params = serde_json::json!({ "protocol": 1 });
+        let hello = self.send_request("initialize", params, INIT_TIMEOUT, true)?;
+        let hello: Hello = serde_json::from_value(hello)
+            .map_err(|e| format!("Invalid initialize reply: {}", e))?;
+
+        // A restarted process gets the config the previous one had
+        let config = self.config.lock().ok().and_then(|c| c.clone());
+        if let Some(config) = config {
+            self.send_request("set_config", serde_json::json!({ "config": config }), RPC_TIMEOUT, true)?;
+        }
+
+        self.connection().ready = true;
+        Ok(hello)
+    }
+
+    /// Route replies from stdout to waiting requests until the process closes it
+    fn read_replies(&self, stdout: ChildStdout) {
+        for line in BufReader::new(stdout).lines().map_while(Result::ok) {
+            let message: serde_json::Value = match serde_json::from_str(&line) {
+                Ok(message) => message,
+                Err(_) => {
+                    eprintln!("[Plugin {}] Ignoring non-JSON output: {}", self.label, line);
+                    continue;
+                }
+            };
+
+            let id = match message.get("id").and_then(|id| id.as_u64()) {
+                Some(id) => id,
+                None => continue,
+            };
+            let reply = match message.get("error") {
+                Some(error) => Err(error
+                    .get("message")
+                    .and_then(|m| m.as_str())
+                    .unwrap_or("Plugin returned an error")
+                    .to_string()),
+                None => Ok(message.get("result").cloned().unwrap_or(serde_json::Value::Null)),
+            };
+
+            let waiting = self.connection().pending.remove(&id);
+            if let Some(waiting) = waiting {
+                let _ = waiting.send(reply);
+            }
+        }
+
+        // No more replies will come: requests still waiting (e.g. `initialize`
+        // of a process killed by `drop`) fail now instead of at their timeout
+        self.connection().pending.clear();
+    }
+
+    /// Collect the exited process; waiting requests fail
+    fn reap(&self) -> String {
+        let child = {
+            let mut connection = self.connection();
+            connection.stdin = None;
+            connection.ready = false;
+            connection.pending.clear();
+            connection.child.take()
+        };
+
+        match child {
+            Some(mut child) => {
+                // It closed stdout, so it is exiting anyway
+                let _ = child.kill();
+                match child.wait() {
+                    Ok(status) => status.to_string(),
+                    Err(e) => e.to_string(),
+                }
+            }
+            None => "not running".to_string(),
+        }
+    }
+
+    /// Sleep unless the plugin is being unloaded; returns false if it is
+    fn sleep(&self, duration: Duration) -> bool {
+        let until = Instant::now() + duration;
+        while Instant::now() < until {
+            if self.stopping() {
+                return false;
+            }
+            std::thread::sleep(Duration::from_millis(50));
+        }
+        !self.stopping()
+    }
+}
+
+/// Keep the process running: start it, wait until it exits, restart with backoff
+fn supervise(shared: Arc<ProcessShared>, ready: Sender<Result<Hello, String>>) {
+    let mut ready = Some(ready);
+    let mut backoff = INITIAL_BACKOFF;
+
+    while !shared.stopping() {
+        let started = Instant::now();
+
+        match shared.spawn() {
+            Ok(stdout) => {
+                let reader_shared = Arc::clone(&shared);
+                let reader = std::thread::spawn(move || reader_shared.read_replies(stdout));
+
+                let hello = shared.initialize();
+                match ready.take() {
+                    Some(ready) => {
+                        // The first start decides whether the plugin loads at all
+                        let failed = hello.is_err();
+                        let _ = ready.send(hello);
+                        if failed {
+                            shared.stopping.store(true, Ordering::Release);
+                            shared.reap();
+                            let _ = reader.join();
+                            return;
+                        }
+                    }
+                    None => match hello {
+                        Ok(_) => eprintln!("[Plugin {}] Process restarted", shared.label),
+                        Err(e) => eprintln!("[Plugin {}] Restarted process failed to initialize: {}", shared.label, e),
+                    },
+                }
+
+                let _ = reader.join();
+                let status = shared.reap();
+                if shared.stopping() {
+                    break;
+                }
+                if started.elapsed() >= STABLE_RUN {
+                    backoff = INITIAL_BACKOFF;
+                }
+                eprintln!("[Plugin {}] Process exited ({}), restarting in {} s",
+                    shared.label, status, backoff.as_secs());
+            }
+            Err(e) => {
+                if let Some(ready) = ready.take() {
+                    let _ = ready.send(Err(e));
+                    return;
+                }
+                eprintln!("[Plugin {}] {}, retrying in {} s", shared.label, e, backoff.as_secs());
+            }
+        }
+
+        shared.connection().restart_at = Some(Instant::now() + backoff);
+        if !shared.sleep(backoff) {
+            break;
+        }
+        backoff = (backoff * 2).min(MAX_BACKOFF);
+    }
+}
+
+/// Plugin running as a separate process
+pub struct ProcessPlugin {
+    shared: Arc<ProcessShared>,
+    supervisor: Option<JoinHandle<()>>,
+    /// Detail and error from the last `check_status` reply
+    last_status: Mutex<(Option<String>, Option<String>)>,
+}
+
+impl ProcessPlugin {
+    /// Start the process and wait for its `initialize` reply
+    fn start(dir: &Path, manifest: ProcessManifest) -> Result<(Self, Hello), String> {
+        let shared = Arc::new(ProcessShared {
+            label: dir
+                .file_name()
+                .map(|n| n.to_string_lossy().to_string())
+                .unwrap_or_default(),
+            dir: dir.to_path_buf(),
+            manifest,
+            connection: Mutex::new(Connection::default()),
+            config: Mutex::new(None),
+            stopping: AtomicBool::new(false),
+        });
+
+        let (ready, hello) = mpsc::channel();
+        let supervisor_shared = Arc::clone(&shared);
+        let supervisor = std::thread::Builder::new()
+            .name(format!("plugin process {}", shared.label))
+            .spawn(move || supervise(supervisor_shared, ready))
+            .map_err(|e| format!("Failed to start plugin supervisor: {}", e))?;
+
+        let plugin = Self {
+            shared,
+            supervisor: Some(supervisor),
+            last_status: Mutex::new((None, None)),
+        };
+        match hello.recv() {
+            Ok(Ok(hello)) => Ok((plugin, hello)),
+            Ok(Err(e)) => Err(e),
+            Err(_) => Err("Plugin supervisor stopped".to_string()),
+        }
+    }
+
+    fn set_last_status(&self, detail: Option<String>, error: Option<String>) {
+        if let Ok(mut last_status) = self.last_status.lock() {
+            *last_status = (detail, error);
+        }
+    }
+}
+
+impl PluginBackend for ProcessPlugin {
+    fn api_version(&self) -> String {
+        "JSON-RPC".to_string()
+    }
+
+    fn check_status(&self) -> PluginStatus {
+        match self.shared.request("check_status", serde_json::Value::Null, RPC_TIMEOUT) {
+            Ok(reply) => match serde_json::from_value::<StatusReply>(reply) {
+                Ok(reply) => {
+                    self.set_last_status(reply.detail, reply.error);
+                    match reply.status.as_str() {
+                        "ok" => PluginStatus::Ok,
+                        "not_configured" => PluginStatus::NotConfigured,
+                        "connection_failed" => PluginStatus::ConnectionFailed,
+                        "auth_failed" => PluginStatus::AuthFailed,
+                        _ => PluginStatus::UnknownError,
+                    }
+                }
+                Err(e) => {
+                    self.set_last_status(None, Some(format!("Invalid check_status reply: {}", e)));
+                    PluginStatus::UnknownError
+                }
+            },
+            Err(e) => {
+                self.set_last_status(None, Some(e.into()));
+                PluginStatus::UnknownError
+            }
+        }
+    }
+
+    fn plugin_error(&self) -> Option<String> {
+        self.last_status.lock().ok().and_then(|s| s.1.clone())
+    }
+
+    fn status_detail(&self) -> Option<String> {
+        self.last_status.lock().ok().and_then(|s| s.0.clone())
+    }
+
+    fn set_config(&mut self, config: &serde_json::Value) -> Result<(), String> {
+        if let Ok(mut saved) = self.shared.config.lock() {
+            *saved = Some(config.clone());
+        }
+
+        match self.shared.request("set_config", serde_json::json!({ "config": config }), RPC_TIMEOUT) {
+            Ok(_) => Ok(()),
+            // Applied when the process is back
+            Err(RpcError::Down(reason)) => {
+                eprintln!("[Plugin {}] Config saved for restart: {}", self.shared.label, reason);
+                Ok(())
+            }
+            Err(RpcError::Failed(e)) => Err(e),
+        }
+    }
+
+    fn on_text(&mut self, text: &str) -> Result<(), String> {
+        self.send_text(serde_json::json!({ "text": text }))
+    }
+
+    fn on_message(&mut self, message: &TextMessage) -> Result<(), String> {
+        self.send_text(serde_json::json!({ "text": message.text, "message": message.details() }))
+    }
+}
+
+impl ProcessPlugin {
+    fn send_text(&self, params: serde_json::Value) -> Result<(), String> {
+        match self.shared.request("on_text", params, RPC_TIMEOUT) {
+            Ok(_) => Ok(()),
+            // A crash is handled by restarting, not by disabling the plugin
+            Err(RpcError::Down(reason)) => {
+                eprintln!("[Plugin {}] Text dropped: {}", self.shared.label, reason);
+                Ok(())
+            }
+            Err(RpcError::Failed(e)) => Err(e),
+        }
+    }
+}
+
+impl Drop for ProcessPlugin {
+    fn drop(&mut self) {
+        self.shared.stopping.store(true, Ordering::Release);
+
+        // Ask the process to exit, then close its stdin; kill it if it doesn't
+        let _ = self.shared.notify("shutdown");
+        self.shared.connection().stdin = None;
+        let deadline = Instant::now() + SHUTDOWN_GRACE;
+        loop {
+            {
+                let mut connection = self.shared.connection();
+                match connection.child.as_mut() {
+                    Some(child) if Instant::now() >= deadline => {
+                        let _ = child.kill();
+                        break;
+                    }
+                    Some(child) => {
+                        if let Ok(Some(_)) = child.try_wait() {
+                            break;
+                        }
+                    }
+                    None => break,
+                }
+            }
+            std::thread::sleep(Duration::from_millis(20));
+        }
+
+        if let Some(supervisor) = self.supervisor.take() {
+            let _ = supervisor.join();
+        }
+    }
+}
+
+/// Start a process plugin described by a manifest
+pub fn load_process_plugin(manifest_path: &Path, process: ProcessManifest) -> Result<LoadedPlugin, String> {
+    let dir = manifest_path.parent()
+        .ok_or_else(|| "Manifest has no parent folder".to_string())?;
+
+    let (plugin, hello) = ProcessPlugin::start(dir, process)?;
+
+    let mut declared = 0;
+    for name in &hello.capabilities {
+        match capabilities::ALL.iter().find(|(_, n)| n == name) {
+            Some((flag, _)) => declared |= flag,
+            None => eprintln!("[Plugins] '{}' declares unknown capability '{}'", hello.name, name),
+        }
+    }
+    let capabilities = declared & SUPPORTED_CAPABILITIES;
+    if capabilities != declared {
+        eprintln!("[Plugins] '{}': process plugins don't support {:?} yet",
+            hello.name, capabilities::names(declared & !SUPPORTED_CAPABILITIES));
+    }
+
+    eprintln!("[Plugins] Loaded '{}' v{} (process, capabilities: {:?})",
+        hello.name, hello.version, capabilities::names(capabilities));
+
+    let config_schema = if hello.config_schema.is_null() {
+        serde_json::json!({})
+    } else {
+        hello.config_schema
+    };
+    Ok(LoadedPlugin::new(
+        Box::new(plugin),
+        manifest_path.to_path_buf(),
+        hello.name,
+        hello.version,
+        capabilities,
+        config_schema,
+    ))
+}
+
+#[cfg(test)]
+pub(super) mod tests {
+    use super::*;
+    use crate::plugins::dynamic::tests::repo_root;
+    use crate::plugins::manifest::PluginManifest;
+
+    /// Manifest of the Python example from the `plugins` folder
+    ///
+    /// Panics unless Python 3 is installed as `python` or `python3` (the
+    /// commands its manifest tries).
+    pub(in crate::plugins) fn python_example() -> PathBuf {
+        let installed = ["python", "python3"].iter().any(|python| {
+            std::process::Command::new(python)
+                .args(["-c", "import sys; sys.exit(sys.version_info[0] != 3)"])
+                .stdout(Stdio::null())
+                .stderr(Stdio::null())
+                .status()
+                .map(|status| status.success())
+                .unwrap_or(false)
+        });
+        assert!(installed, "this test needs Python 3 on PATH (python or python3)");
+        repo_root().join("plugins").join("python-example").join("plugin.toml")
+    }
+
+    #[test]
+    fn test_process_plugin_restarts_after_crash() {
+        let manifest_path = python_example();
+        let manifest = PluginManifest::load(&manifest_path).unwrap().process.unwrap();
+        let (mut plugin, hello) = ProcessPlugin::start(manifest_path.parent().unwrap(), manifest).unwrap();
+        assert_eq!(hello.name, "Python Example");
+
+        plugin.set_config(&serde_json::json!({ "prefix": "#" })).unwrap();
+        plugin.on_text("hello").unwrap();
+        assert_eq!(plugin.check_status(), PluginStatus::Ok);
+        assert!(plugin.status_detail().unwrap().contains("1"));
+
+        // Kill the process behind the host's back
+        if let Some(child) = plugin.shared.connection().child.as_mut() {
+            child.kill().unwrap();
+        }
+        std::thread::sleep(Duration::from_millis(200));
+        assert_eq!(plugin.check_status(), PluginStatus::UnknownError);
+        assert!(plugin.on_text("dropped").is_ok());
+
+        // It comes back with the same config
+        let deadline = Instant::now() + Duration::from_secs(10);
+        while plugin.check_status() != PluginStatus::Ok {
+            assert!(Instant::now() < deadline, "process was not restarted");
+            std::thread::sleep(Duration::from_millis(100));
+        }
+        assert!(plugin.status_detail().unwrap().contains("#"));
+    }
+}
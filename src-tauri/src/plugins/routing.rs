@@ -0,0 +1,174 @@
+//! Which spoken messages a plugin receives
+//!
+//! Every plugin has routing rules, saved with its settings. A message reaches
+//! the plugin's `on_text` only if it passes all of them: include/exclude
+//! regexes, a minimum length, where the message came from and which TTS
+//! provider speaks it. Empty rules let everything through.
+
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+
+/// Where a spoken message came from
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MessageSource {
+    /// Typed in the app
+    Typed,
+    /// Played again from the history (`repeat_tts_message`)
+    Repeated,
+    /// Added by a plugin through `enqueue_text`
+    Api,
+}
+
+/// A spoken message as offered to plugins
+#[derive(Debug, Clone)]
+pub struct TextMessage {
+    pub text: String,
+    pub source: MessageSource,
+    /// Provider id as in the TTS settings ("system", "openai", "plugin:<id>", ...)
+    pub provider: String,
+    /// Id of the history message, if it was added to the history
+    pub id: Option<String>,
+    /// Voice of the provider, if known
+    pub voice: Option<String>,
+}
+
+impl TextMessage {
+    /// Details passed to plugins along with the text (see `MESSAGE_DETAILS_EXAMPLE`)
+    pub fn details(&self) -> serde_json::Value {
+        serde_json::json!({
+            "id": self.id,
+            "source": self.source,
+            "provider": self.provider,
+            "voice": self.voice,
+        })
+    }
+}
+
+/// Per-plugin message filter, saved with the plugin's settings
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RoutingRules {
+    /// Regexes of which at least one must match (empty: any text)
+    pub include: Vec<String>,
+    /// Regexes of which none may match
+    pub exclude: Vec<String>,
+    /// Fewer characters than this (ignoring surrounding spaces) are skipped
+    pub min_length: usize,
+    /// Accepted sources (empty: all)
+    pub sources: Vec<MessageSource>,
+    /// Accepted providers (empty: all)
+    pub providers: Vec<String>,
+}
+
+impl RoutingRules {
+    /// Check the regexes and build the filter
+    pub fn compile(self) -> Result<Router, String> {
+        let include = compile_all(&self.include)?;
+        let exclude = compile_all(&self.exclude)?;
+        Ok(Router {
+            rules: self,
+            include,
+            exclude,
+        })
+    }
+}
+
+fn compile_all(patterns: &[String]) -> Result<Vec<Regex>, String> {
+    patterns
+        .iter()
+        .map(|pattern| Regex::new(pattern).map_err(|e| format!("Invalid regex '{}': {}", pattern, e)))
+        .collect()
+}
+
+/// Routing rules with their regexes compiled
+#[derive(Debug, Clone, Default)]
+pub struct Router {
+    rules: RoutingRules,
+    include: Vec<Regex>,
+    exclude: Vec<Regex>,
+}
+
+impl Router {
+    pub fn rules(&self) -> &RoutingRules {
+        &self.rules
+    }
+
+    /// Whether the message passes the rules
+    pub fn matches(&self, message: &TextMessage) -> bool {
+        let rules = &self.rules;
+        if !rules.sources.is_empty() && !rules.sources.contains(&message.source) {
+            return false;
+        }
+        if !rules.providers.is_empty()
+            && !rules.providers.iter().any(|p| p.eq_ignore_ascii_case(&message.provider))
+        {
+            return false;
+        }
+        if message.text.trim().chars().count() < rules.min_length {
+            return false;
+        }
+        if !self.include.is_empty() && !self.include.iter().any(|r| r.is_match(&message.text)) {
+            return false;
+        }
+        !self.exclude.iter().any(|r| r.is_match(&message.text))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn message(text: &str, source: MessageSource) -> TextMessage {
+        TextMessage {
+            text: text.to_string(),
+            source,
+            provider: "openai".to_string(),
+            id: None,
+            voice: None,
+        }
+    }
+
+    #[test]
+    fn test_routing_rules() {
+        let all = Router::default();
+        assert!(all.matches(&message("", MessageSource::Repeated)));
+
+        let router = RoutingRules {
+            include: vec!["^!".to_string(), "(?i)привет".to_string()],
+            exclude: vec!["spam".to_string()],
+            min_length: 3,
+            sources: vec![MessageSource::Typed, MessageSource::Api],
+            providers: vec!["OpenAI".to_string()],
+        }
+        .compile()
+        .unwrap();
+
+        assert!(router.matches(&message("!hello", MessageSource::Typed)));
+        assert!(router.matches(&message("ПРИВЕТ всем", MessageSource::Api)));
+        // Repeats are not forwarded again
+        assert!(!router.matches(&message("!hello", MessageSource::Repeated)));
+        assert!(!router.matches(&message("hello", MessageSource::Typed)));
+        assert!(!router.matches(&message("!spam", MessageSource::Typed)));
+        assert!(!router.matches(&message(" !a ", MessageSource::Typed)));
+
+        let mut other = message("!hello", MessageSource::Typed);
+        other.provider = "system".to_string();
+        assert!(!router.matches(&other));
+
+        let err = RoutingRules {
+            exclude: vec!["(".to_string()],
+            ..Default::default()
+        }
+        .compile()
+        .unwrap_err();
+        assert!(err.starts_with("Invalid regex '('"));
+
+        let mut details = message("hi", MessageSource::Repeated);
+        details.id = Some("42".to_string());
+        assert_eq!(
+            details.details(),
+            serde_json::json!({"id": "42", "source": "repeated", "provider": "openai", "voice": null})
+        );
+    }
+}
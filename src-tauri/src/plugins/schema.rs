@@ -0,0 +1,227 @@
+//! Checking plugin config against the plugin's `config_schema`
+//!
+//! The host understands the subset of JSON Schema the settings form uses:
+//! `properties` with `type`, `default`, `enum`, `minLength`/`maxLength`,
+//! `minimum`/`maximum`, plus the `required` list. Properties with
+//! `format: "password"` are secrets: the UI only ever sees them masked.
+
+use serde::Serialize;
+use serde_json::{Map, Value};
+
+/// Shown instead of a secret value; sending it back keeps the stored value
+pub const SECRET_MASK: &str = "********";
+
+/// Problem with one config field
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct FieldError {
+    /// Property name (empty for the config as a whole)
+    pub field: String,
+    pub message: String,
+}
+
+impl FieldError {
+    fn new(field: &str, message: impl Into<String>) -> Self {
+        Self {
+            field: field.to_string(),
+            message: message.into(),
+        }
+    }
+}
+
+fn properties(schema: &Value) -> Option<&Map<String, Value>> {
+    schema.get("properties").and_then(|p| p.as_object())
+}
+
+fn is_secret(property: &Value) -> bool {
+    property.get("format").and_then(|f| f.as_str()) == Some("password")
+        // Older plugins mark secrets with `secret: true`
+        || property.get("secret").and_then(|s| s.as_bool()) == Some(true)
+}
+
+/// Check `config` against `schema` and fill in defaults of missing properties
+/// Returns the config to hand to the plugin, or every problem found
+pub fn validate_config(schema: &Value, config: &Value) -> Result<Value, Vec<FieldError>> {
+    let properties = match properties(schema) {
+        Some(properties) => properties,
+        None => return Ok(config.clone()),
+    };
+    let mut values = match config {
+        Value::Object(values) => values.clone(),
+        Value::Null => Map::new(),
+        _ => return Err(vec![FieldError::new("", "Config must be an object")]),
+    };
+    let required: Vec<&str> = schema
+        .get("required")
+        .and_then(|r| r.as_array())
+        .map(|r| r.iter().filter_map(|name| name.as_str()).collect())
+        .unwrap_or_default();
+
+    let mut errors = Vec::new();
+    for (name, property) in properties {
+        // The form sends untouched text fields as empty strings
+        let missing = match values.get(name) {
+            None | Some(Value::Null) => true,
+            Some(Value::String(s)) => s.is_empty(),
+            _ => false,
+        };
+        if missing {
+            if let Some(default) = property.get("default") {
+                values.insert(name.clone(), default.clone());
+            } else if required.contains(&name.as_str()) {
+                errors.push(FieldError::new(name, "Required"));
+            }
+            continue;
+        }
+
+        if let Err(message) = check_value(property, &values[name]) {
+            errors.push(FieldError::new(name, message));
+        }
+    }
+
+    if errors.is_empty() {
+        Ok(Value::Object(values))
+    } else {
+        Err(errors)
+    }
+}
+
+fn check_value(property: &Value, value: &Value) -> Result<(), String> {
+    let type_ok = match property.get("type").and_then(|t| t.as_str()) {
+        Some("string") => value.is_string(),
+        Some("number") => value.is_number(),
+        Some("integer") => value.is_i64() || value.is_u64(),
+        Some("boolean") => value.is_boolean(),
+        Some("array") => value.is_array(),
+        Some("object") => value.is_object(),
+        _ => true,
+    };
+    if !type_ok {
+        let expected = property["type"].as_str().unwrap_or_default();
+        return Err(format!("Expected {}", expected));
+    }
+
+    if let Some(options) = property.get("enum").and_then(|e| e.as_array()) {
+        if !options.contains(value) {
+            let options: Vec<String> = options.iter().map(|o| o.to_string()).collect();
+            return Err(format!("Must be one of: {}", options.join(", ")));
+        }
+    }
+
+    if let Some(text) = value.as_str() {
+        let length = text.chars().count() as u64;
+        if let Some(min) = property.get("minLength").and_then(|m| m.as_u64()) {
+            if length < min {
+                return Err(format!("Must be at least {} characters", min));
+            }
+        }
+        if let Some(max) = property.get("maxLength").and_then(|m| m.as_u64()) {
+            if length > max {
+                return Err(format!("Must be at most {} characters", max));
+            }
+        }
+    }
+
+    if let Some(number) = value.as_f64() {
+        if let Some(min) = property.get("minimum").and_then(|m| m.as_f64()) {
+            if number < min {
+                return Err(format!("Must be at least {}", min));
+            }
+        }
+        if let Some(max) = property.get("maximum").and_then(|m| m.as_f64()) {
+            if number > max {
+                return Err(format!("Must be at most {}", max));
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Config as shown to the UI: secret values replaced with [`SECRET_MASK`]
+pub fn mask_secrets(schema: &Value, config: &Value) -> Value {
+    let mut masked = config.clone();
+    if let (Some(properties), Some(values)) = (properties(schema), masked.as_object_mut()) {
+        for (name, property) in properties {
+            if !is_secret(property) {
+                continue;
+            }
+            if let Some(value) = values.get_mut(name) {
+                if value.as_str().map(|s| !s.is_empty()).unwrap_or(false) {
+                    *value = Value::String(SECRET_MASK.to_string());
+                }
+            }
+        }
+    }
+    masked
+}
+
+/// Config from the UI with masked secrets replaced by the stored values
+pub fn unmask_secrets(schema: &Value, config: &Value, stored: &Value) -> Value {
+    let mut unmasked = config.clone();
+    if let (Some(properties), Some(values)) = (properties(schema), unmasked.as_object_mut()) {
+        for (name, property) in properties {
+            if !is_secret(property) || values.get(name).and_then(|v| v.as_str()) != Some(SECRET_MASK) {
+                continue;
+            }
+            match stored.get(name) {
+                Some(value) => {
+                    values.insert(name.clone(), value.clone());
+                }
+                None => {
+                    values.remove(name);
+                }
+            }
+        }
+    }
+    unmasked
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn schema() -> Value {
+        json!({
+            "type": "object",
+            "required": ["url"],
+            "properties": {
+                "url": { "type": "string", "minLength": 8 },
+                "port": { "type": "integer", "default": 8080, "minimum": 1, "maximum": 65535 },
+                "mode": { "type": "string", "enum": ["fast", "slow"] },
+                "token": { "type": "string", "format": "password" }
+            }
+        })
+    }
+
+    #[test]
+    fn test_validate_config() {
+        let config = validate_config(&schema(), &json!({ "url": "http://host" })).unwrap();
+        assert_eq!(config, json!({ "url": "http://host", "port": 8080 }));
+
+        let errors = validate_config(&schema(), &json!({ "url": "", "port": 0, "mode": "other" })).unwrap_err();
+        let mut fields: Vec<&str> = errors.iter().map(|e| e.field.as_str()).collect();
+        fields.sort();
+        assert_eq!(fields, vec!["mode", "port", "url"]);
+        assert!(errors.contains(&FieldError::new("url", "Required")));
+
+        let errors = validate_config(&schema(), &json!({ "url": 42 })).unwrap_err();
+        assert_eq!(errors, vec![FieldError::new("url", "Expected string")]);
+
+        // Without properties there is nothing to check
+        assert_eq!(validate_config(&json!({}), &json!([1])).unwrap(), json!([1]));
+    }
+
+    #[test]
+    fn test_secrets_are_masked() {
+        let stored = json!({ "url": "http://host", "token": "s3cret" });
+        let masked = mask_secrets(&schema(), &stored);
+        assert_eq!(masked["token"], SECRET_MASK);
+        assert_eq!(masked["url"], "http://host");
+
+        // An unchanged mask keeps the secret, a new value replaces it
+        assert_eq!(unmask_secrets(&schema(), &masked, &stored), stored);
+        let changed = json!({ "url": "http://host", "token": "new" });
+        assert_eq!(unmask_secrets(&schema(), &changed, &stored), changed);
+    }
+}
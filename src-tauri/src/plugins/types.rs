@@ -1,7 +1,13 @@
 //! Plugin types and structures
 
-use plugins_api::{PluginStatus, PluginVTable};
+use super::backend::PluginBackend;
+use super::manifest::{default_plugin_id, PluginGrants};
+use super::policy::{FailurePolicy, FailureStats, FailureTracker};
+use super::routing::{Router, RoutingRules, TextMessage};
+use super::schema::FieldError;
+use plugins_api::{capabilities, PluginStatus};
 use serde::{Deserialize, Serialize};
+use std::time::Instant;
 
 /// Serializable wrapper for PluginStatus
 #[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
@@ -38,9 +44,12 @@ impl From<SerializablePluginStatus> for PluginStatus {
     }
 }
 
-/// Information about a loaded plugin for UI
+/// Information about a plugin for UI (including plugins that failed to load)
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct PluginInfo {
+    /// Stable id (settings are saved under it)
+    pub id: String,
+    /// Display name
     pub name: String,
     pub version: String,
     pub enabled: bool,
@@ -48,6 +57,106 @@ pub struct PluginInfo {
     pub config_schema: serde_json::Value,
     pub config: serde_json::Value,
     pub last_error: Option<String>,
+    /// Free-form status text reported by the plugin
+    pub status_detail: Option<String>,
+    /// False if the library could not be loaded (see `last_error`)
+    pub loaded: bool,
+    /// Plugin ABI version as "major.minor" (None if unknown)
+    pub api_version: Option<String>,
+    /// Declared capabilities (e.g. "config", "text")
+    pub capabilities: Vec<String>,
+    /// Position in the text transform chain (None if the plugin doesn't transform text)
+    pub transform_position: Option<usize>,
+    /// Description, author and homepage from the plugin's manifest
+    pub metadata: PluginMetadata,
+    /// What happens when the plugin's calls fail
+    pub failure_policy: FailurePolicy,
+    /// Error counters since the plugin was loaded
+    pub failures: FailureStats,
+    /// Rights of a sandboxed plugin (None: native or process plugin with full access)
+    pub grants: Option<PluginGrants>,
+    /// Which spoken messages the plugin receives
+    pub routing: RoutingRules,
+}
+
+/// Optional plugin details from `plugin.toml`
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct PluginMetadata {
+    pub description: Option<String>,
+    pub author: Option<String>,
+    pub homepage: Option<String>,
+}
+
+/// Plugin library that was found but could not be loaded
+#[derive(Debug, Clone)]
+pub struct FailedPlugin {
+    /// Library or manifest that failed to load
+    pub path: std::path::PathBuf,
+    /// Id derived from the path (or the manifest, if it could be read)
+    pub id: String,
+    /// File name of the library
+    pub file_name: String,
+    /// Why loading failed
+    pub error: String,
+}
+
+impl FailedPlugin {
+    /// Get plugin info for UI
+    pub fn info(&self) -> PluginInfo {
+        PluginInfo {
+            id: self.id.clone(),
+            name: self.file_name.clone(),
+            version: String::new(),
+            enabled: false,
+            status: SerializablePluginStatus::UnknownError,
+            config_schema: serde_json::json!({}),
+            config: serde_json::json!({}),
+            last_error: Some(self.error.clone()),
+            status_detail: None,
+            loaded: false,
+            api_version: None,
+            capabilities: Vec::new(),
+            transform_position: None,
+            metadata: PluginMetadata::default(),
+            failure_policy: FailurePolicy::default(),
+            failures: FailureStats::default(),
+            grants: None,
+            routing: RoutingRules::default(),
+        }
+    }
+}
+
+/// Why a plugin config was not applied
+#[derive(Debug, Clone, Serialize)]
+pub struct ConfigError {
+    pub message: String,
+    /// Problems with single fields found by the host (empty if the plugin rejected the config)
+    pub fields: Vec<FieldError>,
+}
+
+impl ConfigError {
+    pub fn invalid(fields: Vec<FieldError>) -> Self {
+        let names: Vec<&str> = fields.iter().map(|f| f.field.as_str()).collect();
+        Self {
+            message: format!("Invalid config: {}", names.join(", ")),
+            fields,
+        }
+    }
+}
+
+impl From<String> for ConfigError {
+    fn from(message: String) -> Self {
+        Self {
+            message,
+            fields: Vec::new(),
+        }
+    }
+}
+
+impl std::fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.message)
+    }
 }
 
 /// Configuration stored for a plugin
@@ -56,19 +165,26 @@ pub struct PluginConfig {
     pub enabled: bool,
     pub config: serde_json::Value,
     pub last_error: Option<String>,
+    #[serde(default)]
+    pub failure_policy: FailurePolicy,
+    #[serde(default)]
+    pub routing: RoutingRules,
 }
 
 /// Internal loaded plugin state
 pub struct LoadedPlugin {
-    /// Library handle (kept to prevent unloading)
-    #[allow(dead_code)]
-    pub library: libloading::Library,
-    /// Plugin vtable
-    pub vtable: PluginVTable,
-    /// Opaque plugin data pointer
-    pub data: *mut std::ffi::c_void,
-    /// Plugin name (cached)
+    /// Native library or external process behind this plugin
+    pub backend: Box<dyn PluginBackend>,
+    /// Library or manifest the plugin was loaded from
+    pub source: std::path::PathBuf,
+    /// Capability flags backed by operations the plugin actually provides
+    pub capabilities: u64,
+    /// Stable id (see `manifest::default_plugin_id`)
+    pub id: String,
+    /// Plugin name (cached, may be replaced by the manifest)
     pub name: String,
+    /// Details from the manifest
+    pub metadata: PluginMetadata,
     /// Plugin version string (cached)
     pub version: String,
     /// Config schema (cached)
@@ -79,29 +195,100 @@ pub struct LoadedPlugin {
     pub enabled: bool,
     /// Last error message
     pub last_error: Option<String>,
+    /// Status from the last check (see `check_status`)
+    pub status: PluginStatus,
+    /// What to do when calls fail
+    pub failure_policy: FailurePolicy,
+    /// Failure counters
+    pub failures: FailureTracker,
+    /// Rights of a sandboxed plugin (None if it is not sandboxed)
+    pub grants: Option<PluginGrants>,
+    /// Filter for spoken messages
+    pub router: Router,
 }
 
-// SAFETY: LoadedPlugin is Send because all access is synchronized through Mutex
-// and the plugin vtable functions are called with proper synchronization
-unsafe impl Send for LoadedPlugin {}
-
 impl LoadedPlugin {
-    /// Get plugin info for UI
+    /// Wrap a backend (disabled, not configured yet)
+    pub fn new(
+        backend: Box<dyn PluginBackend>,
+        source: std::path::PathBuf,
+        name: String,
+        version: String,
+        capabilities: u64,
+        config_schema: serde_json::Value,
+    ) -> Self {
+        let mut plugin = Self {
+            backend,
+            id: default_plugin_id(&source),
+            source,
+            capabilities,
+            name,
+            metadata: PluginMetadata::default(),
+            version,
+            config_schema,
+            config: serde_json::json!({}),
+            enabled: false,
+            last_error: None,
+            status: PluginStatus::Ok,
+            failure_policy: FailurePolicy::default(),
+            failures: FailureTracker::default(),
+            grants: None,
+            router: Router::default(),
+        };
+        plugin.check_status();
+        plugin
+    }
+
+    /// Get plugin info for UI (with the status of the last check)
     pub fn info(&self) -> PluginInfo {
+        let status = self.status;
+        // Explain a bad status with the plugin's own message if the host has none
+        let last_error = match status {
+            PluginStatus::Ok => self.last_error.clone(),
+            _ => self.last_error.clone().or_else(|| self.backend.plugin_error()),
+        };
+
         PluginInfo {
+            id: self.id.clone(),
             name: self.name.clone(),
             version: self.version.clone(),
             enabled: self.enabled,
-            status: self.check_status().into(),
+            status: status.into(),
             config_schema: self.config_schema.clone(),
             config: self.config.clone(),
-            last_error: self.last_error.clone(),
+            last_error,
+            status_detail: self.backend.status_detail(),
+            loaded: true,
+            api_version: Some(self.backend.api_version()),
+            capabilities: capabilities::names(self.capabilities)
+                .into_iter()
+                .map(String::from)
+                .collect(),
+            transform_position: None,
+            metadata: self.metadata.clone(),
+            failure_policy: self.failure_policy.clone(),
+            failures: self.failures.stats(&self.failure_policy, Instant::now()),
+            grants: self.grants.clone(),
+            routing: self.router.rules().clone(),
         }
     }
 
-    /// Check current plugin status
-    pub fn check_status(&self) -> PluginStatus {
-        (self.vtable.check_status)(self.data)
+    /// Whether the plugin declared (and implements) a capability
+    pub fn has_capability(&self, capability: u64) -> bool {
+        self.capabilities & capability != 0
+    }
+
+    /// Check current plugin status (plugins that don't report status are always Ok)
+    ///
+    /// The check can be slow (e.g. a network request), so it runs only when asked
+    /// for, after `set_config` and before re-enabling; `info` shows its result.
+    pub fn check_status(&mut self) -> PluginStatus {
+        self.status = if self.has_capability(capabilities::STATUS) {
+            self.backend.check_status()
+        } else {
+            PluginStatus::Ok
+        };
+        self.status
     }
 
     /// Set configuration for plugin (always saves config locally)
@@ -109,72 +296,250 @@ impl LoadedPlugin {
         // Always save config locally first
         self.config = config.clone();
 
-        let json = serde_json::to_string(config)
-            .map_err(|e| format!("Failed to serialize config: {}", e))?;
-
-        let result = (self.vtable.set_config)(
-            self.data,
-            json.as_ptr() as *const ::std::ffi::c_char,
-            json.len(),
-        );
-
-        if result == 0 {
-            self.last_error = None;
-            Ok(())
+        // Plugins without configuration accept anything
+        let result = if self.has_capability(capabilities::CONFIG) {
+            self.backend.set_config(config)
         } else {
-            let err = format!("Plugin set_config returned error code: {}", result);
-            self.last_error = Some(err.clone());
-            Err(err)
+            Ok(())
+        };
+        self.check_status();
+
+        match result {
+            Ok(()) => {
+                self.last_error = None;
+                Ok(())
+            }
+            Err(e) => {
+                self.last_error = Some(e.clone());
+                Err(e)
+            }
         }
     }
 
+    /// Replace the routing rules (an invalid regex keeps the old ones)
+    pub fn set_routing(&mut self, rules: RoutingRules) -> Result<(), String> {
+        self.router = rules.compile()?;
+        Ok(())
+    }
+
     /// Broadcast text to this plugin (if enabled)
     pub fn on_text(&mut self, text: &str) -> Result<(), String> {
-        if !self.enabled {
+        // Plugins that don't handle text ignore it
+        if !self.enabled || !self.has_capability(capabilities::TEXT) {
             return Ok(());
         }
+        self.backend.on_text(text)
+    }
 
-        // Use catch_unwind to prevent plugin panics from crashing the app
-        std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
-            let result = (self.vtable.on_text)(
-                self.data,
-                text.as_ptr() as *const ::std::ffi::c_char,
-                text.len(),
-            );
+    /// Broadcast a spoken message with its details to this plugin (if enabled)
+    pub fn on_message(&mut self, message: &TextMessage) -> Result<(), String> {
+        if !self.enabled || !self.has_capability(capabilities::TEXT) {
+            return Ok(());
+        }
+        self.backend.on_message(message)
+    }
 
-            if result == 0 {
-                Ok(())
-            } else {
-                Err(format!("Plugin on_text returned error code: {}", result))
-            }
-        }))
-        .unwrap_or_else(|_| {
-            Err("Plugin panicked during on_text".to_string())
-        })
+    /// Voices offered by a TTS provider plugin
+    pub fn voices(&self) -> Vec<PluginVoice> {
+        if !self.has_capability(capabilities::SYNTH) {
+            return Vec::new();
+        }
+        self.backend.voices()
+    }
+
+    /// Synthesize speech with a TTS provider plugin
+    pub fn synthesize(&mut self, text: &str, voice: &str) -> Result<SynthesizedAudio, String> {
+        if !self.has_capability(capabilities::SYNTH) {
+            return Err(format!("Plugin '{}' is not a TTS provider", self.name));
+        }
+        self.backend.synthesize(text, voice)
+    }
+
+    /// Hand synthesized audio to the plugin
+    pub fn on_audio(&mut self, clip: &AudioClip) -> Result<(), String> {
+        if !self.enabled || !self.has_capability(capabilities::AUDIO) {
+            return Ok(());
+        }
+        self.backend.on_audio(clip)
+    }
+
+    /// Notify the plugin about a playback or app state change
+    /// Events are informational: missing handlers and panics are ignored
+    pub fn on_event(&self, event: &PluginEvent) {
+        if !self.enabled || !self.has_capability(capabilities::EVENTS) {
+            return;
+        }
+        self.backend.on_event(event);
+    }
+
+    /// Let the plugin rewrite text before it is spoken
+    /// Returns `Ok(None)` if the plugin left the text unchanged
+    pub fn transform_text(&mut self, text: &str) -> Result<Option<String>, String> {
+        if !self.has_capability(capabilities::TRANSFORM) {
+            return Ok(None);
+        }
+        self.backend.transform_text(text)
     }
 
     /// Toggle enabled state
     pub fn set_enabled(&mut self, enabled: bool) {
         self.enabled = enabled;
+        self.failures.reset_disable();
         if !enabled {
             self.last_error = None;
         }
     }
 
+    /// Make a call under the plugin's failure policy: retry it, count the
+    /// final error and disable the plugin once its error budget is used up
+    pub fn call_with_policy<T>(
+        &mut self,
+        mut call: impl FnMut(&mut Self) -> Result<T, String>,
+    ) -> Result<T, String> {
+        let mut attempt = 0;
+        loop {
+            match call(self) {
+                Ok(value) => {
+                    self.failures.record_success();
+                    return Ok(value);
+                }
+                Err(_) if attempt < self.failure_policy.retries && self.enabled => {
+                    attempt += 1;
+                    self.failures.record_retry();
+                    std::thread::sleep(self.failure_policy.retry_delay(attempt));
+                }
+                Err(e) => {
+                    self.record_failure(e.clone());
+                    return Err(e);
+                }
+            }
+        }
+    }
+
+    /// Count an error; disables the plugin if it is over its error budget
+    pub fn record_failure(&mut self, error: String) {
+        let now = Instant::now();
+        if self.failures.record_error(&self.failure_policy, now) {
+            eprintln!("[Plugins] Plugin '{}' failed: {}, disabling", self.name, error);
+            self.disable_for_failure(error);
+        } else {
+            eprintln!("[Plugins] Plugin '{}' failed: {}", self.name, error);
+            self.last_error = Some(error);
+        }
+    }
+
+    /// Disable the plugin because it is failing; it is enabled again after
+    /// the policy's pause once its status is Ok (see `try_reenable`)
+    pub fn disable_for_failure(&mut self, error: String) {
+        self.set_enabled(false);
+        self.set_error(error);
+        self.failures.disabled(&self.failure_policy, Instant::now());
+    }
+
+    /// When the worker should check whether the plugin can be enabled again
+    pub fn reenable_at(&self) -> Option<Instant> {
+        if self.enabled {
+            None
+        } else {
+            self.failures.reenable_at()
+        }
+    }
+
+    /// Enable a plugin disabled for failures if its pause is over and its status is Ok
+    /// Returns true if the plugin was enabled
+    pub fn try_reenable(&mut self) -> bool {
+        let now = Instant::now();
+        if self.enabled || !self.failures.reenable_due(now) {
+            return false;
+        }
+        if self.check_status() != PluginStatus::Ok {
+            self.failures.postpone(&self.failure_policy, now);
+            return false;
+        }
+
+        eprintln!("[Plugins] Plugin '{}' is OK again, enabling", self.name);
+        self.enabled = true;
+        self.last_error = None;
+        self.failures.reenabled();
+        true
+    }
+
     /// Set error message
     pub fn set_error(&mut self, error: String) {
         self.last_error = Some(error);
     }
 }
 
-impl Drop for LoadedPlugin {
-    fn drop(&mut self) {
-        (self.vtable.destroy)(self.data);
-    }
-}
-
 /// Config file structure
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
 pub struct PluginsConfigFile {
     pub plugins: std::collections::HashMap<String, PluginConfig>,
+    /// Order in which text transforms run (plugin ids; unlisted plugins run last)
+    #[serde(default)]
+    pub transform_order: Vec<String>,
+    /// Order in which plugins get messages, audio and events (plugin ids; unlisted plugins come last)
+    #[serde(default)]
+    pub plugin_order: Vec<String>,
+}
+
+/// Voice of a TTS provider plugin
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PluginVoice {
+    pub id: String,
+    #[serde(default)]
+    pub name: String,
+}
+
+/// Audio returned by a TTS provider plugin (format codes from `plugins_api::audio_format`)
+#[derive(Debug, Clone)]
+pub struct SynthesizedAudio {
+    pub data: Vec<u8>,
+    pub format: u32,
+    pub sample_rate: u32,
+    pub channels: u16,
+}
+
+/// Synthesized audio as seen by the host (see `plugins_api::PluginAudio`)
+/// Cheap to clone: the samples are shared by all plugin workers
+#[derive(Debug, Clone)]
+pub struct AudioClip {
+    pub data: std::sync::Arc<[u8]>,
+    /// Format name, e.g. "mp3" or "pcm"
+    pub format: String,
+    /// Sample rate / channel count for raw PCM (0 otherwise)
+    pub sample_rate: u32,
+    pub channels: u16,
+    /// 0 if unknown
+    pub duration_ms: u64,
+    /// Empty if spoken outside the queue
+    pub message_id: String,
+}
+
+/// Playback and app state changes forwarded to plugins
+#[derive(Debug, Clone)]
+pub enum PluginEvent {
+    TtsStarted { id: String, text: String },
+    TtsCompleted { id: String, text: String },
+    TtsFailed { id: String, text: String, error: String },
+    TtsCancelled { id: String, text: String },
+    ProviderChanged(String),
+    BlockingChanged(bool),
+}
+
+/// Result of one plugin's transform step
+#[derive(Debug, Clone, Serialize)]
+pub struct TransformStep {
+    pub plugin: String,
+    pub duration_ms: f64,
+    /// True if the plugin replaced the text
+    pub changed: bool,
+    pub error: Option<String>,
+}
+
+/// Text after all transforms, with per-plugin timing and errors
+#[derive(Debug, Clone, Serialize)]
+pub struct TransformReport {
+    pub original: String,
+    pub text: String,
+    pub steps: Vec<TransformStep>,
 }
@@ -0,0 +1,597 @@
+//! Sandboxed WASM plugins (feature `wasm-plugins`)
+//!
+//! A WASM plugin is a `wasm32-wasip1` module run by wasmtime. It can only do
+//! what its manifest grants (see [`PluginGrants`]): HTTP requests to listed hosts,
+//! one folder mounted as `/data`, adding text to the TTS queue. Calls are cut
+//! off after a few seconds and memory is limited, so a broken module can't hang
+//! or exhaust the app.
+//!
+//! The module implements the same contract as `PluginVTable`, with strings passed
+//! through its linear memory. A string returned by the module is an `i64` packed
+//! as `ptr << 32 | len`; the host copies it and hands it back through `dealloc`.
+//! Strings passed to the module are allocated with `alloc` and freed by the host
+//! after the call.
+//!
+//! Exports (required): `memory`, `alloc(len) -> ptr`, `dealloc(ptr, len)`,
+//! `plugin_api_version() -> i32`, `plugin_name() -> str`, `plugin_version() -> str`,
+//! `plugin_capabilities() -> i64`.
+//!
+//! Exports (optional): `plugin_init()`, `get_config_schema() -> str`,
+//! `set_config(ptr, len) -> i32`, `check_status() -> i32`, `on_text(ptr, len) -> i32`,
+//! `get_last_error() -> str`, `get_status_detail() -> str`,
+//! `transform_text(ptr, len) -> str` (0 keeps the text, negative is an error),
+//! `on_event(ptr, len)` with the event as JSON (`{"event": "tts_started", ...}`).
+//!
+//! Imports (module `app_tts`): `log(level, ptr, len)`, `enqueue_text(ptr, len) -> i32`,
+//! `http_request(ptr, len) -> str` with a JSON request `{ method, url, headers?, body? }`
+//! and a JSON reply `{ status, body }` or `{ error }`.
+
+use super::backend::PluginBackend;
+use super::host::{HostRequest, HostRequestSender, PluginHost};
+use super::manifest::PluginGrants;
+use super::types::{LoadedPlugin, PluginEvent};
+use plugins_api::{capabilities, check_api_version, log_level, PluginStatus};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::{Mutex, MutexGuard, OnceLock};
+use std::time::Duration;
+use wasmtime::{Caller, Config, Engine, Extern, Instance, Linker, Memory, Module, Store, StoreLimits, StoreLimitsBuilder, TypedFunc};
+use wasmtime_wasi::preview1::{self, WasiP1Ctx};
+use wasmtime_wasi::{DirPerms, FilePerms, WasiCtxBuilder};
+
+/// Operations a WASM plugin can provide
+const SUPPORTED_CAPABILITIES: u64 = capabilities::CONFIG
+    | capabilities::STATUS
+    | capabilities::TEXT
+    | capabilities::TRANSFORM
+    | capabilities::EVENTS;
+
+/// The engine's clock: calls are interrupted after a number of ticks
+const EPOCH_TICK: Duration = Duration::from_millis(100);
+
+/// Ticks a call may take (below the worker's call timeout)
+const CALL_TICKS: u64 = 25;
+
+/// Ticks module start-up (`_initialize`, `plugin_init`) may take
+const INIT_TICKS: u64 = 50;
+
+/// Linear memory a module may grow to
+const MAX_MEMORY: usize = 64 * 1024 * 1024;
+
+/// How long an HTTP request of a plugin may take (it counts towards the call's ticks)
+const HTTP_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Engine shared by all WASM plugins, with a thread driving its epoch clock
+fn engine() -> Result<&'static Engine, String> {
+    static ENGINE: OnceLock<Result<Engine, String>> = OnceLock::new();
+    ENGINE
+        .get_or_init(|| {
+            let mut config = Config::new();
+            config.epoch_interruption(true);
+            let engine = Engine::new(&config).map_err(|e| format!("Failed to start WASM engine: {}", e))?;
+
+            let ticker = engine.clone();
+            std::thread::Builder::new()
+                .name("wasm epoch".to_string())
+                .spawn(move || loop {
+                    std::thread::sleep(EPOCH_TICK);
+                    ticker.increment_epoch();
+                })
+                .map_err(|e| format!("Failed to start WASM engine clock: {}", e))?;
+            Ok(engine)
+        })
+        .as_ref()
+        .map_err(|e| e.clone())
+}
+
+/// Store data: everything the imports may touch
+struct WasmState {
+    wasi: WasiP1Ctx,
+    limits: StoreLimits,
+    plugin: String,
+    grants: PluginGrants,
+    sender: Option<HostRequestSender>,
+}
+
+/// HTTP request from `http_request`
+#[derive(Deserialize)]
+struct HttpRequest {
+    #[serde(default = "default_method")]
+    method: String,
+    url: String,
+    #[serde(default)]
+    headers: HashMap<String, String>,
+    #[serde(default)]
+    body: Option<String>,
+}
+
+fn default_method() -> String {
+    "GET".to_string()
+}
+
+/// Functions exported by the module
+struct Exports {
+    alloc: TypedFunc<i32, i32>,
+    dealloc: TypedFunc<(i32, i32), ()>,
+    set_config: Option<TypedFunc<(i32, i32), i32>>,
+    check_status: Option<TypedFunc<(), i32>>,
+    on_text: Option<TypedFunc<(i32, i32), i32>>,
+    get_last_error: Option<TypedFunc<(), i64>>,
+    get_status_detail: Option<TypedFunc<(), i64>>,
+    transform_text: Option<TypedFunc<(i32, i32), i64>>,
+    on_event: Option<TypedFunc<(i32, i32), ()>>,
+}
+
+/// A module instance with its store
+struct WasmInstance {
+    store: Store<WasmState>,
+    memory: Memory,
+    exports: Exports,
+}
+
+impl WasmInstance {
+    fn start_call(&mut self, ticks: u64) {
+        self.store.set_epoch_deadline(ticks);
+    }
+
+    /// Copy a string into the module; free it with `free_input`
+    fn write_input(&mut self, text: &str) -> Result<(i32, i32), String> {
+        let len = i32::try_from(text.len()).map_err(|_| "Text is too long".to_string())?;
+        self.start_call(CALL_TICKS);
+        let ptr = self.exports.alloc.call(&mut self.store, len).map_err(trap)?;
+        self.memory
+            .write(&mut self.store, ptr as u32 as usize, text.as_bytes())
+            .map_err(|e| format!("Plugin returned an invalid buffer: {}", e))?;
+        Ok((ptr, len))
+    }
+
+    fn free_input(&mut self, (ptr, len): (i32, i32)) {
+        self.start_call(CALL_TICKS);
+        if let Err(e) = self.exports.dealloc.call(&mut self.store, (ptr, len)) {
+            eprintln!("[Plugins] WASM dealloc failed: {}", e);
+        }
+    }
+
+    /// Copy a packed string returned by the module and give it back
+    fn take_output(&mut self, packed: i64) -> Result<String, String> {
+        let (ptr, len) = unpack(packed);
+        if ptr == 0 {
+            return Ok(String::new());
+        }
+        let text = read_string(self.memory.data(&self.store), ptr, len)?;
+        self.start_call(CALL_TICKS);
+        self.exports.dealloc.call(&mut self.store, (ptr as i32, len as i32)).map_err(trap)?;
+        Ok(text)
+    }
+
+    /// Call an export taking a string and returning a status code
+    fn call_with_text(&mut self, func: TypedFunc<(i32, i32), i32>, text: &str) -> Result<i32, String> {
+        let input = self.write_input(text)?;
+        self.start_call(CALL_TICKS);
+        let result = func.call(&mut self.store, input).map_err(trap);
+        self.free_input(input);
+        result
+    }
+
+    /// Call an export returning a string (None if the module doesn't export it)
+    fn call_string(&mut self, func: Option<TypedFunc<(), i64>>) -> Option<String> {
+        let func = func?;
+        self.start_call(CALL_TICKS);
+        let packed = func.call(&mut self.store, ()).ok()?;
+        self.take_output(packed).ok().filter(|s| !s.is_empty())
+    }
+}
+
+/// `ptr << 32 | len` -> (ptr, len)
+fn unpack(packed: i64) -> (u32, u32) {
+    ((packed as u64 >> 32) as u32, packed as u64 as u32)
+}
+
+fn read_string(memory: &[u8], ptr: u32, len: u32) -> Result<String, String> {
+    let start = ptr as usize;
+    let bytes = start
+        .checked_add(len as usize)
+        .and_then(|end| memory.get(start..end))
+        .ok_or_else(|| "Plugin passed a string outside its memory".to_string())?;
+    Ok(String::from_utf8_lossy(bytes).into_owned())
+}
+
+fn trap(error: wasmtime::Error) -> String {
+    format!("WASM plugin failed: {}", error)
+}
+
+/// Backend of a WASM plugin
+pub struct WasmPlugin {
+    name: String,
+    api_version: String,
+    instance: Mutex<WasmInstance>,
+}
+
+impl WasmPlugin {
+    fn instance(&self) -> MutexGuard<'_, WasmInstance> {
+        self.instance.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+impl PluginBackend for WasmPlugin {
+    fn api_version(&self) -> String {
+        self.api_version.clone()
+    }
+
+    fn check_status(&self) -> PluginStatus {
+        let mut instance = self.instance();
+        let func = match instance.exports.check_status {
+            Some(func) => func,
+            None => return PluginStatus::Ok,
+        };
+        instance.start_call(CALL_TICKS);
+        match func.call(&mut instance.store, ()) {
+            Ok(status) => PluginStatus::from_i32(status),
+            Err(e) => {
+                eprintln!("[Plugins] '{}' check_status: {}", self.name, trap(e));
+                PluginStatus::UnknownError
+            }
+        }
+    }
+
+    fn plugin_error(&self) -> Option<String> {
+        let mut instance = self.instance();
+        let func = instance.exports.get_last_error;
+        instance.call_string(func)
+    }
+
+    fn status_detail(&self) -> Option<String> {
+        let mut instance = self.instance();
+        let func = instance.exports.get_status_detail;
+        instance.call_string(func)
+    }
+
+    fn set_config(&mut self, config: &serde_json::Value) -> Result<(), String> {
+        let mut instance = self.instance();
+        let func = match instance.exports.set_config {
+            Some(func) => func,
+            None => return Ok(()),
+        };
+        match instance.call_with_text(func, &config.to_string())? {
+            0 => Ok(()),
+            code => {
+                let func = instance.exports.get_last_error;
+                Err(instance.call_string(func).unwrap_or_else(|| format!("set_config failed with code {}", code)))
+            }
+        }
+    }
+
+    fn on_text(&mut self, text: &str) -> Result<(), String> {
+        let mut instance = self.instance();
+        let func = match instance.exports.on_text {
+            Some(func) => func,
+            None => return Ok(()),
+        };
+        match instance.call_with_text(func, text)? {
+            0 => Ok(()),
+            code => {
+                let func = instance.exports.get_last_error;
+                Err(instance.call_string(func).unwrap_or_else(|| format!("on_text failed with code {}", code)))
+            }
+        }
+    }
+
+    fn transform_text(&mut self, text: &str) -> Result<Option<String>, String> {
+        let mut instance = self.instance();
+        let func = match instance.exports.transform_text {
+            Some(func) => func,
+            None => return Ok(None),
+        };
+        let input = instance.write_input(text)?;
+        instance.start_call(CALL_TICKS);
+        let result = func.call(&mut instance.store, input).map_err(trap);
+        instance.free_input(input);
+
+        match result? {
+            0 => Ok(None),
+            packed if packed < 0 => {
+                let func = instance.exports.get_last_error;
+                Err(instance.call_string(func).unwrap_or_else(|| format!("transform_text failed with code {}", packed)))
+            }
+            packed => instance.take_output(packed).map(Some),
+        }
+    }
+
+    fn on_event(&self, event: &PluginEvent) {
+        let mut instance = self.instance();
+        let func = match instance.exports.on_event {
+            Some(func) => func,
+            None => return,
+        };
+        let input = match instance.write_input(&event_json(event).to_string()) {
+            Ok(input) => input,
+            Err(e) => {
+                eprintln!("[Plugins] '{}' on_event: {}", self.name, e);
+                return;
+            }
+        };
+        instance.start_call(CALL_TICKS);
+        if let Err(e) = func.call(&mut instance.store, input) {
+            eprintln!("[Plugins] '{}' on_event: {}", self.name, trap(e));
+        }
+        instance.free_input(input);
+    }
+}
+
+/// Event as passed to `on_event`, e.g. `{"event": "tts_started", "id": "...", "text": "..."}`
+fn event_json(event: &PluginEvent) -> serde_json::Value {
+    match event {
+        PluginEvent::TtsStarted { id, text } => serde_json::json!({ "event": "tts_started", "id": id, "text": text }),
+        PluginEvent::TtsCompleted { id, text } => serde_json::json!({ "event": "tts_completed", "id": id, "text": text }),
+        PluginEvent::TtsFailed { id, text, error } => {
+            serde_json::json!({ "event": "tts_failed", "id": id, "text": text, "error": error })
+        }
+        PluginEvent::TtsCancelled { id, text } => serde_json::json!({ "event": "tts_cancelled", "id": id, "text": text }),
+        PluginEvent::ProviderChanged(provider) => serde_json::json!({ "event": "provider_changed", "provider": provider }),
+        PluginEvent::BlockingChanged(blocking) => serde_json::json!({ "event": "blocking_changed", "blocking": blocking }),
+    }
+}
+
+/// Memory of the module calling an import
+fn caller_memory(caller: &mut Caller<'_, WasmState>) -> Option<Memory> {
+    caller.get_export("memory").and_then(Extern::into_memory)
+}
+
+fn caller_string(caller: &mut Caller<'_, WasmState>, ptr: i32, len: i32) -> Option<String> {
+    let memory = caller_memory(caller)?;
+    read_string(memory.data(&*caller), ptr as u32, len as u32).ok()
+}
+
+/// Copy a string into the calling module's memory (it frees it with `dealloc`)
+fn return_string(caller: &mut Caller<'_, WasmState>, text: &str) -> i64 {
+    let alloc = match caller.get_export("alloc").and_then(Extern::into_func) {
+        Some(alloc) => alloc,
+        None => return 0,
+    };
+    let alloc = match alloc.typed::<i32, i32>(&*caller) {
+        Ok(alloc) => alloc,
+        Err(_) => return 0,
+    };
+    let memory = match caller_memory(caller) {
+        Some(memory) => memory,
+        None => return 0,
+    };
+    let ptr = match alloc.call(&mut *caller, text.len() as i32) {
+        Ok(ptr) => ptr,
+        Err(_) => return 0,
+    };
+    if memory.write(&mut *caller, ptr as u32 as usize, text.as_bytes()).is_err() {
+        return 0;
+    }
+    ((ptr as u32 as i64) << 32) | text.len() as i64
+}
+
+fn http_request(state: &WasmState, request: &str) -> serde_json::Value {
+    let request: HttpRequest = match serde_json::from_str(request) {
+        Ok(request) => request,
+        Err(e) => return serde_json::json!({ "error": format!("Invalid request: {}", e) }),
+    };
+    if !state.grants.allows_url(&request.url) {
+        eprintln!("[Plugin {}] Denied HTTP request to {}", state.plugin, request.url);
+        return serde_json::json!({ "error": format!("Network access to {} is not granted", request.url) });
+    }
+
+    let method = match reqwest::Method::from_bytes(request.method.to_uppercase().as_bytes()) {
+        Ok(method) => method,
+        Err(_) => return serde_json::json!({ "error": format!("Invalid method {}", request.method) }),
+    };
+    let client = match reqwest::blocking::Client::builder()
+        .timeout(HTTP_TIMEOUT)
+        // A redirect could lead to a host that is not granted
+        .redirect(reqwest::redirect::Policy::none())
+        .build()
+    {
+        Ok(client) => client,
+        Err(e) => return serde_json::json!({ "error": e.to_string() }),
+    };
+
+    let mut builder = client.request(method, &request.url);
+    for (name, value) in &request.headers {
+        builder = builder.header(name, value);
+    }
+    if let Some(body) = request.body {
+        builder = builder.body(body);
+    }
+    match builder.send() {
+        Ok(response) => {
+            let status = response.status().as_u16();
+            let body = response.text().unwrap_or_default();
+            serde_json::json!({ "status": status, "body": body })
+        }
+        Err(e) => serde_json::json!({ "error": e.to_string() }),
+    }
+}
+
+fn add_imports(linker: &mut Linker<WasmState>) -> Result<(), String> {
+    let define = |e: wasmtime::Error| format!("Failed to define WASM imports: {}", e);
+
+    linker
+        .func_wrap("app_tts", "log", |mut caller: Caller<'_, WasmState>, level: i32, ptr: i32, len: i32| {
+            let message = caller_string(&mut caller, ptr, len).unwrap_or_default();
+            let level = match level as u32 {
+                log_level::ERROR => "ERROR",
+                log_level::WARN => "WARN",
+                log_level::INFO => "INFO",
+                _ => "DEBUG",
+            };
+            eprintln!("[Plugin {}] {}: {}", caller.data().plugin, level, message);
+        })
+        .map_err(define)?;
+
+    linker
+        .func_wrap("app_tts", "enqueue_text", |mut caller: Caller<'_, WasmState>, ptr: i32, len: i32| -> i32 {
+            let text = match caller_string(&mut caller, ptr, len) {
+                Some(text) if !text.trim().is_empty() => text,
+                _ => return -1,
+            };
+            let state = caller.data();
+            if !state.grants.enqueue {
+                eprintln!("[Plugin {}] Denied enqueue_text: not granted", state.plugin);
+                return -2;
+            }
+            match state.sender {
+                Some(ref sender) => {
+                    sender(HostRequest::EnqueueText {
+                        plugin: state.plugin.clone(),
+                        text,
+                    });
+                    0
+                }
+                None => -1,
+            }
+        })
+        .map_err(define)?;
+
+    linker
+        .func_wrap("app_tts", "http_request", |mut caller: Caller<'_, WasmState>, ptr: i32, len: i32| -> i64 {
+            let request = caller_string(&mut caller, ptr, len).unwrap_or_default();
+            let reply = http_request(caller.data(), &request);
+            return_string(&mut caller, &reply.to_string())
+        })
+        .map_err(define)?;
+
+    Ok(())
+}
+
+/// Load a WASM plugin from its plugin folder
+pub fn load_wasm_plugin(
+    manifest_path: &Path,
+    module_path: &Path,
+    id: &str,
+    grants: PluginGrants,
+    host: &PluginHost,
+) -> Result<LoadedPlugin, String> {
+    let engine = engine()?;
+    let module = Module::from_file(engine, module_path)
+        .map_err(|e| format!("Failed to load WASM module {}: {}", module_path.display(), e))?;
+
+    // Nothing is inherited but stderr (for the log); the granted folder is `/data`
+    let mut wasi = WasiCtxBuilder::new();
+    wasi.inherit_stderr();
+    if let Some(dir) = grants.filesystem_dir(&host.data_dir(id)) {
+        std::fs::create_dir_all(&dir)
+            .map_err(|e| format!("Failed to create plugin folder {}: {}", dir.display(), e))?;
+        wasi.preopened_dir(&dir, "/data", DirPerms::all(), FilePerms::all())
+            .map_err(|e| format!("Failed to open plugin folder {}: {}", dir.display(), e))?;
+    }
+
+    let state = WasmState {
+        wasi: wasi.build_p1(),
+        limits: StoreLimitsBuilder::new().memory_size(MAX_MEMORY).build(),
+        plugin: id.to_string(),
+        grants: grants.clone(),
+        sender: host.sender(),
+    };
+    let mut store = Store::new(engine, state);
+    store.limiter(|state| &mut state.limits);
+    store.set_epoch_deadline(INIT_TICKS);
+
+    let mut linker = Linker::new(engine);
+    preview1::add_to_linker_sync(&mut linker, |state: &mut WasmState| &mut state.wasi)
+        .map_err(|e| format!("Failed to set up WASI: {}", e))?;
+    add_imports(&mut linker)?;
+
+    let instance = linker
+        .instantiate(&mut store, &module)
+        .map_err(|e| format!("Failed to instantiate WASM module: {}", e))?;
+
+    // Reactor modules (wasip1 cdylib) set up their runtime here
+    if let Ok(initialize) = instance.get_typed_func::<(), ()>(&mut store, "_initialize") {
+        initialize.call(&mut store, ()).map_err(trap)?;
+    }
+
+    let required = |store: &mut Store<WasmState>, name: &str| -> Result<_, String> {
+        instance
+            .get_typed_func::<(), i64>(&mut *store, name)
+            .map_err(|_| format!("WASM module does not export `{}`", name))
+    };
+    let api_version = instance
+        .get_typed_func::<(), i32>(&mut store, "plugin_api_version")
+        .map_err(|_| "WASM module does not export `plugin_api_version`".to_string())?
+        .call(&mut store, ())
+        .map_err(trap)? as u32;
+    check_api_version(api_version)?;
+    let name_fn = required(&mut store, "plugin_name")?;
+    let version_fn = required(&mut store, "plugin_version")?;
+    let capabilities_fn = required(&mut store, "plugin_capabilities")?;
+
+    let memory = instance
+        .get_memory(&mut store, "memory")
+        .ok_or_else(|| "WASM module does not export `memory`".to_string())?;
+    let exports = Exports {
+        alloc: instance
+            .get_typed_func(&mut store, "alloc")
+            .map_err(|_| "WASM module does not export `alloc(len) -> ptr`".to_string())?,
+        dealloc: instance
+            .get_typed_func(&mut store, "dealloc")
+            .map_err(|_| "WASM module does not export `dealloc(ptr, len)`".to_string())?,
+        set_config: optional(&instance, &mut store, "set_config"),
+        check_status: optional(&instance, &mut store, "check_status"),
+        on_text: optional(&instance, &mut store, "on_text"),
+        get_last_error: optional(&instance, &mut store, "get_last_error"),
+        get_status_detail: optional(&instance, &mut store, "get_status_detail"),
+        transform_text: optional(&instance, &mut store, "transform_text"),
+        on_event: optional(&instance, &mut store, "on_event"),
+    };
+
+    if let Ok(init) = instance.get_typed_func::<(), ()>(&mut store, "plugin_init") {
+        init.call(&mut store, ()).map_err(trap)?;
+    }
+
+    let mut wasm = WasmInstance { store, memory, exports };
+    let name = {
+        let packed = name_fn.call(&mut wasm.store, ()).map_err(trap)?;
+        wasm.take_output(packed)?
+    };
+    let version = {
+        let packed = version_fn.call(&mut wasm.store, ()).map_err(trap)?;
+        wasm.take_output(packed)?
+    };
+    let config_schema = match optional::<(), i64>(&instance, &mut wasm.store, "get_config_schema") {
+        Some(func) => {
+            let json = wasm.call_string(Some(func)).unwrap_or_default();
+            serde_json::from_str(&json).unwrap_or_else(|_| serde_json::json!({}))
+        }
+        None => serde_json::json!({}),
+    };
+
+    let declared = capabilities_fn.call(&mut wasm.store, ()).map_err(trap)? as u64;
+    let capabilities = declared & SUPPORTED_CAPABILITIES;
+    if capabilities != declared {
+        eprintln!("[Plugins] '{}': WASM plugins don't support {:?}",
+            name, capabilities::names(declared & !SUPPORTED_CAPABILITIES));
+    }
+    let (major, minor) = plugins_api::unpack_api_version(api_version);
+    eprintln!("[Plugins] Loaded '{}' v{} (wasm, capabilities: {:?}, grants: {:?})",
+        name, version, capabilities::names(capabilities), grants);
+
+    let backend = WasmPlugin {
+        name: name.clone(),
+        api_version: format!("{}.{}", major, minor),
+        instance: Mutex::new(wasm),
+    };
+    let mut plugin = LoadedPlugin::new(
+        Box::new(backend),
+        manifest_path.to_path_buf(),
+        name,
+        version,
+        capabilities,
+        config_schema,
+    );
+    plugin.grants = Some(grants);
+    Ok(plugin)
+}
+
+fn optional<Params, Results>(instance: &Instance, store: &mut Store<WasmState>, name: &str) -> Option<TypedFunc<Params, Results>>
+where
+    Params: wasmtime::WasmParams,
+    Results: wasmtime::WasmResults,
+{
+    instance.get_typed_func(store, name).ok()
+}
@@ -0,0 +1,61 @@
+//! Watching the plugins directory
+//!
+//! Copying or deleting a plugin produces a burst of file events; they are
+//! collected until the directory has been quiet for a moment and then handed
+//! over at once (see [`PluginManager::handle_changes`](super::PluginManager::handle_changes)).
+
+use notify::{RecursiveMode, Watcher};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc;
+use std::time::Duration;
+
+/// How long the directory must stay quiet before changes are reported
+const DEBOUNCE: Duration = Duration::from_millis(500);
+
+/// Watch `dir` for changes on a background thread for the rest of the app's life
+/// `on_change` receives the paths changed since the last call
+pub fn spawn_watcher<F>(dir: &Path, on_change: F) -> Result<(), String>
+where
+    F: Fn(Vec<PathBuf>) + Send + 'static,
+{
+    let (tx, rx) = mpsc::channel();
+    let mut watcher = notify::recommended_watcher(tx)
+        .map_err(|e| format!("Failed to create watcher: {}", e))?;
+    watcher
+        .watch(dir, RecursiveMode::Recursive)
+        .map_err(|e| format!("Failed to watch {}: {}", dir.display(), e))?;
+
+    std::thread::Builder::new()
+        .name("plugin-watcher".to_string())
+        .spawn(move || {
+            // Keep the watcher alive as long as the thread runs
+            let _watcher = watcher;
+            let mut changed: Vec<PathBuf> = Vec::new();
+
+            loop {
+                let event = if changed.is_empty() {
+                    rx.recv().map_err(|_| mpsc::RecvTimeoutError::Disconnected)
+                } else {
+                    rx.recv_timeout(DEBOUNCE)
+                };
+
+                match event {
+                    Ok(Ok(event)) => {
+                        for path in event.paths {
+                            if !changed.contains(&path) {
+                                changed.push(path);
+                            }
+                        }
+                    }
+                    Ok(Err(e)) => eprintln!("[PluginWatcher] {}", e),
+                    Err(mpsc::RecvTimeoutError::Timeout) => {
+                        on_change(std::mem::take(&mut changed));
+                    }
+                    Err(mpsc::RecvTimeoutError::Disconnected) => break,
+                }
+            }
+        })
+        .map_err(|e| format!("Failed to start watcher thread: {}", e))?;
+
+    Ok(())
+}
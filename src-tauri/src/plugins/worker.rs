@@ -0,0 +1,516 @@
+//! Per-plugin worker threads
+//!
+//! Every loaded plugin lives on its own thread and is only called from there.
+//! The host talks to it through a bounded job queue: notifications (text, events,
+//! audio) are queued without waiting, calls that need an answer wait for it with
+//! a timeout. A slow or hung plugin is marked as failing instead of blocking the app.
+
+use super::host::{HostRequest, HostRequestSender, PluginHost};
+use super::types::{LoadedPlugin, PluginInfo, SynthesizedAudio};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, RecvTimeoutError, SyncSender, TrySendError};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+/// Jobs waiting for a plugin before new ones are dropped
+pub const QUEUE_CAPACITY: usize = 64;
+
+/// How long a plugin may take to answer a call
+pub const CALL_TIMEOUT: Duration = Duration::from_secs(3);
+
+/// How long a TTS provider plugin may take to synthesize speech
+pub const SYNTH_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Call queued for a plugin and how long it may take
+struct Job {
+    run: Box<dyn FnOnce(&mut LoadedPlugin) + Send>,
+    timeout: Duration,
+}
+
+/// State shared between a worker thread and its handles
+struct WorkerShared {
+    /// Plugin info, refreshed after every job (without checking the plugin's status)
+    info: Mutex<PluginInfo>,
+    /// Deadline of the running job (None while idle)
+    busy_until: Mutex<Option<Instant>>,
+    /// Error to apply to the plugin once the running job returns
+    failure: Mutex<Option<String>>,
+    /// Set by `stop`: queued jobs are dropped and the plugin is unloaded
+    stopping: AtomicBool,
+    thread: Mutex<Option<JoinHandle<()>>>,
+}
+
+impl WorkerShared {
+    fn info(&self) -> MutexGuard<'_, PluginInfo> {
+        self.info.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+/// Handle to a plugin running on its own thread (cheap to clone)
+#[derive(Clone)]
+pub struct PluginWorker {
+    id: String,
+    name: String,
+    source: PathBuf,
+    capabilities: u64,
+    jobs: SyncSender<Job>,
+    shared: Arc<WorkerShared>,
+    timeout: Duration,
+}
+
+impl PluginWorker {
+    /// Move a loaded plugin to a new worker thread
+    ///
+    /// The plugin is destroyed on that thread once all handles are dropped
+    /// and the queued jobs have run.
+    pub fn spawn(plugin: LoadedPlugin, host: &PluginHost) -> Result<Self, String> {
+        Self::spawn_with_timeout(plugin, host, CALL_TIMEOUT)
+    }
+
+    /// Like [`PluginWorker::spawn`] with a custom call timeout
+    pub fn spawn_with_timeout(plugin: LoadedPlugin, host: &PluginHost, timeout: Duration) -> Result<Self, String> {
+        let id = plugin.id.clone();
+        let name = plugin.name.clone();
+        let source = plugin.source.clone();
+        let capabilities = plugin.capabilities;
+        let shared = Arc::new(WorkerShared {
+            info: Mutex::new(plugin.info()),
+            busy_until: Mutex::new(None),
+            failure: Mutex::new(None),
+            stopping: AtomicBool::new(false),
+            thread: Mutex::new(None),
+        });
+        let (jobs, receiver) = mpsc::sync_channel::<Job>(QUEUE_CAPACITY);
+
+        let thread_shared = Arc::clone(&shared);
+        let sender = host.sender();
+        let thread = std::thread::Builder::new()
+            .name(format!("plugin {}", name))
+            .spawn(move || run(plugin, receiver, thread_shared, sender))
+            .map_err(|e| format!("Failed to start worker for plugin '{}': {}", name, e))?;
+        if let Ok(mut slot) = shared.thread.lock() {
+            *slot = Some(thread);
+        }
+
+        Ok(Self {
+            id,
+            name,
+            source,
+            capabilities,
+            jobs,
+            shared,
+            timeout,
+        })
+    }
+
+    /// Stable plugin id
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Library or manifest the plugin was loaded from
+    pub fn source(&self) -> &Path {
+        &self.source
+    }
+
+    /// Whether the plugin declared (and implements) a capability
+    pub fn has_capability(&self, capability: u64) -> bool {
+        self.capabilities & capability != 0
+    }
+
+    /// Last known plugin info (never waits for the plugin)
+    pub fn info(&self) -> PluginInfo {
+        self.shared.info().clone()
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.shared.info().enabled
+    }
+
+    /// Queue a job without waiting for it
+    ///
+    /// Returns false if the plugin was marked as failing because it is stuck
+    /// in a call or its queue is full (the job is dropped then).
+    pub fn post(&self, job: impl FnOnce(&mut LoadedPlugin) + Send + 'static) -> bool {
+        self.send(Box::new(job), self.timeout).is_ok()
+    }
+
+    /// Queue a job that calls the plugin under its failure policy
+    ///
+    /// The job may retry, so it is given time for every attempt and the pauses
+    /// between them before the plugin counts as stuck.
+    pub fn post_with_policy(&self, job: impl FnOnce(&mut LoadedPlugin) + Send + 'static) -> bool {
+        self.send(Box::new(job), self.policy_timeout()).is_ok()
+    }
+
+    /// Like [`PluginWorker::call`] for a job that calls the plugin under its failure policy
+    pub fn call_with_policy<T: Send + 'static>(
+        &self,
+        job: impl FnOnce(&mut LoadedPlugin) -> T + Send + 'static,
+    ) -> Result<T, String> {
+        self.call_with_timeout(self.policy_timeout(), job)
+    }
+
+    fn policy_timeout(&self) -> Duration {
+        self.shared.info().failure_policy.call_budget(self.timeout)
+    }
+
+    /// Run a job on the worker thread and wait for its result
+    pub fn call<T: Send + 'static>(&self, job: impl FnOnce(&mut LoadedPlugin) -> T + Send + 'static) -> Result<T, String> {
+        self.call_with_timeout(self.timeout, job)
+    }
+
+    /// Like [`PluginWorker::call`] with a custom timeout
+    pub fn call_with_timeout<T: Send + 'static>(
+        &self,
+        timeout: Duration,
+        job: impl FnOnce(&mut LoadedPlugin) -> T + Send + 'static,
+    ) -> Result<T, String> {
+        let (reply, result) = mpsc::channel();
+        let shared = Arc::clone(&self.shared);
+        self.send(
+            Box::new(move |plugin| {
+                let value = job(plugin);
+                // Callers read the snapshot right after the reply, so refresh it first
+                *shared.info() = plugin.info();
+                let _ = reply.send(value);
+            }),
+            timeout,
+        )?;
+
+        match result.recv_timeout(timeout) {
+            Ok(value) => Ok(value),
+            Err(RecvTimeoutError::Timeout) => {
+                let error = format!("Plugin did not respond within {} ms", timeout.as_millis());
+                self.mark_failing(error.clone());
+                Err(error)
+            }
+            Err(RecvTimeoutError::Disconnected) => Err(format!("Plugin '{}' worker has stopped", self.name)),
+        }
+    }
+
+    /// Synthesize speech (waits up to [`SYNTH_TIMEOUT`])
+    pub fn synthesize(&self, text: &str, voice: &str) -> Result<SynthesizedAudio, String> {
+        let text = text.to_string();
+        let voice = voice.to_string();
+        self.call_with_timeout(SYNTH_TIMEOUT, move |plugin| plugin.synthesize(&text, &voice))?
+    }
+
+    fn send(&self, run: Box<dyn FnOnce(&mut LoadedPlugin) + Send>, timeout: Duration) -> Result<(), String> {
+        // A plugin stuck in a call won't get to the queue any time soon
+        let busy_until = self.shared.busy_until.lock().map(|until| *until).unwrap_or(None);
+        if let Some(busy_until) = busy_until {
+            if Instant::now() > busy_until {
+                let error = "Plugin is stuck in a call that has timed out".to_string();
+                self.mark_failing(error.clone());
+                return Err(error);
+            }
+        }
+
+        match self.jobs.try_send(Job { run, timeout }) {
+            Ok(()) => Ok(()),
+            Err(TrySendError::Full(_)) => {
+                let error = format!("Plugin is not keeping up ({} calls queued)", QUEUE_CAPACITY);
+                self.mark_failing(error.clone());
+                Err(error)
+            }
+            Err(TrySendError::Disconnected(_)) => Err(format!("Plugin '{}' worker has stopped", self.name)),
+        }
+    }
+
+    /// Unload the plugin: its `destroy` runs and the library is released
+    ///
+    /// Queued calls are dropped. Waits until the plugin is gone, so its files can
+    /// be replaced afterwards. A plugin stuck in a call is marked as failing and
+    /// unloads once the call returns; `stop` can be called again to wait for that.
+    pub fn stop(&self, timeout: Duration) -> Result<(), String> {
+        self.shared.stopping.store(true, Ordering::Release);
+        // Wake the worker up if it is waiting for jobs
+        let _ = self.jobs.try_send(Job {
+            run: Box::new(|_| {}),
+            timeout: self.timeout,
+        });
+
+        let deadline = Instant::now() + timeout;
+        loop {
+            let finished = match self.shared.thread.lock() {
+                Ok(thread) => match *thread {
+                    Some(ref thread) => thread.is_finished(),
+                    None => return Ok(()),
+                },
+                Err(_) => return Ok(()),
+            };
+            if finished {
+                break;
+            }
+            if Instant::now() >= deadline {
+                let error = format!(
+                    "Plugin '{}' did not stop within {} ms, it is unloaded when its current call returns",
+                    self.name,
+                    timeout.as_millis()
+                );
+                self.mark_failing(error.clone());
+                return Err(error);
+            }
+            std::thread::sleep(Duration::from_millis(10));
+        }
+
+        let thread = match self.shared.thread.lock().ok().and_then(|mut t| t.take()) {
+            Some(thread) => thread,
+            None => return Ok(()),
+        };
+        if thread.join().is_err() {
+            eprintln!("[Plugins] Plugin '{}' panicked while unloading", self.name);
+        }
+        Ok(())
+    }
+
+    /// Disable the plugin with an error without waiting for it
+    fn mark_failing(&self, error: String) {
+        let mut info = self.shared.info();
+        if !info.enabled {
+            return;
+        }
+        eprintln!("[Plugins] Plugin '{}' failed: {}, disabling", self.name, error);
+
+        // The plugin itself is updated when its worker gets control back
+        if let Ok(mut failure) = self.shared.failure.lock() {
+            *failure = Some(error.clone());
+        }
+        info.enabled = false;
+        info.last_error = Some(error);
+    }
+}
+
+/// Worker thread: run jobs until every handle is dropped
+fn run(
+    mut plugin: LoadedPlugin,
+    jobs: mpsc::Receiver<Job>,
+    shared: Arc<WorkerShared>,
+    sender: Option<HostRequestSender>,
+) {
+    loop {
+        // A plugin disabled by its failure policy is looked at again when its pause is over
+        let job = match plugin.reenable_at() {
+            Some(at) => match jobs.recv_timeout(at.saturating_duration_since(Instant::now())) {
+                Ok(job) => job,
+                Err(RecvTimeoutError::Timeout) => {
+                    let recovered = plugin.try_reenable();
+                    *shared.info() = plugin.info();
+                    if recovered {
+                        if let Some(ref sender) = sender {
+                            sender(HostRequest::PluginRecovered {
+                                plugin: plugin.name.clone(),
+                            });
+                        }
+                    }
+                    continue;
+                }
+                Err(RecvTimeoutError::Disconnected) => break,
+            },
+            None => match jobs.recv() {
+                Ok(job) => job,
+                Err(_) => break,
+            },
+        };
+        if shared.stopping.load(Ordering::Acquire) {
+            break;
+        }
+        let was_enabled = plugin.enabled;
+
+        if let Ok(mut busy_until) = shared.busy_until.lock() {
+            *busy_until = Some(Instant::now() + job.timeout);
+        }
+        (job.run)(&mut plugin);
+        if let Ok(mut busy_until) = shared.busy_until.lock() {
+            *busy_until = None;
+        }
+
+        // A failed notification nobody waits for used up the plugin's error budget
+        let failed = was_enabled && !plugin.enabled && plugin.last_error.is_some();
+
+        let failure = shared.failure.lock().ok().and_then(|mut f| f.take());
+        if let Some(error) = failure {
+            plugin.failures.record_error(&plugin.failure_policy, Instant::now());
+            plugin.disable_for_failure(error);
+        }
+
+        *shared.info() = plugin.info();
+
+        if failed {
+            if let Some(ref sender) = sender {
+                sender(HostRequest::PluginFailed {
+                    plugin: plugin.name.clone(),
+                    error: plugin.last_error.clone().unwrap_or_default(),
+                });
+            }
+        }
+    }
+    // `plugin` is dropped here: destroyed on the thread that made all its calls
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::plugins::backend::PluginBackend;
+    use crate::plugins::dynamic::{load_plugin, tests::example_plugin};
+    use plugins_api::{capabilities, PluginStatus};
+    use std::sync::atomic::AtomicUsize;
+
+    /// Plugin whose first text call takes `delay` and fails, later ones succeed
+    struct FlakyPlugin {
+        calls: u32,
+        delay: Duration,
+        status_checks: Arc<AtomicUsize>,
+    }
+
+    impl FlakyPlugin {
+        fn load(path: &Path, delay: Duration, status_checks: Arc<AtomicUsize>) -> LoadedPlugin {
+            let mut plugin = LoadedPlugin::new(
+                Box::new(FlakyPlugin { calls: 0, delay, status_checks }),
+                path.to_path_buf(),
+                "Flaky".to_string(),
+                "1.0.0".to_string(),
+                capabilities::TEXT | capabilities::STATUS,
+                serde_json::json!({}),
+            );
+            plugin.set_enabled(true);
+            plugin
+        }
+    }
+
+    impl PluginBackend for FlakyPlugin {
+        fn api_version(&self) -> String {
+            "3.6".to_string()
+        }
+
+        fn check_status(&self) -> PluginStatus {
+            self.status_checks.fetch_add(1, Ordering::SeqCst);
+            PluginStatus::Ok
+        }
+
+        fn plugin_error(&self) -> Option<String> {
+            None
+        }
+
+        fn status_detail(&self) -> Option<String> {
+            None
+        }
+
+        fn set_config(&mut self, _config: &serde_json::Value) -> Result<(), String> {
+            Ok(())
+        }
+
+        fn on_text(&mut self, _text: &str) -> Result<(), String> {
+            self.calls += 1;
+            if self.calls == 1 {
+                std::thread::sleep(self.delay);
+                Err("first call fails".to_string())
+            } else {
+                Ok(())
+            }
+        }
+    }
+
+    #[test]
+    fn test_retry_within_call_budget() {
+        let temp = tempfile::tempdir().unwrap();
+        let host = PluginHost::new(temp.path().to_path_buf());
+        let plugin = FlakyPlugin::load(&temp.path().join("flaky"), Duration::from_millis(30), Arc::new(AtomicUsize::new(0)));
+        let worker = PluginWorker::spawn_with_timeout(plugin, &host, Duration::from_millis(100)).unwrap();
+
+        // Fail, 100 ms pause, succeed: longer than one timeout, within the policy's budget
+        assert!(worker.post_with_policy(|p| {
+            let _ = p.call_with_policy(|p| p.on_text("hello"));
+        }));
+        std::thread::sleep(Duration::from_millis(130));
+        assert!(worker.post(|_| {}));
+
+        let (enabled, stats) = worker.call(|p| (p.enabled, p.info().failures)).unwrap();
+        assert!(enabled);
+        assert_eq!(stats.retries, 1);
+        assert_eq!(stats.total_errors, 0);
+    }
+
+    #[test]
+    fn test_jobs_do_not_check_status() {
+        let temp = tempfile::tempdir().unwrap();
+        let host = PluginHost::new(temp.path().to_path_buf());
+        let status_checks = Arc::new(AtomicUsize::new(0));
+        let plugin = FlakyPlugin::load(&temp.path().join("flaky"), Duration::ZERO, Arc::clone(&status_checks));
+        let worker = PluginWorker::spawn(plugin, &host).unwrap();
+        let checked_on_load = status_checks.load(Ordering::SeqCst);
+
+        for _ in 0..5 {
+            assert!(worker.post(|p| {
+                let _ = p.on_text("hello");
+            }));
+        }
+        worker.call(|p| p.set_routing(Default::default())).unwrap().unwrap();
+        assert_eq!(PluginStatus::from(worker.info().status), PluginStatus::Ok);
+        assert_eq!(status_checks.load(Ordering::SeqCst), checked_on_load);
+
+        assert_eq!(worker.call(|p| p.check_status()).unwrap(), PluginStatus::Ok);
+        assert_eq!(status_checks.load(Ordering::SeqCst), checked_on_load + 1);
+    }
+
+    #[test]
+    fn test_stop_can_be_retried() {
+        let temp = tempfile::tempdir().unwrap();
+        let host = PluginHost::new(temp.path().to_path_buf());
+        let plugin = FlakyPlugin::load(&temp.path().join("flaky"), Duration::from_millis(300), Arc::new(AtomicUsize::new(0)));
+        let worker = PluginWorker::spawn(plugin, &host).unwrap();
+        assert!(worker.post(|p| {
+            let _ = p.on_text("slow");
+        }));
+        std::thread::sleep(Duration::from_millis(50));
+
+        // Still in the call: the plugin stays loaded and is marked as failing
+        let err = worker.stop(Duration::from_millis(50)).unwrap_err();
+        assert!(err.contains("did not stop"));
+        let info = worker.info();
+        assert!(!info.enabled);
+        assert_eq!(info.last_error.as_deref(), Some(err.as_str()));
+
+        // Stopping again waits for the call to return
+        worker.stop(Duration::from_secs(5)).unwrap();
+        assert!(worker.shared.thread.lock().unwrap().is_none());
+    }
+
+    #[test]
+    fn test_call_timeout_marks_plugin_failing() {
+        let path = example_plugin().expect("example plugin was not built");
+        let temp = tempfile::tempdir().unwrap();
+        let host = PluginHost::new(temp.path().to_path_buf());
+
+        let mut plugin = load_plugin(&path, "file-logger", &host).unwrap();
+        plugin.set_config(&serde_json::json!({ "file_path": temp.path().join("log.txt") })).unwrap();
+        plugin.set_enabled(true);
+        let worker = PluginWorker::spawn_with_timeout(plugin, &host, Duration::from_millis(50)).unwrap();
+
+        assert_eq!(worker.call(|p| p.name.clone()).unwrap(), "File Logger");
+
+        // A stuck call doesn't block the caller
+        assert!(worker.post(|_| std::thread::sleep(Duration::from_millis(300))));
+        let started = Instant::now();
+        let err = worker.call(|p| p.name.clone()).unwrap_err();
+        assert!(started.elapsed() < Duration::from_millis(250));
+        assert!(err.contains("did not respond"));
+        assert!(!worker.is_enabled());
+
+        // While it is stuck, calls fail right away instead of piling up
+        assert!(!worker.post(|_| {}));
+
+        // Once the plugin is back, it is disabled with the same error
+        std::thread::sleep(Duration::from_millis(400));
+        let (enabled, last_error) = worker.call(|p| (p.enabled, p.last_error.clone())).unwrap();
+        assert!(!enabled);
+        assert_eq!(last_error, Some(err));
+    }
+}
@@ -113,10 +113,11 @@ pub enum AppStateEvent {
     PluginsChanged(Vec<crate::plugins::PluginInfo>),
     HotkeyModeChanged(HotkeyMode),
     ShowWindowRequested,
+    PluginRequest(crate::plugins::HostRequest),
 }
 
 // Re-export TTS types for use in other modules
-pub use crate::tts::{TtsEngine, TtsProvider, TtsStatus, Voice};
+pub use crate::tts::{TtsEngine, TtsProvider, TtsStatus, Voice, VoiceHandle};
 
 // Re-export audio settings types
 pub use crate::virtual_mic::AudioSettingsManager;
@@ -227,6 +228,10 @@ pub struct AppState {
     // === TTS state ===
     /// TTS engine for text-to-speech functionality
     pub tts_engine: Arc<Mutex<TtsEngine>>,
+    /// Selected TTS provider (shared with the engine, which may be busy speaking)
+    pub tts_provider: Arc<Mutex<TtsProvider>>,
+    /// Voice of the selected provider (shared with the engine like `tts_provider`)
+    pub tts_voice: VoiceHandle,
     /// Whether TTS is currently speaking
     pub tts_is_speaking: Arc<AtomicBool>,
     /// Whether continuous play mode is enabled
@@ -268,6 +273,7 @@ pub struct AppState {
 
 impl AppState {
     pub fn new() -> Self {
+        let tts_engine = TtsEngine::new();
         Self {
             blocking_enabled: Arc::new(AtomicBool::new(false)),
             win_pressed: Arc::new(AtomicBool::new(false)),
@@ -279,7 +285,9 @@ impl AppState {
             intercepted_keys: Arc::new(Mutex::new(VecDeque::with_capacity(MAX_KEYS))),
             active_window_keys: Arc::new(Mutex::new(VecDeque::with_capacity(MAX_KEYS))),
             // TTS state
-            tts_engine: Arc::new(Mutex::new(TtsEngine::new())),
+            tts_provider: tts_engine.provider_handle(),
+            tts_voice: tts_engine.voice_handle(),
+            tts_engine: Arc::new(Mutex::new(tts_engine)),
             tts_is_speaking: Arc::new(AtomicBool::new(false)),
             continuous_play: Arc::new(AtomicBool::new(false)),
             tts_history: Arc::new(Mutex::new(Vec::with_capacity(MAX_TTS_MESSAGES))),
@@ -670,7 +678,6 @@ impl AppState {
     }
 
     /// Get current playing message ID
-    #[allow(dead_code)]
     pub fn get_current_tts_message_id(&self) -> Option<String> {
         if let Ok(current_id) = self.tts_current_message_id.lock() {
             current_id.clone()
@@ -706,6 +713,91 @@ impl AppState {
         }
     }
 
+    /// Forward a playback or app state event to plugins
+    pub fn notify_plugins(&self, event: crate::plugins::PluginEvent) {
+        if let Ok(plugin_manager) = self.plugin_manager.lock() {
+            if let Some(ref manager) = *plugin_manager {
+                manager.dispatch_event(&event);
+            }
+        }
+    }
+
+    /// Hand a spoken message to plugins whose routing rules accept it
+    pub fn broadcast_text_to_plugins(&self, text: &str, source: crate::plugins::MessageSource, id: Option<String>) {
+        let provider = match self.tts_provider.lock() {
+            Ok(provider) => String::from(provider.clone()),
+            Err(poisoned) => String::from(poisoned.into_inner().clone()),
+        };
+        // Read through the shared handle: the engine itself is locked while it speaks
+        let voice = self.tts_voice.current_voice();
+        let message = crate::plugins::TextMessage {
+            text: text.to_string(),
+            source,
+            provider,
+            id,
+            voice,
+        };
+
+        let changed_plugins = match self.plugin_manager.lock() {
+            Ok(mut plugin_manager) => match plugin_manager.as_mut() {
+                Some(manager) => {
+                    if manager.broadcast_text(&message) {
+                        Some(manager.get_plugins())
+                    } else {
+                        None
+                    }
+                }
+                None => None,
+            },
+            Err(_) => None,
+        };
+
+        // Emit outside the plugin manager lock
+        if let Some(plugins) = changed_plugins {
+            self.emit_plugins_changed(plugins);
+        }
+    }
+
+    /// Hand synthesized audio of the current message to plugins
+    pub fn broadcast_audio_to_plugins(&self, audio_data: &[u8], format: crate::audio_format::AudioFormat) {
+        let message_id = self.get_current_tts_message_id().unwrap_or_default();
+
+        let changed_plugins = match self.plugin_manager.lock() {
+            Ok(mut plugin_manager) => match plugin_manager.as_mut() {
+                Some(manager) if manager.has_audio_listeners() => {
+                    let (sample_rate, channels) = match format {
+                        crate::audio_format::AudioFormat::Pcm { sample_rate, channels } => (sample_rate, channels),
+                        _ => (0, 0),
+                    };
+                    let duration_ms = crate::audio_format::duration(audio_data, format)
+                        .map(|d| d.as_millis() as u64)
+                        .unwrap_or(0);
+
+                    let clip = crate::plugins::AudioClip {
+                        data: audio_data.into(),
+                        format: format.name().to_string(),
+                        sample_rate,
+                        channels,
+                        duration_ms,
+                        message_id,
+                    };
+                    if manager.broadcast_audio(&clip) {
+                        Some(manager.get_plugins())
+                    } else {
+                        None
+                    }
+                }
+                _ => None,
+            },
+            Err(_) => None,
+        };
+
+        // Emit outside the plugin manager lock
+        if let Some(plugins) = changed_plugins {
+            self.emit_plugins_changed(plugins);
+        }
+    }
+
     /// Emit plugins changed event
     pub fn emit_plugins_changed(&self, plugins: Vec<crate::plugins::PluginInfo>) {
         if let Ok(sender) = self.event_sender.lock() {
@@ -1,40 +1,55 @@
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 use std::path::PathBuf;
 use std::sync::{Arc, Mutex};
 use std::result::Result as StdResult;
 use std::fs;
 
 // Re-export OpenAI types
-pub use crate::openai::{OpenAIClient, OpenAIConfig, OpenAIVoice};
+pub use crate::openai::{OpenAIClient, OpenAIConfig, OpenAIResponseFormat, OpenAIVoice};
 // Re-export Localhost types
 pub use crate::localhost::{LocalhostClient, LocalhostConfig, LocalhostVoice};
 // Import audio player for non-blocking Rodio playback
-use crate::audio_player::{AudioPlayer, OutputConfig};
+use crate::audio_player::{AudioEventCallback, AudioPlayer, OutputChannel, OutputConfig, VolumeControl};
+use crate::audio_format::AudioFormat;
+use crate::plugins::{PluginManager, PluginVoice, SharedPluginManager};
+
+/// Callback receiving synthesized audio right after the provider returns it
+pub type SynthesizedAudioCallback = Arc<dyn Fn(&[u8], AudioFormat) + Send + Sync>;
 
 /// TTS settings file
 #[derive(Debug, Clone, Serialize, Deserialize)]
 struct TtsSettingsFile {
     current_provider: TtsProvider,
+    /// Selected voice per plugin TTS provider
+    #[serde(default)]
+    plugin_voices: HashMap<String, String>,
 }
 
 impl Default for TtsSettingsFile {
     fn default() -> Self {
         Self {
             current_provider: TtsProvider::System,
+            plugin_voices: HashMap::new(),
         }
     }
 }
 
 /// TTS provider options
-#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
-#[serde(rename_all = "lowercase")]
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(from = "String", into = "String")]
 pub enum TtsProvider {
     System,
     OpenAI,
     Silero,
     Localhost,
+    /// TTS provider plugin, stored as "plugin:<id>" (older settings may hold the plugin name)
+    Plugin(String),
 }
 
+/// Prefix of plugin provider ids
+pub const PLUGIN_PROVIDER_PREFIX: &str = "plugin:";
+
 impl Default for TtsProvider {
     fn default() -> Self {
         Self::System
@@ -43,6 +58,9 @@ impl Default for TtsProvider {
 
 impl From<String> for TtsProvider {
     fn from(s: String) -> Self {
+        if let Some(name) = s.strip_prefix(PLUGIN_PROVIDER_PREFIX) {
+            return TtsProvider::Plugin(name.to_string());
+        }
         match s.to_lowercase().as_str() {
             "openai" => TtsProvider::OpenAI,
             "silero" => TtsProvider::Silero,
@@ -59,6 +77,7 @@ impl From<TtsProvider> for String {
             TtsProvider::OpenAI => "openai".to_string(),
             TtsProvider::Silero => "silero".to_string(),
             TtsProvider::Localhost => "localhost".to_string(),
+            TtsProvider::Plugin(name) => format!("{}{}", PLUGIN_PROVIDER_PREFIX, name),
         }
     }
 }
@@ -83,6 +102,22 @@ pub struct Voice {
     pub name: String,
 }
 
+/// Convert a plugin audio format code (`plugins_api::audio_format`) into [`AudioFormat`]
+fn plugin_audio_format(format: u32, sample_rate: u32, channels: u16) -> StdResult<AudioFormat, String> {
+    use plugins_api::audio_format;
+
+    match format {
+        audio_format::MP3 => Ok(AudioFormat::Mp3),
+        audio_format::OPUS => Ok(AudioFormat::Opus),
+        audio_format::AAC => Ok(AudioFormat::Aac),
+        audio_format::FLAC => Ok(AudioFormat::Flac),
+        audio_format::WAV => Ok(AudioFormat::Wav),
+        audio_format::OGG => Ok(AudioFormat::Ogg),
+        audio_format::PCM => Ok(AudioFormat::Pcm { sample_rate, channels }),
+        other => Err(format!("Unknown plugin audio format: {}", other)),
+    }
+}
+
 /// TTS Engine abstraction for supporting different providers
 pub struct TtsEngine {
     provider: Arc<Mutex<TtsProvider>>,
@@ -112,16 +147,57 @@ pub struct TtsEngine {
     // Speaker settings
     speaker_device_id: Arc<Mutex<Option<String>>>,
     speaker_enabled: Arc<Mutex<bool>>,
-    speaker_volume: Arc<Mutex<f32>>,
     // Virtual mic settings
     virtual_mic_device_id: Arc<Mutex<Option<String>>>,
-    virtual_mic_volume: Arc<Mutex<f32>>,
+    // Per-output delay offsets (ms)
+    speaker_delay_ms: Arc<Mutex<u32>>,
+    virtual_mic_delay_ms: Arc<Mutex<u32>>,
+    // Live volume levels (master, per output, mute), shared with playback threads
+    volume_control: VolumeControl,
+    // Receives synthesized audio before playback (plugins)
+    synthesized_audio_callback: Arc<Mutex<Option<SynthesizedAudioCallback>>>,
+    // Plugin TTS providers: shared plugin manager and selected voice per plugin
+    plugin_manager: Arc<Mutex<Option<SharedPluginManager>>>,
+    plugin_voices: Arc<Mutex<HashMap<String, String>>>,
+}
+
+/// Shared view of the selected provider's voice, readable while the engine is
+/// locked for speaking (see `TtsEngine::voice_handle`)
+#[derive(Clone)]
+pub struct VoiceHandle {
+    provider: Arc<Mutex<TtsProvider>>,
+    openai_client: Arc<Mutex<Option<OpenAIClient>>>,
+    localhost_client: Arc<Mutex<Option<LocalhostClient>>>,
+    silero_voice: Arc<Mutex<String>>,
+    plugin_voices: Arc<Mutex<HashMap<String, String>>>,
+}
+
+impl VoiceHandle {
+    /// Voice the selected provider speaks with (None if unknown or the default one)
+    pub fn current_voice(&self) -> Option<String> {
+        let provider = self.provider.lock().map(|p| p.clone()).unwrap_or_default();
+        let voice = match provider {
+            TtsProvider::System => return None,
+            TtsProvider::OpenAI => match self.openai_client.lock() {
+                Ok(client) => client.as_ref().map(|c| c.get_config().voice.clone()),
+                Err(_) => None,
+            },
+            TtsProvider::Silero => self.silero_voice.lock().ok().map(|voice| voice.clone()),
+            TtsProvider::Localhost => match self.localhost_client.lock() {
+                Ok(client) => client.as_ref().and_then(|c| c.get_config().voice.clone()),
+                Err(_) => None,
+            },
+            TtsProvider::Plugin(plugin) => self.plugin_voices.lock().ok().and_then(|voices| voices.get(&plugin).cloned()),
+        };
+        voice.filter(|v| !v.is_empty())
+    }
 }
 
 impl TtsEngine {
     pub fn new() -> Self {
         // Try to initialize SAPI on creation
         let (sapi_synthesizer, sapi_available) = Self::initialize_sapi();
+        let volume_control = VolumeControl::new();
 
         Self {
             provider: Arc::new(Mutex::new(TtsProvider::System)),
@@ -141,12 +217,16 @@ impl TtsEngine {
             silero_available: Arc::new(Mutex::new(false)),
             is_speaking: Arc::new(Mutex::new(false)),
             // Audio output settings
-            audio_player: Arc::new(Mutex::new(Some(AudioPlayer::new()))),
+            audio_player: Arc::new(Mutex::new(Some(AudioPlayer::with_volume_control(volume_control.clone())))),
             speaker_device_id: Arc::new(Mutex::new(None)),
             speaker_enabled: Arc::new(Mutex::new(true)),
-            speaker_volume: Arc::new(Mutex::new(1.0)),
             virtual_mic_device_id: Arc::new(Mutex::new(None)),
-            virtual_mic_volume: Arc::new(Mutex::new(1.0)),
+            speaker_delay_ms: Arc::new(Mutex::new(0)),
+            virtual_mic_delay_ms: Arc::new(Mutex::new(0)),
+            volume_control,
+            synthesized_audio_callback: Arc::new(Mutex::new(None)),
+            plugin_manager: Arc::new(Mutex::new(None)),
+            plugin_voices: Arc::new(Mutex::new(HashMap::new())),
         }
     }
 
@@ -167,9 +247,12 @@ impl TtsEngine {
                 if settings_path.exists() {
                     if let Ok(content) = fs::read_to_string(&settings_path) {
                         if let Ok(settings) = serde_json::from_str::<TtsSettingsFile>(&content) {
+                            println!("[TTS] Loaded saved provider: {:?}", settings.current_provider);
                             if let Ok(mut provider) = self.provider.lock() {
                                 *provider = settings.current_provider;
-                                println!("[TTS] Loaded saved provider: {:?}", settings.current_provider);
+                            }
+                            if let Ok(mut voices) = self.plugin_voices.lock() {
+                                *voices = settings.plugin_voices;
                             }
                         }
                     }
@@ -183,12 +266,16 @@ impl TtsEngine {
         if let Ok(dir_guard) = self.config_dir.lock() {
             if let Some(ref config_dir) = *dir_guard {
                 let current_provider = if let Ok(provider) = self.provider.lock() {
-                    *provider
+                    provider.clone()
                 } else {
                     TtsProvider::System
                 };
+                let plugin_voices = self.plugin_voices.lock()
+                    .map(|voices| voices.clone())
+                    .unwrap_or_default();
                 let settings = TtsSettingsFile {
                     current_provider,
+                    plugin_voices,
                 };
                 let settings_path = config_dir.join("tts_settings.json");
                 if let Ok(content) = serde_json::to_string_pretty(&settings) {
@@ -260,6 +347,22 @@ impl TtsEngine {
         Ok(())
     }
 
+    /// Shared handle to the selected provider, readable without locking the engine
+    pub fn provider_handle(&self) -> Arc<Mutex<TtsProvider>> {
+        Arc::clone(&self.provider)
+    }
+
+    /// Shared handle to the selected provider's voice, readable without locking the engine
+    pub fn voice_handle(&self) -> VoiceHandle {
+        VoiceHandle {
+            provider: Arc::clone(&self.provider),
+            openai_client: Arc::clone(&self.openai_client),
+            localhost_client: Arc::clone(&self.localhost_client),
+            silero_voice: Arc::clone(&self.silero_voice),
+            plugin_voices: Arc::clone(&self.plugin_voices),
+        }
+    }
+
     pub fn set_provider(&self, provider: TtsProvider) {
         if let Ok(mut p) = self.provider.lock() {
             *p = provider;
@@ -304,31 +407,74 @@ impl TtsEngine {
         }
     }
 
-    /// Set speaker enabled
+    /// Set speaker enabled (also mutes/unmutes speaker output that is already playing)
     pub fn set_speaker_enabled(&self, enabled: bool) {
         if let Ok(mut e) = self.speaker_enabled.lock() {
             *e = enabled;
         }
+        self.volume_control.set_muted(OutputChannel::Speaker, !enabled);
     }
 
-    /// Set speaker volume (0.0 - 1.0)
+    /// Set speaker volume (0.0 - 1.0), applied to current playback immediately
     pub fn set_speaker_volume(&self, volume: f32) {
-        if let Ok(mut vol) = self.speaker_volume.lock() {
-            *vol = volume.clamp(0.0, 1.0);
-        }
+        self.volume_control.set_volume(OutputChannel::Speaker, volume);
     }
 
-    /// Set virtual mic device (None = disabled)
+    /// Set virtual mic device (None = disabled, also mutes virtual mic output that is already playing)
     pub fn set_virtual_mic_device(&self, device_id: Option<String>) {
+        self.volume_control.set_muted(OutputChannel::VirtualMic, device_id.is_none());
         if let Ok(mut dev) = self.virtual_mic_device_id.lock() {
             *dev = device_id;
         }
     }
 
-    /// Set virtual mic volume (0.0 - 1.0)
+    /// Set virtual mic volume (0.0 - 1.0), applied to current playback immediately
     pub fn set_virtual_mic_volume(&self, volume: f32) {
-        if let Ok(mut vol) = self.virtual_mic_volume.lock() {
-            *vol = volume.clamp(0.0, 1.0);
+        self.volume_control.set_volume(OutputChannel::VirtualMic, volume);
+    }
+
+    /// Set speaker delay offset in milliseconds (applied from the next playback)
+    pub fn set_speaker_delay(&self, delay_ms: u32) {
+        if let Ok(mut delay) = self.speaker_delay_ms.lock() {
+            *delay = delay_ms;
+        }
+    }
+
+    /// Set virtual mic delay offset in milliseconds (applied from the next playback)
+    pub fn set_virtual_mic_delay(&self, delay_ms: u32) {
+        if let Ok(mut delay) = self.virtual_mic_delay_ms.lock() {
+            *delay = delay_ms;
+        }
+    }
+
+    /// Set master volume multiplier (0.0 - 1.0) for all outputs
+    pub fn set_master_volume(&self, volume: f32) {
+        self.volume_control.set_master(volume);
+    }
+
+    /// Set callback receiving output level meters and stream errors during playback
+    pub fn set_audio_event_callback(&self, callback: AudioEventCallback) {
+        if let Ok(mut guard) = self.audio_player.lock() {
+            if let Some(ref mut player) = *guard {
+                player.set_event_callback(callback);
+            }
+        }
+    }
+
+    /// Set callback receiving synthesized audio before it is played
+    pub fn set_synthesized_audio_callback(&self, callback: SynthesizedAudioCallback) {
+        if let Ok(mut guard) = self.synthesized_audio_callback.lock() {
+            *guard = Some(callback);
+        }
+    }
+
+    /// Pass synthesized audio to the registered callback
+    fn notify_synthesized(&self, audio_data: &[u8], format: AudioFormat) {
+        let callback = self.synthesized_audio_callback.lock()
+            .map(|c| c.clone())
+            .unwrap_or(None);
+        if let Some(callback) = callback {
+            callback(audio_data, format);
         }
     }
 
@@ -353,7 +499,7 @@ impl TtsEngine {
         }
 
         let result = match if let Ok(provider) = self.provider.lock() {
-            *provider
+            provider.clone()
         } else {
             TtsProvider::System
         } {
@@ -361,6 +507,7 @@ impl TtsEngine {
             TtsProvider::OpenAI => self.speak_openai(text),
             TtsProvider::Silero => self.speak_silero(text),
             TtsProvider::Localhost => self.speak_localhost(text),
+            TtsProvider::Plugin(name) => self.speak_plugin(&name, text),
         };
 
         if result.is_err() {
@@ -387,7 +534,7 @@ impl TtsEngine {
         }
 
         match if let Ok(provider) = self.provider.lock() {
-            *provider
+            provider.clone()
         } else {
             TtsProvider::System
         } {
@@ -395,6 +542,7 @@ impl TtsEngine {
             TtsProvider::OpenAI => self.stop_openai(),
             TtsProvider::Silero => self.stop_silero(),
             TtsProvider::Localhost => self.stop_localhost(),
+            TtsProvider::Plugin(_) => self.stop_plugin(),
         }
     }
 
@@ -466,25 +614,8 @@ impl TtsEngine {
     fn speak_openai(&self, text: &str) -> std::result::Result<(), String> {
         eprintln!("[TTS OpenAI] Starting speech for text: '{}'", text);
 
-        // Get audio output settings
-        let speaker_enabled = self.speaker_enabled.lock()
-            .map(|e| *e)
-            .unwrap_or(true);
-        let speaker_device_id = self.speaker_device_id.lock()
-            .map(|id| id.clone())
-            .unwrap_or(None);
-        let speaker_volume = self.speaker_volume.lock()
-            .map(|v| *v)
-            .unwrap_or(1.0);
-        let virtual_mic_device_id = self.virtual_mic_device_id.lock()
-            .map(|id| id.clone())
-            .unwrap_or(None);
-        let virtual_mic_volume = self.virtual_mic_volume.lock()
-            .map(|v| *v)
-            .unwrap_or(1.0);
-
         // Check if at least one output is enabled
-        if !speaker_enabled && virtual_mic_device_id.is_none() {
+        if !self.has_enabled_output() {
             if let Ok(mut speaking) = self.is_speaking.lock() {
                 *speaking = false;
             }
@@ -519,7 +650,7 @@ impl TtsEngine {
 
         eprintln!("[TTS OpenAI] Calling OpenAI API...");
         // Run async HTTP request in a separate thread with its own runtime
-        let audio_data = std::thread::spawn(move || {
+        let (audio_data, format) = std::thread::spawn(move || {
             let rt = tokio::runtime::Runtime::new()
                 .map_err(|e| format!("Failed to create runtime: {}", e))?;
 
@@ -531,60 +662,85 @@ impl TtsEngine {
         .join()
         .map_err(|e| format!("Thread panicked: {:?}", e))??;
 
-        eprintln!("[TTS OpenAI] Received {} bytes from API", audio_data.len());
+        eprintln!("[TTS OpenAI] Received {} bytes from API ({:?})", audio_data.len(), format);
 
         // Validate we got some data
         if audio_data.is_empty() {
             return Err("Received empty audio data from OpenAI API".to_string());
         }
 
-        eprintln!("[TTS OpenAI] Starting Rodio async playback (speaker={}, virtual_mic={:?})",
-            speaker_enabled, virtual_mic_device_id);
+        self.notify_synthesized(&audio_data, format);
+        self.start_playback("OpenAI", audio_data, format)?;
+
+        // Return immediately - playback continues in background
+        eprintln!("[TTS OpenAI] Returning immediately, playback continues in background");
+        Ok(())
+    }
+
+    /// True if the speaker or the virtual mic is enabled
+    fn has_enabled_output(&self) -> bool {
+        let speaker_enabled = self.speaker_enabled.lock()
+            .map(|e| *e)
+            .unwrap_or(true);
+        let virtual_mic_enabled = self.virtual_mic_device_id.lock()
+            .map(|id| id.is_some())
+            .unwrap_or(false);
+        speaker_enabled || virtual_mic_enabled
+    }
+
+    /// Start non-blocking playback of synthesized audio on the enabled outputs
+    fn start_playback(&self, label: &str, audio_data: Vec<u8>, format: AudioFormat) -> std::result::Result<(), String> {
+        // Get audio output settings
+        let speaker_enabled = self.speaker_enabled.lock()
+            .map(|e| *e)
+            .unwrap_or(true);
+        let speaker_device_id = self.speaker_device_id.lock()
+            .map(|id| id.clone())
+            .unwrap_or(None);
+        let virtual_mic_device_id = self.virtual_mic_device_id.lock()
+            .map(|id| id.clone())
+            .unwrap_or(None);
+
+        eprintln!("[TTS {}] Starting Rodio async playback (speaker={}, virtual_mic={:?})",
+            label, speaker_enabled, virtual_mic_device_id);
 
         // Clone Arc for the completion callback
         let is_speaking = Arc::clone(&self.is_speaking);
+        let label = label.to_string();
 
         // Get audio player and start non-blocking dual output playback
-        {
-            let mut player_guard = self.audio_player.lock()
-                .map_err(|e| format!("Failed to lock audio player: {}", e))?;
-
-            if let Some(ref mut player) = *player_guard {
-                // Build speaker config
-                let speaker_config = if speaker_enabled {
-                    Some(OutputConfig {
-                        device_id: speaker_device_id,
-                        volume: speaker_volume,
-                    })
-                } else {
-                    None
-                };
+        let mut player_guard = self.audio_player.lock()
+            .map_err(|e| format!("Failed to lock audio player: {}", e))?;
 
-                // Build virtual mic config
-                let virtual_mic_config = virtual_mic_device_id.map(|id| OutputConfig {
-                    device_id: Some(id),
-                    volume: virtual_mic_volume,
-                });
-
-                // Set completion callback to clear speaking flag when playback finishes
-                player.set_completion_callback(Box::new(move || {
-                    eprintln!("[TTS OpenAI] Playback completed, clearing is_speaking flag");
-                    if let Ok(mut speaking) = is_speaking.lock() {
-                        *speaking = false;
-                    }
-                }));
+        if let Some(ref mut player) = *player_guard {
+            let speaker_delay = self.speaker_delay_ms.lock().map(|d| *d).unwrap_or(0);
+            let virtual_mic_delay = self.virtual_mic_delay_ms.lock().map(|d| *d).unwrap_or(0);
 
-                // This is non-blocking - returns immediately
-                player.play_mp3_async_dual(audio_data, speaker_config, virtual_mic_config)
-                    .map_err(|e| format!("Failed to start playback: {}", e))?;
+            // Build speaker config
+            let speaker_config = if speaker_enabled {
+                Some(OutputConfig::speaker(speaker_device_id).with_delay(speaker_delay))
             } else {
-                return Err("Audio player not initialized".to_string());
-            }
-        }
+                None
+            };
 
-        // Return immediately - playback continues in background
-        eprintln!("[TTS OpenAI] Returning immediately, playback continues in background");
-        Ok(())
+            // Build virtual mic config
+            let virtual_mic_config = virtual_mic_device_id
+                .map(|id| OutputConfig::virtual_mic(Some(id)).with_delay(virtual_mic_delay));
+
+            // Set completion callback to clear speaking flag when playback finishes
+            player.set_completion_callback(Box::new(move || {
+                eprintln!("[TTS {}] Playback completed, clearing is_speaking flag", label);
+                if let Ok(mut speaking) = is_speaking.lock() {
+                    *speaking = false;
+                }
+            }));
+
+            // This is non-blocking - returns immediately
+            player.play_async_dual(audio_data, format, speaker_config, virtual_mic_config)
+                .map_err(|e| format!("Failed to start playback: {}", e))
+        } else {
+            Err("Audio player not initialized".to_string())
+        }
     }
 
     fn stop_openai(&self) -> std::result::Result<(), String> {
@@ -903,7 +1059,7 @@ impl TtsEngine {
 
     pub fn get_status(&self) -> TtsStatus {
         let provider = if let Ok(p) = self.provider.lock() {
-            *p
+            p.clone()
         } else {
             TtsProvider::System
         };
@@ -1012,6 +1168,17 @@ impl TtsEngine {
             })
     }
 
+    /// Set OpenAI response audio format
+    pub fn set_openai_response_format(&self, format: OpenAIResponseFormat) -> StdResult<(), String> {
+        self.openai_client.lock()
+            .map_err(|_| "Failed to lock".to_string())
+            .and_then(|mut client| {
+                client.as_mut()
+                    .ok_or_else(|| "Client not initialized".to_string())
+                    .map(|c| c.set_response_format(format))
+            })
+    }
+
     /// Set OpenAI proxy
     pub fn set_openai_proxy(&self, host: Option<String>, port: Option<u16>) -> StdResult<(), String> {
         self.openai_client.lock()
@@ -1049,25 +1216,8 @@ impl TtsEngine {
     fn speak_localhost(&self, text: &str) -> std::result::Result<(), String> {
         eprintln!("[TTS Localhost] Starting speech for text: '{}'", text);
 
-        // Get audio output settings
-        let speaker_enabled = self.speaker_enabled.lock()
-            .map(|e| *e)
-            .unwrap_or(true);
-        let speaker_device_id = self.speaker_device_id.lock()
-            .map(|id| id.clone())
-            .unwrap_or(None);
-        let speaker_volume = self.speaker_volume.lock()
-            .map(|v| *v)
-            .unwrap_or(1.0);
-        let virtual_mic_device_id = self.virtual_mic_device_id.lock()
-            .map(|id| id.clone())
-            .unwrap_or(None);
-        let virtual_mic_volume = self.virtual_mic_volume.lock()
-            .map(|v| *v)
-            .unwrap_or(1.0);
-
         // Check if at least one output is enabled
-        if !speaker_enabled && virtual_mic_device_id.is_none() {
+        if !self.has_enabled_output() {
             if let Ok(mut speaking) = self.is_speaking.lock() {
                 *speaking = false;
             }
@@ -1093,7 +1243,7 @@ impl TtsEngine {
 
         eprintln!("[TTS Localhost] Calling local server API...");
         // Run async HTTP request in a separate thread with its own runtime
-        let audio_data = std::thread::spawn(move || {
+        let (audio_data, format) = std::thread::spawn(move || {
             let rt = tokio::runtime::Runtime::new()
                 .map_err(|e| format!("Failed to create runtime: {}", e))?;
 
@@ -1105,64 +1255,107 @@ impl TtsEngine {
         .join()
         .map_err(|e| format!("Thread panicked: {:?}", e))??;
 
-        eprintln!("[TTS Localhost] Received {} bytes from API", audio_data.len());
+        eprintln!("[TTS Localhost] Received {} bytes from API ({:?})", audio_data.len(), format);
 
         // Validate we got some data
         if audio_data.is_empty() {
             return Err("Received empty audio data from local server".to_string());
         }
 
-        eprintln!("[TTS Localhost] Starting Rodio async playback (speaker={}, virtual_mic={:?})",
-            speaker_enabled, virtual_mic_device_id);
+        self.notify_synthesized(&audio_data, format);
+        self.start_playback("Localhost", audio_data, format)?;
 
-        // Clone Arc for the completion callback
-        let is_speaking = Arc::clone(&self.is_speaking);
-
-        // Get audio player and start non-blocking dual output playback
-        {
-            let mut player_guard = self.audio_player.lock()
-                .map_err(|e| format!("Failed to lock audio player: {}", e))?;
+        // Return immediately - playback continues in background
+        eprintln!("[TTS Localhost] Returning immediately, playback continues in background");
+        Ok(())
+    }
 
+    fn stop_localhost(&self) -> std::result::Result<(), String> {
+        eprintln!("[TTS Localhost] Stopping playback");
+        if let Ok(mut player_guard) = self.audio_player.lock() {
             if let Some(ref mut player) = *player_guard {
-                // Build speaker config
-                let speaker_config = if speaker_enabled {
-                    Some(OutputConfig {
-                        device_id: speaker_device_id,
-                        volume: speaker_volume,
-                    })
-                } else {
-                    None
-                };
+                player.stop();
+                player.clear_completion_callback();
+            }
+        }
+        Ok(())
+    }
 
-                // Build virtual mic config
-                let virtual_mic_config = virtual_mic_device_id.map(|id| OutputConfig {
-                    device_id: Some(id),
-                    volume: virtual_mic_volume,
-                });
-
-                // Set completion callback to clear speaking flag when playback finishes
-                player.set_completion_callback(Box::new(move || {
-                    eprintln!("[TTS Localhost] Playback completed, clearing is_speaking flag");
-                    if let Ok(mut speaking) = is_speaking.lock() {
-                        *speaking = false;
-                    }
-                }));
+    // === Plugin TTS providers ===
 
-                // This is non-blocking - returns immediately
-                player.play_mp3_async_dual(audio_data, speaker_config, virtual_mic_config)
-                    .map_err(|e| format!("Failed to start playback: {}", e))?;
-            } else {
-                return Err("Audio player not initialized".to_string());
+    /// Give the engine access to plugins acting as TTS providers
+    pub fn set_plugin_manager(&self, plugin_manager: SharedPluginManager) {
+        if let Ok(mut guard) = self.plugin_manager.lock() {
+            *guard = Some(plugin_manager);
+        }
+    }
+
+    /// Run a closure with the plugin manager
+    fn with_plugin_manager<T>(&self, f: impl FnOnce(&mut PluginManager) -> StdResult<T, String>) -> StdResult<T, String> {
+        let shared = self.plugin_manager.lock()
+            .map(|m| m.clone())
+            .unwrap_or(None)
+            .ok_or_else(|| "Plugin manager not initialized".to_string())?;
+        let mut guard = shared.lock()
+            .map_err(|e| format!("Failed to lock plugin manager: {}", e))?;
+        let manager = guard.as_mut()
+            .ok_or_else(|| "Plugin manager not initialized".to_string())?;
+        f(manager)
+    }
+
+    /// Voices of a plugin TTS provider
+    pub fn get_plugin_voices(&self, plugin: &str) -> StdResult<Vec<PluginVoice>, String> {
+        self.with_plugin_manager(|manager| manager.plugin_voices(plugin))
+    }
+
+    /// Selected voice of a plugin TTS provider (empty for the plugin's default)
+    pub fn get_plugin_voice(&self, plugin: &str) -> String {
+        self.plugin_voices.lock()
+            .map(|voices| voices.get(plugin).cloned().unwrap_or_default())
+            .unwrap_or_default()
+    }
+
+    /// Select a voice of a plugin TTS provider
+    pub fn set_plugin_voice(&self, plugin: &str, voice: String) {
+        if let Ok(mut voices) = self.plugin_voices.lock() {
+            voices.insert(plugin.to_string(), voice);
+        }
+        self.save_provider_settings();
+    }
+
+    fn speak_plugin(&self, plugin: &str, text: &str) -> std::result::Result<(), String> {
+        eprintln!("[TTS Plugin] Starting speech with '{}' for text: '{}'", plugin, text);
+
+        // Check if at least one output is enabled
+        if !self.has_enabled_output() {
+            if let Ok(mut speaking) = self.is_speaking.lock() {
+                *speaking = false;
             }
+            return Err("Both speaker and virtual mic are disabled. Please enable at least one output.".to_string());
         }
 
-        // Return immediately - playback continues in background
-        eprintln!("[TTS Localhost] Returning immediately, playback continues in background");
+        let voice = self.get_plugin_voice(plugin);
+        // Synthesize outside the plugin manager lock, it may take a while
+        let provider = self.with_plugin_manager(|manager| manager.tts_provider(plugin))?;
+        let audio = provider.synthesize(text, &voice)?;
+        let format = plugin_audio_format(audio.format, audio.sample_rate, audio.channels)?;
+
+        eprintln!("[TTS Plugin] Received {} bytes from '{}' ({:?})", audio.data.len(), plugin, format);
+
+        // Validate we got some data
+        if audio.data.is_empty() {
+            return Err(format!("Received empty audio data from plugin '{}'", plugin));
+        }
+
+        self.notify_synthesized(&audio.data, format);
+        self.start_playback("Plugin", audio.data, format)?;
+
+        eprintln!("[TTS Plugin] Returning immediately, playback continues in background");
         Ok(())
     }
 
-    fn stop_localhost(&self) -> std::result::Result<(), String> {
-        eprintln!("[TTS Localhost] Stopping playback");
+    fn stop_plugin(&self) -> std::result::Result<(), String> {
+        eprintln!("[TTS Plugin] Stopping playback");
         if let Ok(mut player_guard) = self.audio_player.lock() {
             if let Some(ref mut player) = *player_guard {
                 player.stop();
@@ -1290,9 +1483,13 @@ impl Clone for TtsEngine {
             audio_player: Arc::clone(&self.audio_player),
             speaker_device_id: Arc::clone(&self.speaker_device_id),
             speaker_enabled: Arc::clone(&self.speaker_enabled),
-            speaker_volume: Arc::clone(&self.speaker_volume),
             virtual_mic_device_id: Arc::clone(&self.virtual_mic_device_id),
-            virtual_mic_volume: Arc::clone(&self.virtual_mic_volume),
+            speaker_delay_ms: Arc::clone(&self.speaker_delay_ms),
+            virtual_mic_delay_ms: Arc::clone(&self.virtual_mic_delay_ms),
+            volume_control: self.volume_control.clone(),
+            synthesized_audio_callback: Arc::clone(&self.synthesized_audio_callback),
+            plugin_manager: Arc::clone(&self.plugin_manager),
+            plugin_voices: Arc::clone(&self.plugin_voices),
         }
     }
 }
@@ -9,6 +9,9 @@ use std::path::PathBuf;
 /// Audio output settings
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct AudioSettings {
+    /// Master volume applied on top of per-output volumes (0-100)
+    #[serde(default = "default_volume")]
+    pub master_volume: u8,
     /// Speaker device ID (None = default)
     #[serde(skip_serializing_if = "Option::is_none")]
     pub speaker_device: Option<String>,
@@ -27,11 +30,25 @@ pub struct AudioSettings {
     /// Last virtual mic device (for quick enable)
     #[serde(skip_serializing_if = "Option::is_none")]
     pub last_virtual_mic_device: Option<String>,
+    /// Speaker delay offset in milliseconds
+    #[serde(default)]
+    pub speaker_delay_ms: u32,
+    /// Virtual mic delay offset in milliseconds
+    #[serde(default)]
+    pub virtual_mic_delay_ms: u32,
+}
+
+/// Maximum per-output delay offset (ms)
+pub const MAX_OUTPUT_DELAY_MS: u32 = 2000;
+
+fn default_volume() -> u8 {
+    100
 }
 
 impl Default for AudioSettings {
     fn default() -> Self {
         Self {
+            master_volume: 100,
             speaker_device: None,
             speaker_enabled: true,
             speaker_volume: 100,
@@ -39,6 +56,8 @@ impl Default for AudioSettings {
             virtual_mic_device: None,
             virtual_mic_volume: 100,
             last_virtual_mic_device: None,
+            speaker_delay_ms: 0,
+            virtual_mic_delay_ms: 0,
         }
     }
 }
@@ -92,6 +111,26 @@ impl AudioSettingsManager {
         self.save()
     }
 
+    /// Set master volume and save
+    pub fn set_master_volume(&mut self, volume: u8) -> Result<(), String> {
+        self.update(|s| s.master_volume = volume.min(100))
+    }
+
+    /// Master volume as a 0.0 - 1.0 multiplier
+    pub fn master_volume_f32(&self) -> f32 {
+        self.settings.master_volume as f32 / 100.0
+    }
+
+    /// Speaker volume as 0.0 - 1.0
+    pub fn speaker_volume_f32(&self) -> f32 {
+        self.settings.speaker_volume as f32 / 100.0
+    }
+
+    /// Virtual mic volume as 0.0 - 1.0
+    pub fn virtual_mic_volume_f32(&self) -> f32 {
+        self.settings.virtual_mic_volume as f32 / 100.0
+    }
+
     /// Set speaker device and save
     pub fn set_speaker_device(&mut self, device: Option<String>) -> Result<(), String> {
         self.update(|s| {
@@ -140,6 +179,16 @@ impl AudioSettingsManager {
     pub fn set_virtual_mic_volume(&mut self, volume: u8) -> Result<(), String> {
         self.update(|s| s.virtual_mic_volume = volume.min(100))
     }
+
+    /// Set speaker delay offset and save
+    pub fn set_speaker_delay(&mut self, delay_ms: u32) -> Result<(), String> {
+        self.update(|s| s.speaker_delay_ms = delay_ms.min(MAX_OUTPUT_DELAY_MS))
+    }
+
+    /// Set virtual mic delay offset and save
+    pub fn set_virtual_mic_delay(&mut self, delay_ms: u32) -> Result<(), String> {
+        self.update(|s| s.virtual_mic_delay_ms = delay_ms.min(MAX_OUTPUT_DELAY_MS))
+    }
 }
 
 #[cfg(test)]
@@ -150,6 +199,7 @@ mod tests {
     #[test]
     fn test_audio_settings_default() {
         let settings = AudioSettings::default();
+        assert_eq!(settings.master_volume, 100);
         assert_eq!(settings.speaker_device, None);
         assert_eq!(settings.speaker_enabled, true);
         assert_eq!(settings.speaker_volume, 100);
@@ -181,5 +231,36 @@ mod tests {
 
         manager.set_virtual_mic_volume(25).unwrap();
         assert_eq!(manager.virtual_mic_volume_f32(), 0.25);
+
+        manager.set_master_volume(150).unwrap();
+        assert_eq!(manager.master_volume_f32(), 1.0);
+    }
+
+    #[test]
+    fn test_master_volume_missing_in_old_file() {
+        let temp_dir = TempDir::new().unwrap();
+        std::fs::write(
+            temp_dir.path().join("audio_settings.json"),
+            r#"{"speaker_enabled": true, "speaker_volume": 80, "virtual_mic_volume": 60}"#,
+        ).unwrap();
+
+        let manager = AudioSettingsManager::new(temp_dir.path().to_path_buf()).unwrap();
+        assert_eq!(manager.get().master_volume, 100);
+        assert_eq!(manager.get().speaker_volume, 80);
+        assert_eq!(manager.get().speaker_delay_ms, 0);
+        assert_eq!(manager.get().virtual_mic_delay_ms, 0);
+    }
+
+    #[test]
+    fn test_output_delay() {
+        let temp_dir = TempDir::new().unwrap();
+        let mut manager = AudioSettingsManager::new(temp_dir.path().to_path_buf()).unwrap();
+
+        manager.set_speaker_delay(150).unwrap();
+        manager.set_virtual_mic_delay(10_000).unwrap();
+
+        let manager2 = AudioSettingsManager::new(temp_dir.path().to_path_buf()).unwrap();
+        assert_eq!(manager2.get().speaker_delay_ms, 150);
+        assert_eq!(manager2.get().virtual_mic_delay_ms, MAX_OUTPUT_DELAY_MS);
     }
 }
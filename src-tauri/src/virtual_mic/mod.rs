@@ -6,5 +6,5 @@
 pub mod audio_settings;
 pub mod device;
 
-pub use audio_settings::{AudioSettings, AudioSettingsManager};
+pub use audio_settings::{AudioSettings, AudioSettingsManager, MAX_OUTPUT_DELAY_MS};
 pub use device::{find_all_output_devices, find_virtual_devices, OutputDeviceInfo, VirtualDeviceInfo};
@@ -67,7 +67,7 @@ export const useKeyboardStore = defineStore('keyboard', {
     // TTS state
     isPlaying: false as boolean,
     continuousPlay: false as boolean,
-    ttsProvider: 'system' as 'system' | 'openai',
+    ttsProvider: 'system' as string,
     // Input language state
     inputLanguage: 'en' as 'ru' | 'en',
     // TTS history state
@@ -502,7 +502,7 @@ export const useKeyboardStore = defineStore('keyboard', {
         const status = await invoke<TtsStatus>('get_tts_status');
         this.isPlaying = status.is_speaking;
         this.continuousPlay = status.continuous_play;
-        this.ttsProvider = status.provider as 'system' | 'openai';
+        this.ttsProvider = status.provider;
       } catch (error) {
         console.error('Failed to fetch TTS status:', error);
       }
@@ -645,7 +645,14 @@ export const useKeyboardStore = defineStore('keyboard', {
       // Listen for TTS provider changes
       listen<string>('tts_provider_changed', (event) => {
         const provider = event.payload;
-        this.ttsProvider = provider as 'system' | 'openai';
+        this.ttsProvider = provider;
+      });
+
+      // Listen for notifications raised by plugins
+      listen<{ plugin: string; title: string; body: string }>('plugins:notification', (event) => {
+        const { plugin, title, body } = event.payload;
+        const message = body ? `${plugin}: ${title} — ${body}` : `${plugin}: ${title}`;
+        this.showToast(message, 'info');
       });
 
       // Listen for TTS config changes